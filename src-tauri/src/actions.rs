@@ -0,0 +1,180 @@
+// Executes actions proposed by the flow analysis once someone approves them,
+// using the same PTY, launch and task-queue paths as the UI. Every attempt is
+// appended to `<data>/action-log.jsonl`.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use tauri::Manager;
+
+use crate::{FlowAction, FlowActionKind};
+
+const ACTION_LOG_FILE: &str = "action-log.jsonl";
+const ROLE_DIRS: [&str; 3] = ["stimulus", "will", "supply"];
+/// ESC interrupts the current turn in Claude Code / Codex without exiting.
+const INTERRUPT: &str = "\x1b";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionLogEntry {
+    pub timestamp_ms: u64,
+    /// Who approved it: "user", "api", ...
+    pub source: String,
+    pub action: FlowAction,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Where actions resolve cells and files.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionContext {
+    pub output_dir: Option<String>,
+    pub cols: Option<u32>,
+    pub tool_cmd: Option<String>,
+}
+
+fn check_cell(app: &tauri::AppHandle, cell_id: &str) -> Result<crate::CellState, String> {
+    app.state::<crate::CellStateMap>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(cell_id)
+        .cloned()
+        .ok_or_else(|| format!("unknown cell {}", cell_id))
+}
+
+/// Check that `rel` names a file inside one of the role dirs.
+fn check_new_file_path(rel: &str) -> Result<&Path, String> {
+    let rel_path = Path::new(rel);
+    if !rel_path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("invalid path: {}", rel));
+    }
+    let first = rel_path
+        .components()
+        .next()
+        .and_then(|c| c.as_os_str().to_str())
+        .unwrap_or("");
+    if !ROLE_DIRS.contains(&first) || rel_path.components().count() < 2 {
+        return Err(format!("path must be inside stimulus/, will/ or supply/: {}", rel));
+    }
+    Ok(rel_path)
+}
+
+/// Create `rel` under the output dir without following symlinks out of the
+/// workspace or replacing anything that is already there.
+fn create_new_file(output_dir: &str, rel: &str, content: &str) -> Result<PathBuf, String> {
+    use std::io::Write;
+
+    let rel_path = check_new_file_path(rel)?;
+    let root = crate::sandbox::check(output_dir)?;
+    let (Some(dir), Some(name)) = (rel_path.parent(), rel_path.file_name()) else {
+        return Err(format!("invalid path: {}", rel));
+    };
+    std::fs::create_dir_all(root.join(dir)).map_err(|e| e.to_string())?;
+    // Re-resolve after creating: a role dir that is a symlink must still lead into a root.
+    let dir = crate::sandbox::check(&root.join(dir).to_string_lossy())?;
+    let full = dir.join(name);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&full)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => format!("{} already exists", rel),
+            _ => format!("{}: {}", full.display(), e),
+        })?;
+    file.write_all(content.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(full)
+}
+
+async fn execute(app: &tauri::AppHandle, action: &FlowAction, ctx: &ActionContext) -> Result<String, String> {
+    let cols = ctx.cols.unwrap_or(3) as usize;
+    match &action.kind {
+        FlowActionKind::SendPrompt { cell_id, prompt } => {
+            check_cell(app, cell_id)?;
+            crate::commands::pty::paste_to_cell(&app.state(), cell_id, prompt)?;
+            Ok(format!("sent prompt to {}", cell_id))
+        }
+        FlowActionKind::EnqueueTask { cell_id, task } => {
+            check_cell(app, cell_id)?;
+            crate::pty_manager::emit_task(app, cell_id, task);
+            Ok(format!("queued task for {}", cell_id))
+        }
+        FlowActionKind::PauseCell { cell_id } => {
+            check_cell(app, cell_id)?;
+            let has_pty = app.state::<crate::PtySessions>().0.lock().map_err(|e| e.to_string())?.contains_key(cell_id);
+            if !has_pty {
+                return Err(format!("{} has no running session", cell_id));
+            }
+            crate::commands::pty::write_to_cell(&app.state(), cell_id, INTERRUPT)?;
+            Ok(format!("interrupted {}", cell_id))
+        }
+        FlowActionKind::LaunchCell { cell_id } => {
+            let cell = check_cell(app, cell_id)?;
+            let work_dir = ctx.output_dir.as_deref().map(|dir| crate::ai::cell_work_dir(&cell, dir, cols)).transpose()?;
+            crate::commands::launch::launch_cell(
+                app.clone(),
+                app.state(),
+                app.state(),
+                cell_id.clone(),
+                work_dir.clone(),
+                ctx.tool_cmd.clone(),
+            )
+            .await?;
+            Ok(match work_dir {
+                Some(dir) => format!("launched {} in {}", cell_id, dir),
+                None => format!("launched {}", cell_id),
+            })
+        }
+        FlowActionKind::CreateFile { path, content } => {
+            let output_dir = ctx.output_dir.as_deref().ok_or("output dir not provided")?;
+            let full = create_new_file(output_dir, path, content)?;
+            Ok(format!("created {}", full.display()))
+        }
+    }
+}
+
+/// Execute an approved action and record the outcome in the audit log.
+pub(crate) async fn apply(
+    app: &tauri::AppHandle,
+    action: FlowAction,
+    ctx: &ActionContext,
+    source: &str,
+) -> Result<ActionLogEntry, String> {
+    let result = execute(app, &action, ctx).await;
+    let entry = ActionLogEntry {
+        timestamp_ms: crate::now_millis(),
+        source: source.to_string(),
+        action,
+        ok: result.is_ok(),
+        detail: match &result {
+            Ok(detail) | Err(detail) => detail.clone(),
+        },
+    };
+    if let Err(e) = crate::storage::append_jsonl(app, ACTION_LOG_FILE, &entry) {
+        eprintln!("action log: {}", e);
+    }
+    result.map(|_| entry)
+}
+
+/// Most recent log entries first.
+pub(crate) fn load_log(app: &tauri::AppHandle, limit: usize) -> Vec<ActionLogEntry> {
+    let mut entries: Vec<ActionLogEntry> = crate::storage::load_jsonl(app, ACTION_LOG_FILE);
+    entries.reverse();
+    entries.truncate(limit);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_files_must_be_plain_paths_under_a_role_dir() {
+        assert!(check_new_file_path("will/plan.md").is_ok());
+        assert!(check_new_file_path("supply/notes/a.txt").is_ok());
+        assert!(check_new_file_path("will").is_err());
+        assert!(check_new_file_path("other/plan.md").is_err());
+        assert!(check_new_file_path("will/../../etc/passwd").is_err());
+        assert!(check_new_file_path("/will/plan.md").is_err());
+    }
+}
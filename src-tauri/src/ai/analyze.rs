@@ -1,11 +1,21 @@
 use crate::storage::{AiConfig, AnalysisEntry};
-use crate::{AnalyzeResult, CellState, FlowConnection};
+use crate::{AnalyzeResult, CellState, FlowAction, FlowAnalysis, FlowConnection};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 
-use super::call_ai;
+use super::context::{self, estimate_tokens, CellContext, Context};
+use super::templates::{self, Rendered, Template};
+use super::schema::validate;
 use super::utils::extract_json_object;
+use super::{call_ai_reply, call_ai_structured, AiTask, JsonOutput};
 
-fn get_cell_role(cell_id: &str, cols: usize) -> &'static str {
+const MAX_OUTPUT_TOKENS: u32 = 1200;
+/// Screen lines used as the retrieval query for a cell's work files.
+const RETRIEVAL_QUERY_LINES: usize = 30;
+const RETRIEVAL_HITS_PER_CELL: usize = 2;
+const RETRIEVAL_CHUNK_CHARS: usize = 600;
+
+pub(crate) fn get_cell_role(cell_id: &str, cols: usize) -> &'static str {
     let index: usize = cell_id
         .strip_prefix("cell-")
         .and_then(|s| s.parse().ok())
@@ -22,15 +32,7 @@ fn get_cell_role(cell_id: &str, cols: usize) -> &'static str {
 }
 
 fn format_history(history: &[AnalysisEntry]) -> String {
-    if history.is_empty() {
-        return String::new();
-    }
-    let last_5 = if history.len() > 5 {
-        &history[history.len() - 5..]
-    } else {
-        history
-    };
-    last_5
+    history
         .iter()
         .map(|entry| {
             let summaries: String = entry
@@ -42,146 +44,140 @@ fn format_history(history: &[AnalysisEntry]) -> String {
                 })
                 .collect::<Vec<_>>()
                 .join("\n");
-            format!("{}\n{}", entry.timestamp, summaries)
+            format!("{}\n{}", crate::time::format_local(entry.timestamp_ms), summaries)
         })
         .collect::<Vec<_>>()
         .join("\n\n")
 }
 
-fn format_cells(cells: &[&CellState]) -> String {
+fn format_cells(cells: &[&CellContext]) -> String {
+    if cells.is_empty() {
+        return "(no active cells)".to_string();
+    }
     cells
         .iter()
         .map(|c| {
-            let output = if c.last_output.len() > 600 {
-                let mut start = c.last_output.len() - 600;
-                while !c.last_output.is_char_boundary(start) {
-                    start += 1;
-                }
-                &c.last_output[start..]
-            } else {
-                &c.last_output
-            };
-            format!("  [{}]\n{}", c.theme, output)
+            let text = if c.text.is_empty() { "(output omitted to fit the context budget)" } else { &c.text };
+            format!("  [{}] {}\n{}", c.cell.id, c.cell.theme, text)
         })
         .collect::<Vec<_>>()
         .join("\n---\n")
 }
 
-fn cell_work_dir(cell: &CellState, output_dir: &str, cols: usize) -> String {
+/// A theme names a folder under the cell's role dir, so it may not leave it.
+pub(crate) fn check_theme(theme: &str) -> Result<(), String> {
+    if theme.contains('/') || theme.contains("..") || theme.chars().any(char::is_control) {
+        return Err(format!("{:?}: a theme may not contain '/', '..' or control characters", theme));
+    }
+    Ok(())
+}
+
+pub(crate) fn cell_work_dir(cell: &CellState, output_dir: &str, cols: usize) -> Result<String, String> {
     let role = get_cell_role(&cell.id, cols).to_lowercase();
     let base = output_dir.trim_end_matches('/');
     if cell.theme.is_empty() {
-        format!("{}/{}", base, role)
+        Ok(format!("{}/{}", base, role))
     } else {
-        format!("{}/{}/{}", base, role, cell.theme)
+        check_theme(&cell.theme)?;
+        Ok(format!("{}/{}/{}", base, role, cell.theme))
     }
 }
 
 fn git_diff_stat(dir: &str) -> String {
     let expanded = crate::files::expand_tilde(dir);
-    let path = std::path::Path::new(&expanded);
-    if !path.exists() {
+    if !std::path::Path::new(&expanded).exists() {
         return "(directory does not exist)".to_string();
     }
-    match std::process::Command::new("git")
-        .args(["-C", &expanded, "diff", "--stat"])
-        .output()
-    {
-        Ok(output) if output.status.success() => {
-            let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if text.is_empty() {
-                let status = std::process::Command::new("git")
-                    .args(["-C", &expanded, "status", "--short"])
-                    .output()
-                    .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
-                    .unwrap_or_default();
-                if status.is_empty() {
-                    "(no changes)".to_string()
-                } else {
-                    status
-                }
-            } else {
-                text
+    let Ok(path) = crate::sandbox::check(dir) else {
+        return "(outside the workspace roots)".to_string();
+    };
+    let Some(repo) = crate::git::Repo::in_workspace(&path) else {
+        return "(not a git repo)".to_string();
+    };
+    let stat = match repo.uncommitted_stat_text() {
+        Ok(stat) => stat,
+        Err(e) => return format!("(git error: {})", e),
+    };
+    if !stat.is_empty() {
+        return stat;
+    }
+    // Only untracked files, which a diff does not show.
+    let status: Vec<String> = repo.status().unwrap_or_default().iter().map(|e| e.short()).collect();
+    if status.is_empty() {
+        "(no changes)".to_string()
+    } else {
+        status.join("\n")
+    }
+}
+
+/// Per themed cell, the chunks of its work dir that best match what its screen
+/// currently shows (see `retrieval`).
+fn relevant_files(cells: &[&CellState], output_dir: &str, cols: usize) -> String {
+    let sections: Vec<String> = cells
+        .iter()
+        .filter(|c| !c.theme.is_empty())
+        .filter_map(|c| {
+            let screen = context::clean_screen(&c.last_output);
+            let recent: Vec<&str> = screen.lines().rev().take(RETRIEVAL_QUERY_LINES).collect();
+            let query = format!("{} {}", c.theme, recent.join(" "));
+            let dir = crate::sandbox::check(&cell_work_dir(c, output_dir, cols).ok()?).ok()?;
+            let hits = crate::retrieval::search(&dir, &query, RETRIEVAL_HITS_PER_CELL);
+            if hits.is_empty() {
+                return None;
             }
-        }
-        _ => "(not a git repo)".to_string(),
+            Some(format!("  [{}]\n{}", c.theme, crate::retrieval::format_hits(&hits, RETRIEVAL_CHUNK_CHARS)))
+        })
+        .collect();
+    if sections.is_empty() {
+        "(no matching files)".to_string()
+    } else {
+        sections.join("\n")
     }
 }
 
 fn time_since_last(history: &[AnalysisEntry]) -> String {
-    if history.is_empty() {
-        return "first analysis".to_string();
-    }
-    let last = &history[history.len() - 1];
-    let now = crate::now_millis() / 1000;
-    let parts: Vec<&str> = last.timestamp.split('T').collect();
-    if parts.len() != 2 {
-        return "unknown".to_string();
-    }
-    let date_parts: Vec<u64> = parts[0].split('-').filter_map(|s| s.parse().ok()).collect();
-    let time_str = parts[1].trim_end_matches('Z').trim_end_matches(".000");
-    let time_parts: Vec<u64> = time_str.split(':').filter_map(|s| s.parse().ok()).collect();
-    if date_parts.len() != 3 || time_parts.len() != 3 {
-        return "unknown".to_string();
-    }
-    let days_approx = (date_parts[0] - 1970) * 365 + (date_parts[0] - 1970) / 4
-        + match date_parts[1] {
-            1 => 0, 2 => 31, 3 => 59, 4 => 90, 5 => 120, 6 => 151,
-            7 => 181, 8 => 212, 9 => 243, 10 => 273, 11 => 304, 12 => 334,
-            _ => 0,
-        } + date_parts[2] - 1;
-    let last_epoch = days_approx * 86400 + time_parts[0] * 3600 + time_parts[1] * 60 + time_parts[2];
-    let delta = now.saturating_sub(last_epoch);
-    if delta < 60 {
-        format!("{}s ago", delta)
-    } else if delta < 3600 {
-        format!("{}m ago", delta / 60)
-    } else if delta < 86400 {
-        format!("{}h ago", delta / 3600)
-    } else {
-        format!("{}d ago", delta / 86400)
+    match history.last() {
+        None => "first analysis".to_string(),
+        Some(last) if last.timestamp_ms == 0 => "unknown".to_string(),
+        Some(last) => crate::time::format_relative(last.timestamp_ms, crate::now_millis()),
     }
 }
 
-pub(crate) async fn analyze_cells(
+/// A rendered analysis prompt with the config and context budget it was built for.
+pub(crate) struct AnalysisPrompt<'a> {
+    pub config: AiConfig,
+    pub max_output: u32,
+    pub prompt: Rendered,
+    pub ctx: Context<'a>,
+}
+
+/// Build the analysis prompt from the `analysis` template; None when no cell has output.
+pub(crate) fn analysis_prompt<'a>(
     config: &AiConfig,
-    cells: &[CellState],
-    history: &[AnalysisEntry],
+    cells: &'a [CellState],
+    history: &'a [AnalysisEntry],
     language: &str,
     cols: usize,
     output_dir: Option<&str>,
-) -> Result<AnalyzeResult, String> {
-    let stimuli: Vec<&CellState> = cells
-        .iter()
-        .filter(|c| get_cell_role(&c.id, cols) == "Stimulus" && !c.last_output.is_empty())
-        .collect();
-    let will: Vec<&CellState> = cells
-        .iter()
-        .filter(|c| get_cell_role(&c.id, cols) == "Will" && !c.last_output.is_empty())
-        .collect();
-    let supply: Vec<&CellState> = cells
-        .iter()
-        .filter(|c| get_cell_role(&c.id, cols) == "Supply" && !c.last_output.is_empty())
-        .collect();
-
-    if stimuli.is_empty() && will.is_empty() && supply.is_empty() {
-        return Ok(AnalyzeResult { summaries: HashMap::new(), ideas: Vec::new(), flow: None });
+) -> Option<AnalysisPrompt<'a>> {
+    let mut active: Vec<&CellState> = cells.iter().filter(|c| !c.last_output.is_empty()).collect();
+    if active.is_empty() {
+        return None;
     }
+    active.sort_by_key(|c| c.id.strip_prefix("cell-").and_then(|n| n.parse::<usize>().ok()).unwrap_or(0));
 
     let diff_text = if let Some(out_dir) = output_dir {
-        let active_cells: Vec<&CellState> = cells
-            .iter()
-            .filter(|c| !c.last_output.is_empty() && !c.theme.is_empty())
-            .collect();
-        if active_cells.is_empty() {
+        let themed: Vec<&&CellState> = active.iter().filter(|c| !c.theme.is_empty()).collect();
+        if themed.is_empty() {
             "(no cells with themes to diff)".to_string()
         } else {
-            active_cells
+            themed
                 .iter()
                 .map(|c| {
-                    let wdir = cell_work_dir(c, out_dir, cols);
-                    let diff = git_diff_stat(&wdir);
-                    format!("  [{}] {}\n{}", c.theme, wdir, diff)
+                    match cell_work_dir(c, out_dir, cols) {
+                        Ok(wdir) => format!("  [{}] {}\n{}", c.theme, wdir, git_diff_stat(&wdir)),
+                        Err(e) => format!("  [{}]\n({})", c.theme, e),
+                    }
                 })
                 .collect::<Vec<_>>()
                 .join("\n")
@@ -190,125 +186,334 @@ pub(crate) async fn analyze_cells(
         "(output_dir not provided)".to_string()
     };
 
+    let files_text = match output_dir {
+        Some(out_dir) => relevant_files(&active, out_dir, cols),
+        None => "(output_dir not provided)".to_string(),
+    };
     let time_since = time_since_last(history);
 
+    let (analysis_config, max_output) = AiTask::Analyze.apply(config, MAX_OUTPUT_TOKENS);
+    let skeleton = render_prompt("", ["", "", ""], &time_since, &diff_text, &files_text, language);
+    let overhead = estimate_tokens(&analysis_config, &skeleton.text);
+    let ctx = context::build(&analysis_config, &active, history, overhead, max_output as usize);
+    let by_role = |role: &str| -> Vec<&CellContext> {
+        ctx.cells.iter().filter(|c| get_cell_role(&c.cell.id, cols) == role).collect()
+    };
+
     let history_block = {
-        let s = format_history(history);
+        let s = format_history(ctx.history);
         if s.is_empty() { String::new() } else { format!("## Past Session History\n{}\n", s) }
     };
-    let stimuli_text = if !stimuli.is_empty() { format_cells(&stimuli) } else { "(no active cells)".to_string() };
-    let will_text = if !will.is_empty() { format_cells(&will) } else { "(no active cells)".to_string() };
-    let supply_text = if !supply.is_empty() { format_cells(&supply) } else { "(no active cells)".to_string() };
-
-    let prompt = format!(
-        r#"You are "Command", an AI that analyzes the flow of knowledge work.
-
-Knowledge work flows through 3 layers:
-- Stimulus (receiving from outside) → Will (converting to personal intent) → Supply (creating and shipping)
-
-The user runs multiple AI agents in parallel, each working on a different aspect.
-Your job is to find CONNECTIONS between cells and help the human decide what to do next.
-
-{}
-## Current Session
-
-### Stimulus Layer
-{}
-
-### Will Layer
-{}
-
-### Supply Layer
-{}
-
-### Changes since last analysis ({})
-{}
-
-Respond in: {}
-
-## Instructions
-1. For each cell, summarize what is happening (1 sentence)
-2. Find CONNECTIONS: what insight or output from one cell can feed into another cell? Be specific.
-3. Identify what CHANGED since last analysis and whether it moved the flow forward
-4. Ask the human 1-2 SPECIFIC QUESTIONS about decisions only they can make
-5. Identify blockages and the single most important next action
-
-## Output format (JSON only, no markdown)
-{{
-  "summaries": {{ "<cellId>": "..." }},
-  "ideas": ["2-3 concrete cross-cell insights"],
-  "flow": {{
-    "stimuli_to_will": "...",
-    "will_to_supply": "...",
-    "stuck": "...",
-    "next": "...",
-    "blocked_cells": [],
-    "priority_cell": null,
-    "confidence": "high|medium|low",
-    "connections": [
-      {{ "from_cell": "theme-A", "to_cell": "theme-B", "insight": "..." }}
-    ],
-    "human_questions": ["specific question for human decision"],
-    "changes_since_last": "summary of what progressed since last analysis"
-  }}
-}}"#,
-        history_block, stimuli_text, will_text, supply_text, time_since, diff_text, language
+    let prompt = render_prompt(
+        &history_block,
+        [
+            &format_cells(&by_role("Stimulus")),
+            &format_cells(&by_role("Will")),
+            &format_cells(&by_role("Supply")),
+        ],
+        &time_since,
+        &diff_text,
+        &files_text,
+        language,
     );
+    Some(AnalysisPrompt { config: analysis_config, max_output, prompt, ctx })
+}
 
-    let mut analysis_config = config.clone();
-    if analysis_config.model.is_none() && analysis_config.provider == "gemini" {
-        analysis_config.model = Some("gemini-2.5-flash".to_string());
+pub(crate) async fn analyze_cells(
+    config: &AiConfig,
+    cells: &[CellState],
+    history: &[AnalysisEntry],
+    language: &str,
+    cols: usize,
+    output_dir: Option<&str>,
+) -> Result<AnalyzeResult, String> {
+    let Some(AnalysisPrompt { config: analysis_config, max_output, prompt, ctx }) =
+        analysis_prompt(config, cells, history, language, cols, output_dir)
+    else {
+        return Ok(AnalyzeResult {
+            summaries: HashMap::new(),
+            ideas: Vec::new(),
+            flow: None,
+            analysis_id: None,
+            diagnostics: Vec::new(),
+            context: None,
+            answered_by: None,
+        });
+    };
+
+    let schema = analysis_schema();
+    let json_mode = JsonOutput { name: "report_analysis", schema: &schema };
+    let mut messages = vec![("user".to_string(), prompt.text)];
+    let reply = call_ai_reply(AiTask::Analyze, &analysis_config, None, &messages, max_output, Some(&json_mode)).await?;
+    let text = reply.text.clone();
+    let (mut parsed, mut problems) = parse_checked(&text, &schema);
+
+    let mut diagnostics: Vec<String> = prompt.warnings;
+    diagnostics.extend(reply.fallback_errors.iter().map(|e| format!("provider failed, fell back: {}", e)));
+    if !problems.is_empty() {
+        // Ask the provider that answered, not the head of the chain.
+        let mut repair_config = analysis_config.clone();
+        repair_config.provider = reply.provider.clone();
+        repair_config.model = Some(reply.model.clone());
+        repair_config.fallbacks.clear();
+
+        // One repair round-trip: show the model what was wrong with its reply.
+        diagnostics.push(format!("invalid response: {}", problems.join("; ")));
+        messages.push(("assistant".to_string(), text.clone()));
+        messages.push(("user".to_string(), repair_request(&problems)));
+        match call_ai_structured(AiTask::Analyze, &repair_config, None, &messages, max_output, Some(&json_mode)).await {
+            Ok(repaired) => {
+                let (value, errors) = parse_checked(&repaired, &schema);
+                if prefer_repair(parsed.is_some(), &problems, value.is_some(), &errors) {
+                    parsed = value;
+                    problems = errors;
+                }
+                if problems.is_empty() {
+                    diagnostics.push("repaired after one retry".to_string());
+                }
+            }
+            Err(e) => diagnostics.push(format!("repair request failed: {}", e)),
+        }
+        if !problems.is_empty() {
+            diagnostics.push(format!("partial result: {}", problems.join("; ")));
+        }
     }
 
-    let text = call_ai(&analysis_config, &prompt, 1200).await?;
-    match extract_json_object(&text) {
-        Some(json_str) => {
-            let parsed: serde_json::Value =
-                serde_json::from_str(&json_str).map_err(|e| e.to_string())?;
-
-            let summaries: HashMap<String, String> = parsed
-                .get("summaries")
-                .and_then(|v| serde_json::from_value(v.clone()).ok())
-                .unwrap_or_default();
-            let ideas: Vec<String> = parsed
-                .get("ideas")
-                .and_then(|v| serde_json::from_value(v.clone()).ok())
-                .unwrap_or_default();
-            let flow = parsed.get("flow").and_then(|v| {
-                let blocked_cells: Option<Vec<String>> = v.get("blocked_cells")
-                    .and_then(|bc| serde_json::from_value(bc.clone()).ok())
-                    .map(|cells: Vec<String>| cells.into_iter().filter(|s| !s.is_empty()).collect());
-                let priority_cell: Option<String> = v.get("priority_cell")
-                    .and_then(|pc| pc.as_str())
-                    .filter(|s| !s.is_empty() && *s != "null")
-                    .map(|s| s.to_string());
-                let confidence: Option<String> = v.get("confidence")
-                    .and_then(|c| c.as_str())
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_string());
-                let connections: Option<Vec<FlowConnection>> = v.get("connections")
-                    .and_then(|c| serde_json::from_value(c.clone()).ok());
-                let human_questions: Option<Vec<String>> = v.get("human_questions")
-                    .and_then(|q| serde_json::from_value(q.clone()).ok());
-                let changes_since_last: Option<String> = v.get("changes_since_last")
-                    .and_then(|c| c.as_str())
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_string());
-                Some(crate::FlowAnalysis {
-                    stimuli_to_will: v.get("stimuli_to_will")?.as_str()?.to_string(),
-                    will_to_supply: v.get("will_to_supply")?.as_str()?.to_string(),
-                    stuck: v.get("stuck")?.as_str()?.to_string(),
-                    next: v.get("next")?.as_str()?.to_string(),
-                    blocked_cells,
-                    priority_cell,
-                    confidence,
-                    connections,
-                    human_questions,
-                    changes_since_last,
-                })
-            });
-            Ok(AnalyzeResult { summaries, ideas, flow })
+    let Some(value) = parsed else {
+        diagnostics.push(format!("no JSON in response: {}", snippet(&text)));
+        return Ok(AnalyzeResult {
+            summaries: HashMap::new(),
+            ideas: Vec::new(),
+            flow: None,
+            analysis_id: None,
+            diagnostics,
+            context: Some(ctx.report),
+            answered_by: Some(reply.answered_by()),
+        });
+    };
+    Ok(AnalyzeResult {
+        summaries: lenient_summaries(&value),
+        ideas: string_list(value.get("ideas")).unwrap_or_default(),
+        flow: value.get("flow").and_then(lenient_flow),
+        analysis_id: None,
+        diagnostics,
+        context: Some(ctx.report),
+        answered_by: Some(reply.answered_by()),
+    })
+}
+
+fn render_prompt(
+    history_block: &str,
+    [stimuli_text, will_text, supply_text]: [&str; 3],
+    time_since: &str,
+    diff_text: &str,
+    files_text: &str,
+    language: &str,
+) -> Rendered {
+    templates::render(
+        Template::Analysis,
+        &[
+            ("history", history_block),
+            ("stimulus_cells", stimuli_text),
+            ("will_cells", will_text),
+            ("supply_cells", supply_text),
+            ("time_since", time_since),
+            ("diffs", diff_text),
+            ("files", files_text),
+            ("language", language),
+        ],
+    )
+}
+
+// ─── Structured output ───────────────────────────────────────────────────────
+
+pub(super) fn analysis_schema() -> Value {
+    let text = json!({"type": "string"});
+    let texts = json!({"type": "array", "items": {"type": "string"}});
+    json!({
+        "type": "object",
+        "required": ["summaries", "ideas", "flow"],
+        "properties": {
+            "summaries": {"type": "object", "additionalProperties": text},
+            "ideas": texts,
+            "flow": {
+                "type": "object",
+                "required": ["stimuli_to_will", "will_to_supply", "stuck", "next"],
+                "properties": {
+                    "stimuli_to_will": text,
+                    "will_to_supply": text,
+                    "stuck": text,
+                    "next": text,
+                    "blocked_cells": texts,
+                    "priority_cell": {"type": ["string", "null"]},
+                    "confidence": {"type": "string", "enum": ["high", "medium", "low"]},
+                    "connections": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["from_cell", "to_cell", "insight"],
+                            "properties": {"from_cell": text, "to_cell": text, "insight": text}
+                        }
+                    },
+                    "human_questions": texts,
+                    "changes_since_last": text,
+                    "actions": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["type"],
+                            "properties": {
+                                "type": {
+                                    "type": "string",
+                                    "enum": ["send_prompt", "enqueue_task", "pause_cell", "launch_cell", "create_file"]
+                                },
+                                "cell_id": text,
+                                "prompt": text,
+                                "task": text,
+                                "path": text,
+                                "content": text,
+                                "reason": text
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Parse model output as JSON, tolerating markdown fences and surrounding prose.
+pub(super) fn parse_json(text: &str) -> Result<Value, String> {
+    let trimmed = text.trim();
+    if let Ok(v) = serde_json::from_str(trimmed) {
+        return Ok(v);
+    }
+    let unfenced = trimmed
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    if let Ok(v) = serde_json::from_str(unfenced) {
+        return Ok(v);
+    }
+    let object = extract_json_object(trimmed).ok_or("no JSON object found")?;
+    serde_json::from_str(&object).map_err(|e| format!("invalid JSON: {}", e))
+}
+
+/// Parsed value (if any) and the list of schema problems.
+pub(super) fn parse_checked(text: &str, schema: &Value) -> (Option<Value>, Vec<String>) {
+    match parse_json(text) {
+        Ok(value) => {
+            let errors = validate(schema, &value);
+            (Some(value), errors)
+        }
+        Err(e) => (None, vec![e]),
+    }
+}
+
+/// Follow-up message listing what was wrong with the first reply.
+pub(super) fn repair_request(problems: &[String]) -> String {
+    format!(
+        "Your reply did not match the required JSON format:\n- {}\n\n\
+         Reply again with the complete, corrected JSON object only, no markdown.",
+        problems.join("\n- ")
+    )
+}
+
+/// Whether the repaired reply should replace the original: it must parse, and
+/// must not be worse than a reply that already parsed.
+pub(super) fn prefer_repair(parsed: bool, problems: &[String], repaired: bool, errors: &[String]) -> bool {
+    repaired && (!parsed || errors.len() <= problems.len())
+}
+
+fn snippet(text: &str) -> String {
+    let mut end = text.len().min(200);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+fn string_list(v: Option<&Value>) -> Option<Vec<String>> {
+    let items = v?.as_array()?;
+    Some(items.iter().filter_map(|i| i.as_str()).filter(|s| !s.is_empty()).map(str::to_string).collect())
+}
+
+fn non_empty_str(v: Option<&Value>) -> Option<String> {
+    v.and_then(|s| s.as_str())
+        .filter(|s| !s.is_empty() && *s != "null")
+        .map(str::to_string)
+}
+
+fn lenient_summaries(value: &Value) -> HashMap<String, String> {
+    value
+        .get("summaries")
+        .and_then(|s| s.as_object())
+        .map(|obj| {
+            obj.iter()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Keep whatever the model got right; missing text fields become empty.
+fn lenient_flow(v: &Value) -> Option<FlowAnalysis> {
+    v.as_object()?;
+    let text = |key: &str| v.get(key).and_then(|s| s.as_str()).unwrap_or("").to_string();
+    let connections = v.get("connections").and_then(|c| c.as_array()).map(|items| {
+        items
+            .iter()
+            .filter_map(|item| serde_json::from_value::<FlowConnection>(item.clone()).ok())
+            .collect()
+    });
+    // Drop malformed actions rather than the whole analysis
+    let actions = v.get("actions").and_then(|a| a.as_array()).map(|items| {
+        items
+            .iter()
+            .filter_map(|item| serde_json::from_value::<FlowAction>(item.clone()).ok())
+            .collect()
+    });
+    Some(FlowAnalysis {
+        stimuli_to_will: text("stimuli_to_will"),
+        will_to_supply: text("will_to_supply"),
+        stuck: text("stuck"),
+        next: text("next"),
+        blocked_cells: string_list(v.get("blocked_cells")),
+        priority_cell: non_empty_str(v.get("priority_cell")),
+        confidence: non_empty_str(v.get("confidence")),
+        connections,
+        human_questions: string_list(v.get("human_questions")),
+        changes_since_last: non_empty_str(v.get("changes_since_last")),
+        actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(theme: &str) -> CellState {
+        CellState {
+            id: "cell-0".to_string(),
+            theme: theme.to_string(),
+            pid: None,
+            last_output: String::new(),
+            status: "idle".to_string(),
+            updated_at: 0,
+            output_bytes: 0,
+            summary: None,
+        }
+    }
+
+    #[test]
+    fn themes_stay_inside_the_role_dir() {
+        assert!(check_theme("landing page").is_ok());
+        assert!(check_theme("v1.2 release").is_ok());
+        for theme in ["../../etc", "a/b", "..", "x\ny", "x\u{1b}[2J"] {
+            assert!(check_theme(theme).is_err(), "{:?}", theme);
         }
-        None => Ok(AnalyzeResult { summaries: HashMap::new(), ideas: Vec::new(), flow: None }),
+        assert!(cell_work_dir(&cell("../../.."), "/out", 3).is_err());
+        assert!(cell_work_dir(&cell(""), "/out/", 3).is_ok_and(|dir| !dir.ends_with('/')));
     }
 }
@@ -0,0 +1,482 @@
+// Token-budgeted prompt context for grid analysis. Estimates tokens for the
+// configured provider/model, splits the budget across cells by recency and
+// activity, and keeps the cleanest, most recent part of each cell's screen.
+// Cells with a rolling summary (see `summaries`) send it plus newer output only.
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{AiConfig, AnalysisEntry};
+use crate::CellState;
+
+use super::providers::effective_model;
+use super::utils::strip_ansi;
+
+/// Upper bound on prompt input, even for million-token models: beyond this the
+/// analysis gets slower and pricier without getting better.
+const MAX_INPUT_TOKENS: usize = 24_000;
+/// Smallest slice worth giving a cell; below this it is listed without output.
+const MIN_CELL_TOKENS: usize = 40;
+/// Share of the budget reserved for past analyses.
+const HISTORY_SHARE: f64 = 0.15;
+pub(crate) const MAX_HISTORY_ENTRIES: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellBudget {
+    pub cell_id: String,
+    pub theme: String,
+    /// Tokens of output included in the prompt.
+    pub tokens: usize,
+    /// Tokens the cleaned output would have taken in full.
+    pub full_tokens: usize,
+    pub truncated: bool,
+    /// The cell's rolling summary stood in for output it already covers.
+    #[serde(default)]
+    pub summarized: bool,
+}
+
+/// What went into the prompt and what was cut to fit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextReport {
+    pub model: String,
+    pub context_window: usize,
+    pub budget_tokens: usize,
+    pub used_tokens: usize,
+    pub cells: Vec<CellBudget>,
+    pub history_included: usize,
+    pub history_dropped: usize,
+}
+
+pub(crate) struct CellContext<'a> {
+    pub cell: &'a CellState,
+    pub text: String,
+}
+
+pub(crate) struct Context<'a> {
+    pub cells: Vec<CellContext<'a>>,
+    pub history: &'a [AnalysisEntry],
+    pub report: ContextReport,
+}
+
+// ─── Estimation ──────────────────────────────────────────────────────────────
+
+/// Rough token count: ASCII runs at the provider's chars-per-token ratio,
+/// other characters (CJK, symbols) at about one token each.
+pub(crate) fn estimate_tokens(config: &AiConfig, text: &str) -> usize {
+    let chars_per_token = match config.provider.as_str() {
+        "anthropic" => 3.5,
+        "ollama" => 3.7,
+        _ => 4.0,
+    };
+    let ascii = text.bytes().filter(|b| b.is_ascii()).count();
+    let other = text.chars().filter(|c| !c.is_ascii()).count();
+    (ascii as f64 / chars_per_token).ceil() as usize + other
+}
+
+/// Context window of the configured model, in tokens.
+pub(crate) fn context_window(config: &AiConfig) -> usize {
+    let model = effective_model(config).to_lowercase();
+    match config.provider.as_str() {
+        "gemini" => 1_000_000,
+        "anthropic" => 200_000,
+        "openai" if model.starts_with("gpt-4.1") => 1_000_000,
+        "openai" if model.starts_with('o') || model.starts_with("gpt-5") => 200_000,
+        "openai" if model.starts_with("gpt-3.5") => 16_000,
+        "openai" => 128_000,
+        // Ollama truncates to its default num_ctx unless the model file raises it.
+        "ollama" => 4_096,
+        _ => 32_000,
+    }
+}
+
+// ─── Screen text ─────────────────────────────────────────────────────────────
+
+fn is_chrome(line: &str) -> bool {
+    // Box drawing, block elements and braille spinners: TUI frames, not content.
+    line.chars()
+        .all(|c| c.is_whitespace() || ('\u{2500}'..='\u{259f}').contains(&c) || ('\u{2800}'..='\u{28ff}').contains(&c))
+}
+
+/// Agent reply markers used by Claude Code (⏺) and Codex (•).
+fn is_reply_start(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with('⏺') || line.starts_with('•') || line.starts_with('●')
+}
+
+/// ANSI-free screen text without TUI frames, repeated lines or blank runs.
+pub(crate) fn clean_screen(raw: &str) -> String {
+    let stripped = strip_ansi(raw);
+    let mut lines: Vec<&str> = Vec::new();
+    for line in stripped.lines().map(str::trim_end) {
+        if !line.is_empty() && is_chrome(line) {
+            continue;
+        }
+        if line.is_empty() && lines.last().map_or(true, |l| l.is_empty()) {
+            continue;
+        }
+        if !line.is_empty() && lines.last() == Some(&line) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// The most recent lines of `text` that fit in `budget` tokens. When the cut
+/// lands inside an agent reply, start at that reply's first line if doing so
+/// keeps at least half the budget, so the model sees whole replies.
+pub(crate) fn fit_tail(config: &AiConfig, text: &str, budget: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut used = 0;
+    let mut start = lines.len();
+    while start > 0 {
+        let cost = estimate_tokens(config, lines[start - 1]) + 1;
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        start -= 1;
+    }
+    if start == lines.len() {
+        // A single overlong line: keep its end.
+        let last = lines.last().copied().unwrap_or("");
+        let skip = last.chars().count().saturating_sub(budget * 3);
+        return last.chars().skip(skip).collect();
+    }
+    if start > 0 {
+        let mut kept = 0;
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            if is_reply_start(line) {
+                if i > start && kept * 2 <= budget {
+                    start = i;
+                }
+                break;
+            }
+            kept += estimate_tokens(config, line) + 1;
+        }
+    }
+    lines[start..].join("\n")
+}
+
+// ─── Allocation ──────────────────────────────────────────────────────────────
+
+fn weight(cell: &CellState, now_ms: u64) -> f64 {
+    let age_min = now_ms.saturating_sub(cell.updated_at) as f64 / 60_000.0;
+    let recency = if age_min < 2.0 {
+        3.0
+    } else if age_min < 10.0 {
+        2.0
+    } else if age_min < 60.0 {
+        1.5
+    } else {
+        1.0
+    };
+    let activity = match cell.status.as_str() {
+        "active" | "error" => 2.0,
+        _ => 1.0,
+    };
+    recency * activity
+}
+
+/// Split `budget` in proportion to `weights`, never giving a cell more than it
+/// needs and handing what is left over to the others.
+fn allocate(needs: &[usize], weights: &[f64], budget: usize) -> Vec<usize> {
+    let mut grants = vec![0; needs.len()];
+    let mut open: Vec<usize> = (0..needs.len()).filter(|&i| needs[i] > 0).collect();
+    let mut left = budget;
+    while !open.is_empty() && left > 0 {
+        let total: f64 = open.iter().map(|&i| weights[i]).sum();
+        let share = |i: usize| (left as f64 * weights[i] / total) as usize;
+        let satisfied: Vec<usize> = open.iter().copied().filter(|&i| needs[i] <= share(i)).collect();
+        if satisfied.is_empty() {
+            for &i in &open {
+                grants[i] = share(i);
+            }
+            break;
+        }
+        for &i in &satisfied {
+            grants[i] = needs[i];
+            left -= needs[i];
+        }
+        open.retain(|i| !satisfied.contains(i));
+    }
+    grants
+}
+
+/// Pick what of `cells` and `history` goes into the prompt. `overhead_tokens`
+/// is the fixed part of the prompt; `max_output` the reply size requested.
+pub(crate) fn build<'a>(
+    config: &AiConfig,
+    cells: &[&'a CellState],
+    history: &'a [AnalysisEntry],
+    overhead_tokens: usize,
+    max_output: usize,
+) -> Context<'a> {
+    let window = context_window(config);
+    let budget = window
+        .min(MAX_INPUT_TOKENS)
+        .saturating_sub(overhead_tokens + max_output.min(window / 4));
+
+    // History: newest first, until its share is used up.
+    let history_budget = (budget as f64 * HISTORY_SHARE) as usize;
+    let mut history_used = 0;
+    let mut history_included = 0;
+    for entry in history.iter().rev().take(MAX_HISTORY_ENTRIES) {
+        let cost: usize = entry.summaries.values().map(|s| estimate_tokens(config, s) + 6).sum::<usize>() + 8;
+        if history_used + cost > history_budget {
+            break;
+        }
+        history_used += cost;
+        history_included += 1;
+    }
+    let kept_history = &history[history.len() - history_included..];
+
+    // A cell with a rolling summary contributes the summary plus only the
+    // output printed since; summaries are short and always included.
+    let summaries: Vec<Option<&str>> = cells.iter().map(|c| c.summary.as_ref().map(|s| s.text.as_str())).collect();
+    let summary_tokens: usize = summaries.iter().flatten().map(|s| estimate_tokens(config, s) + 4).sum();
+    let cleaned: Vec<String> = cells.iter().map(|c| clean_screen(crate::summaries::new_output(c))).collect();
+    let needs: Vec<usize> = cleaned.iter().map(|t| estimate_tokens(config, t)).collect();
+    let now = crate::now_millis();
+    let weights: Vec<f64> = cells.iter().map(|c| weight(c, now)).collect();
+    let cell_budget = budget.saturating_sub(history_used + summary_tokens);
+    let grants = allocate(&needs, &weights, cell_budget);
+
+    let mut out = Vec::new();
+    let mut report_cells = Vec::new();
+    let mut used = history_used + summary_tokens;
+    for (i, cell) in cells.iter().enumerate() {
+        let grant = if grants[i] < needs[i] && grants[i] < MIN_CELL_TOKENS { 0 } else { grants[i] };
+        let recent = if grant >= needs[i] {
+            cleaned[i].clone()
+        } else if grant == 0 {
+            String::new()
+        } else {
+            fit_tail(config, &cleaned[i], grant)
+        };
+        let tokens = estimate_tokens(config, &recent);
+        used += tokens;
+        report_cells.push(CellBudget {
+            cell_id: cell.id.clone(),
+            theme: cell.theme.clone(),
+            tokens,
+            full_tokens: needs[i],
+            truncated: tokens < needs[i],
+            summarized: summaries[i].is_some(),
+        });
+        let text = match (summaries[i], recent.is_empty()) {
+            (Some(summary), true) => format!("Summary: {}", summary),
+            (Some(summary), false) => format!("Summary: {}\nRecent output:\n{}", summary, recent),
+            (None, _) => recent,
+        };
+        out.push(CellContext { cell, text });
+    }
+
+    Context {
+        cells: out,
+        history: kept_history,
+        report: ContextReport {
+            model: format!("{}/{}", config.provider, effective_model(config)),
+            context_window: window,
+            budget_tokens: budget,
+            used_tokens: used,
+            cells: report_cells,
+            history_included,
+            history_dropped: history.len() - history_included,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summaries::CellSummary;
+
+    fn config(provider: &str, model: Option<&str>) -> AiConfig {
+        AiConfig {
+            provider: provider.to_string(),
+            model: model.map(str::to_string),
+            ..AiConfig::default()
+        }
+    }
+
+    fn cell(id: &str, output: &str) -> CellState {
+        CellState {
+            id: id.to_string(),
+            theme: format!("theme of {}", id),
+            pid: None,
+            last_output: output.to_string(),
+            status: "idle".to_string(),
+            updated_at: crate::now_millis(),
+            output_bytes: output.len() as u64,
+            summary: None,
+        }
+    }
+
+    fn history(count: usize, summary: &str) -> Vec<AnalysisEntry> {
+        (0..count)
+            .map(|i| {
+                serde_json::from_value(serde_json::json!({
+                    "id": format!("a{}", i),
+                    "timestamp_ms": i,
+                    "summaries": {"cell-0": summary},
+                    "themes": {},
+                    "ideas": [],
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (0..count).map(|i| format!("output line number {}", i)).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn estimates_ascii_by_ratio_and_other_characters_one_each() {
+        assert_eq!(estimate_tokens(&config("openai", None), ""), 0);
+        assert_eq!(estimate_tokens(&config("openai", None), "abcdefgh"), 2);
+        assert_eq!(estimate_tokens(&config("anthropic", None), "abcdefgh"), 3);
+        assert_eq!(estimate_tokens(&config("gemini", None), "日本語"), 3);
+        assert_eq!(estimate_tokens(&config("gemini", None), "abcd日本"), 3);
+    }
+
+    #[test]
+    fn context_window_by_provider_and_model() {
+        assert_eq!(context_window(&config("gemini", None)), 1_000_000);
+        assert_eq!(context_window(&config("anthropic", None)), 200_000);
+        assert_eq!(context_window(&config("openai", None)), 128_000);
+        assert_eq!(context_window(&config("openai", Some("GPT-4.1-mini"))), 1_000_000);
+        assert_eq!(context_window(&config("openai", Some("o3"))), 200_000);
+        assert_eq!(context_window(&config("openai", Some("gpt-3.5-turbo"))), 16_000);
+        assert_eq!(context_window(&config("ollama", None)), 4_096);
+        assert_eq!(context_window(&config("other", None)), 32_000);
+    }
+
+    #[test]
+    fn clean_screen_drops_ansi_frames_repeats_and_blank_runs() {
+        let raw = "\x1b[32mhello\x1b[0m\r\n\n\n╭────╮\n⠋ \nhello\nhello\nworld  \n\n\n";
+        assert_eq!(clean_screen(raw), "hello\n\nhello\nworld");
+    }
+
+    #[test]
+    fn fit_tail_keeps_the_newest_lines_within_budget() {
+        let config = config("openai", None);
+        let text = (0..10).map(|i| format!("line{}", i)).collect::<Vec<_>>().join("\n");
+        // "lineN" is 2 tokens plus 1 for the newline.
+        assert_eq!(fit_tail(&config, &text, 9), "line7\nline8\nline9");
+        assert_eq!(fit_tail(&config, &text, 1000), text);
+    }
+
+    #[test]
+    fn fit_tail_keeps_the_end_of_an_overlong_line() {
+        let line = format!("{}end", "x".repeat(200));
+        let kept = fit_tail(&config("openai", None), &line, 10);
+        assert_eq!(kept.len(), 30);
+        assert!(kept.ends_with("end"));
+    }
+
+    #[test]
+    fn fit_tail_starts_at_a_reply_when_it_keeps_half_the_budget() {
+        let config = config("openai", None);
+        let partial = "a".repeat(20);
+        let text = format!("⏺ one\n{}\n⏺ two\ny1", partial);
+        // Budget 13: the cut lands in reply one; dropping its tail still leaves half.
+        assert_eq!(fit_tail(&config, &text, 13), "⏺ two\ny1");
+        // Budget 11: the tail of reply one is more than half of what fits, so keep it.
+        assert_eq!(fit_tail(&config, &text, 11), format!("{}\n⏺ two\ny1", partial));
+    }
+
+    #[test]
+    fn allocate_hands_surplus_to_cells_that_need_more() {
+        assert_eq!(allocate(&[10, 100, 100], &[1.0, 1.0, 1.0], 150), vec![10, 70, 70]);
+        assert_eq!(allocate(&[1000, 1000], &[3.0, 1.0], 100), vec![75, 25]);
+        assert_eq!(allocate(&[0, 50], &[1.0, 1.0], 100), vec![0, 50]);
+        assert_eq!(allocate(&[10, 20], &[1.0, 1.0], 1000), vec![10, 20]);
+    }
+
+    #[test]
+    fn weight_favours_recent_active_cells() {
+        let now = crate::now_millis();
+        let mut c = cell("cell-0", "");
+        c.status = "active".to_string();
+        c.updated_at = now;
+        assert_eq!(weight(&c, now), 6.0);
+        c.status = "idle".to_string();
+        c.updated_at = now - 2 * 3_600_000;
+        assert_eq!(weight(&c, now), 1.0);
+    }
+
+    #[test]
+    fn build_includes_everything_that_fits() {
+        let config = config("openai", None);
+        let a = cell("cell-0", "\x1b[1mbuilding\x1b[0m\nok");
+        let b = cell("cell-1", "tests passed");
+        let ctx = build(&config, &[&a, &b], &[], 500, 2000);
+        assert_eq!(ctx.report.budget_tokens, MAX_INPUT_TOKENS - 2500);
+        assert_eq!(ctx.cells[0].text, "building\nok");
+        assert_eq!(ctx.cells[1].text, "tests passed");
+        assert!(ctx.report.cells.iter().all(|c| !c.truncated && !c.summarized));
+        assert_eq!(ctx.report.model, "openai/gpt-4o-mini");
+    }
+
+    #[test]
+    fn build_truncates_large_cells_to_the_budget() {
+        let config = config("ollama", None);
+        let big = cell("cell-0", &numbered_lines(2000));
+        let small = cell("cell-1", "done");
+        let ctx = build(&config, &[&big, &small], &[], 100, 1000);
+        assert_eq!(ctx.report.budget_tokens, 4_096 - 1_100);
+        assert!(ctx.report.used_tokens <= ctx.report.budget_tokens);
+        assert!(ctx.report.cells[0].truncated);
+        assert!(ctx.cells[0].text.ends_with("output line number 1999"));
+        assert_eq!(ctx.cells[1].text, "done");
+        assert!(!ctx.report.cells[1].truncated);
+    }
+
+    #[test]
+    fn build_skips_cells_whose_share_is_too_small() {
+        let config = config("ollama", None);
+        let output = numbered_lines(100);
+        let cells: Vec<CellState> = (0..3).map(|i| cell(&format!("cell-{}", i), &output)).collect();
+        let refs: Vec<&CellState> = cells.iter().collect();
+        // 4096 - 3000 - 1000 leaves 96 tokens: 32 each, under MIN_CELL_TOKENS.
+        let ctx = build(&config, &refs, &[], 3000, 1000);
+        assert!(ctx.cells.iter().all(|c| c.text.is_empty()));
+        assert!(ctx.report.cells.iter().all(|c| c.truncated && c.tokens == 0));
+    }
+
+    #[test]
+    fn build_sends_summary_plus_newer_output() {
+        let config = config("openai", None);
+        let mut c = cell("cell-0", "old\nnew");
+        c.summary = Some(CellSummary { text: "did old things".to_string(), updated_at: 0, log_offset: 0, covered_bytes: 4 });
+        let ctx = build(&config, &[&c], &[], 0, 0);
+        assert_eq!(ctx.cells[0].text, "Summary: did old things\nRecent output:\nnew");
+        assert!(ctx.report.cells[0].summarized);
+
+        c.summary.as_mut().unwrap().covered_bytes = c.output_bytes;
+        let ctx = build(&config, &[&c], &[], 0, 0);
+        assert_eq!(ctx.cells[0].text, "Summary: did old things");
+    }
+
+    #[test]
+    fn build_keeps_the_newest_history_within_its_share() {
+        let config = config("openai", None);
+        let c = cell("cell-0", "ok");
+
+        let short = history(12, "fine");
+        let ctx = build(&config, &[&c], &short, 0, 0);
+        assert_eq!(ctx.report.history_included, MAX_HISTORY_ENTRIES);
+        assert_eq!(ctx.report.history_dropped, 2);
+        assert_eq!(ctx.history.last().unwrap().id, "a11");
+
+        // About 1000 tokens each against a 3600-token share.
+        let long = history(6, &"word ".repeat(800));
+        let ctx = build(&config, &[&c], &long, 0, 0);
+        assert_eq!(ctx.report.history_included, 3);
+        assert_eq!(ctx.history.first().unwrap().id, "a3");
+    }
+}
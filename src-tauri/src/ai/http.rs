@@ -0,0 +1,305 @@
+// HTTP transport for provider calls: one shared client with timeouts, retries
+// with exponential backoff on 429/5xx and network errors (honouring
+// `Retry-After`), and a per-provider concurrency limit. While a provider has
+// asked us to back off, other calls to it wait out the same cooldown.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest gap between bytes of a response; local models can think for a while.
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_ATTEMPTS: u32 = 4;
+#[cfg(not(test))]
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+// Tests exercise retries without waiting for real backoff.
+#[cfg(test)]
+const BASE_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+struct Limiter {
+    permits: Arc<Semaphore>,
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Concurrent requests allowed per provider. Ollama runs one model at a time.
+fn max_concurrent(provider: &str) -> usize {
+    match provider {
+        "Ollama" => 1,
+        _ => 4,
+    }
+}
+
+fn limiter(provider: &str) -> Arc<Limiter> {
+    static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<Limiter>>>> = OnceLock::new();
+    LIMITERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(provider.to_string())
+        .or_insert_with(|| {
+            Arc::new(Limiter {
+                permits: Arc::new(Semaphore::new(max_concurrent(provider))),
+                cooldown_until: Mutex::new(None),
+            })
+        })
+        .clone()
+}
+
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    // 529: Anthropic "overloaded".
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// `Retry-After` as delta-seconds or an HTTP date.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = crate::time::parse_http_date(value)?;
+    Some(Duration::from_millis(at.saturating_sub(crate::now_millis())))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    (BASE_BACKOFF * 2u32.pow(attempt)).min(MAX_BACKOFF)
+}
+
+/// A failed provider call. `fallback` is set when another provider may well
+/// succeed (outage, rate limit, quota, bad key), as opposed to a bad request.
+pub(crate) struct ProviderError {
+    pub message: String,
+    pub fallback: bool,
+}
+
+impl From<String> for ProviderError {
+    fn from(message: String) -> Self {
+        ProviderError { message, fallback: false }
+    }
+}
+
+/// POST `body` as JSON and return the status and parsed JSON reply, retrying
+/// transient failures.
+pub(super) async fn post_json(
+    provider: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &serde_json::Value,
+) -> Result<(reqwest::StatusCode, serde_json::Value), ProviderError> {
+    let limiter = limiter(provider);
+    let _permit = limiter.permits.clone().acquire_owned().await.map_err(|e| e.to_string())?;
+
+    let mut attempt = 0;
+    loop {
+        let wait = limiter.cooldown_until.lock().unwrap().map(|at| at.saturating_duration_since(Instant::now()));
+        if let Some(wait) = wait.filter(|w| !w.is_zero()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        let mut req = client().post(url).header("Content-Type", "application/json");
+        for (key, value) in headers {
+            req = req.header(*key, *value);
+        }
+        let (delay, error) = match req.json(body).send().await {
+            Ok(resp) if is_retryable(resp.status()) => {
+                let status = resp.status();
+                let delay = retry_after(resp.headers()).unwrap_or_else(|| backoff(attempt));
+                let detail = resp
+                    .json::<serde_json::Value>()
+                    .await
+                    .map(|v| super::utils::extract_error(&v))
+                    .unwrap_or_default();
+                (delay, format!("{} API error {}: {}", provider, status, detail))
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                return resp
+                    .json()
+                    .await
+                    .map(|json| (status, json))
+                    .map_err(|e| ProviderError {
+                        // Usually an HTML error page from a proxy or an outage.
+                        message: format!("{} response parse failed: {}", provider, e),
+                        fallback: true,
+                    });
+            }
+            Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => {
+                (backoff(attempt), format!("{} request failed: {}", provider, e))
+            }
+            Err(e) => return Err(format!("{} request failed: {}", provider, e).into()),
+        };
+
+        attempt += 1;
+        if attempt >= MAX_ATTEMPTS || delay > MAX_BACKOFF * 4 {
+            return Err(ProviderError {
+                message: format!("{} (gave up after {} attempts)", error, attempt),
+                fallback: true,
+            });
+        }
+        *limiter.cooldown_until.lock().unwrap() = Some(Instant::now() + delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    type Reply = Result<(reqwest::StatusCode, serde_json::Value), ProviderError>;
+
+    // Each test uses its own provider name so limiters and cooldowns don't leak between tests.
+    async fn post(provider: &'static str, uri: String) -> Reply {
+        post_json(provider, &format!("{}/v1/chat", uri), &[("x-api-key", "k")], &json!({"q": 1})).await
+    }
+
+    /// Run `count` calls at once and wait for all of them.
+    async fn post_many(provider: &'static str, uri: &str, count: usize) -> Vec<Reply> {
+        let handles: Vec<_> = (0..count).map(|_| tokio::spawn(post(provider, uri.to_string()))).collect();
+        let mut replies = Vec::new();
+        for handle in handles {
+            replies.push(handle.await.unwrap());
+        }
+        replies
+    }
+
+    fn ok() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({"text": "hi"}))
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        for code in [408, 429, 500, 502, 503, 504, 529] {
+            assert!(is_retryable(reqwest::StatusCode::from_u16(code).unwrap()), "{}", code);
+        }
+        for code in [200, 400, 401, 403, 404, 422] {
+            assert!(!is_retryable(reqwest::StatusCode::from_u16(code).unwrap()), "{}", code);
+        }
+    }
+
+    #[test]
+    fn retry_after_as_seconds_or_date() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, " 7 ".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(reqwest::header::RETRY_AFTER, "Thu, 01 Jan 1970 00:00:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert(reqwest::header::RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(0), BASE_BACKOFF);
+        assert_eq!(backoff(3), BASE_BACKOFF * 8);
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn retries_429_and_5xx_until_success() {
+        let server = MockServer::start().await;
+        for status in [429, 503] {
+            Mock::given(method("POST"))
+                .and(path("/v1/chat"))
+                .respond_with(ResponseTemplate::new(status))
+                .up_to_n_times(1)
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST")).and(path("/v1/chat")).respond_with(ok()).expect(1).mount(&server).await;
+
+        let (status, body) = post("test-retry", server.uri()).await.map_err(|e| e.message).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body["text"], "hi");
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({"error": {"message": "boom"}})))
+            .expect(u64::from(MAX_ATTEMPTS))
+            .mount(&server)
+            .await;
+
+        let err = post("test-give-up", server.uri()).await.unwrap_err();
+        assert!(err.fallback);
+        assert!(err.message.contains("500"), "{}", err.message);
+        assert!(err.message.contains("gave up after 4 attempts"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_4xx() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({"error": {"message": "bad"}})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (status, body) = post("test-4xx", server.uri()).await.map_err(|e| e.message).unwrap();
+        assert_eq!(status, 400);
+        assert_eq!(body["error"]["message"], "bad");
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST")).respond_with(ok()).mount(&server).await;
+
+        let started = Instant::now();
+        post("test-retry-after", server.uri()).await.map_err(|e| e.message).unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn gives_up_at_once_when_told_to_wait_too_long() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = post("test-long-wait", server.uri()).await.unwrap_err();
+        assert!(err.message.contains("gave up after 1 attempts"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_calls_per_provider() {
+        let server = MockServer::start().await;
+        let delay = Duration::from_millis(300);
+        Mock::given(method("POST")).respond_with(ok().set_delay(delay)).mount(&server).await;
+
+        // Ollama takes one call at a time: three calls run back to back.
+        let started = Instant::now();
+        assert!(post_many("Ollama", &server.uri(), 3).await.iter().all(Result::is_ok));
+        assert!(started.elapsed() >= delay * 3);
+
+        // Other providers take four at once.
+        let started = Instant::now();
+        assert!(post_many("test-parallel", &server.uri(), 3).await.iter().all(Result::is_ok));
+        assert!(started.elapsed() < delay * 3);
+    }
+}
@@ -1,13 +1,34 @@
 mod analyze;
+mod context;
+mod http;
 mod providers;
+mod schema;
+mod templates;
+mod tools;
 mod utils;
 
-pub(crate) use analyze::analyze_cells;
+pub(crate) use analyze::{analysis_prompt, analyze_cells, cell_work_dir, check_theme, get_cell_role};
+pub use context::ContextReport;
+pub(crate) use context::{clean_screen, estimate_tokens, fit_tail, MAX_HISTORY_ENTRIES};
+pub use templates::{Rendered, TemplateInfo};
+pub(crate) use templates::{init as init_templates, list as list_templates, Template};
+pub(crate) use tools::{instructions as tool_instructions, parse_step, step_schema, ToolCall, ToolSpec};
+pub(crate) use utils::{strip_ansi, AnsiStripper};
 
 use crate::storage::AiConfig;
-use providers::{check_key, call_gemini, call_openai, call_anthropic, call_ollama};
+use crate::usage::Usage;
+use http::ProviderError;
+use providers::{check_key, call_gemini, call_openai, call_anthropic, call_ollama, effective_model};
+use std::path::{Path, PathBuf};
+pub(crate) use providers::JsonOutput;
 
-#[derive(serde::Deserialize)]
+/// Chunks retrieved per work stream for the genre summary and the advisor.
+const STREAM_HITS: usize = 3;
+const STREAM_CHUNK_CHARS: usize = 800;
+/// Newest file names that, with the stream name, make the genre summary's query.
+const STREAM_QUERY_FILES: usize = 10;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GenreInput {
     pub name: String,
     pub dir: String,
@@ -19,103 +40,300 @@ pub struct ChatMessage {
     pub content: String,
 }
 
+// ─── Task routing ─────────────────────────────────────────────────────────────
+
+/// What an AI call is for; each task can use its own model (`AiConfig::tasks`).
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AiTask {
+    Name,
+    Summarize,
+    Analyze,
+    Chat,
+}
+
+impl AiTask {
+    /// Name used in `AiConfig::tasks` and the usage ledger.
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            AiTask::Name => "name",
+            AiTask::Summarize => "summarize",
+            AiTask::Analyze => "analyze",
+            AiTask::Chat => "chat",
+        }
+    }
+
+    /// `config` with this task's provider, model and temperature applied, and
+    /// the max_tokens to request (`default_max_tokens` unless overridden).
+    pub(crate) fn apply(self, config: &AiConfig, default_max_tokens: u32) -> (AiConfig, u32) {
+        let mut resolved = config.clone();
+        let Some(task) = config.tasks.get(self.key()) else { return (resolved, default_max_tokens) };
+        if let Some(provider) = task.provider.as_ref().filter(|p| !p.is_empty()) {
+            if *provider != config.provider {
+                // The main model belongs to another provider.
+                resolved.model = None;
+            }
+            resolved.provider = provider.clone();
+        }
+        if let Some(model) = task.model.as_ref().filter(|m| !m.is_empty()) {
+            resolved.model = Some(model.clone());
+        }
+        if task.temperature.is_some() {
+            resolved.temperature = task.temperature;
+        }
+        (resolved, task.max_tokens.unwrap_or(default_max_tokens))
+    }
+}
+
 // ─── Public AI call API ───────────────────────────────────────────────────────
 
-/// Single-turn AI call.
-pub async fn call_ai(config: &AiConfig, prompt: &str, max_tokens: u32) -> Result<String, String> {
+/// Single-turn AI call. `task` labels the call in the usage ledger.
+pub(crate) async fn call_ai(task: AiTask, config: &AiConfig, prompt: &str, max_tokens: u32) -> Result<String, String> {
     let messages = vec![("user".to_string(), prompt.to_string())];
-    call_ai_messages(config, None, &messages, max_tokens).await
+    call_ai_messages(task, config, None, &messages, max_tokens).await
 }
 
 /// Multi-turn chat with optional system prompt.
 /// `messages` is a list of `(role, content)` where role is "user" or "assistant".
-pub async fn call_ai_messages(
+pub(crate) async fn call_ai_messages(
+    task: AiTask,
     config: &AiConfig,
     system: Option<&str>,
     messages: &[(String, String)],
     max_tokens: u32,
 ) -> Result<String, String> {
+    call_ai_structured(task, config, system, messages, max_tokens, None).await
+}
+
+/// Like `call_ai_messages`, but asks for JSON output (see `JsonOutput`).
+/// The reply is still text and must be parsed and validated by the caller.
+pub(crate) async fn call_ai_structured(
+    task: AiTask,
+    config: &AiConfig,
+    system: Option<&str>,
+    messages: &[(String, String)],
+    max_tokens: u32,
+    json: Option<&JsonOutput<'_>>,
+) -> Result<String, String> {
+    call_ai_reply(task, config, system, messages, max_tokens, json).await.map(|reply| reply.text)
+}
+
+/// A model reply and which provider in the fallback chain produced it.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiReply {
+    pub text: String,
+    pub provider: String,
+    pub model: String,
+    /// Errors from providers tried before this one.
+    pub fallback_errors: Vec<String>,
+}
+
+impl AiReply {
+    /// "provider/model", for display.
+    pub fn answered_by(&self) -> String {
+        format!("{}/{}", self.provider, self.model)
+    }
+}
+
+/// The configured provider followed by `config.fallbacks`, each as a full config.
+fn provider_chain(config: &AiConfig) -> Vec<AiConfig> {
+    let mut chain = vec![config.clone()];
+    for choice in &config.fallbacks {
+        let mut next = config.clone();
+        next.provider = choice.provider.clone();
+        next.model = choice.model.clone();
+        let duplicate = chain.iter().any(|c| c.provider == next.provider && effective_model(c) == effective_model(&next));
+        if !duplicate {
+            chain.push(next);
+        }
+    }
+    chain
+}
+
+async fn call_provider(
+    config: &AiConfig,
+    system: Option<&str>,
+    messages: &[(String, String)],
+    max_tokens: u32,
+    json: Option<&JsonOutput<'_>>,
+) -> Result<(String, Usage), ProviderError> {
     check_key(config)?;
     match config.provider.as_str() {
-        "gemini" => call_gemini(config, system, messages, max_tokens).await,
-        "openai" => call_openai(config, system, messages, max_tokens).await,
-        "anthropic" => call_anthropic(config, system, messages, max_tokens).await,
-        "ollama" => call_ollama(config, system, messages, max_tokens).await,
-        p => Err(format!(
-            "Unknown provider: \"{}\". Set a valid provider in Settings (⚙).",
-            p
-        )),
+        "gemini" => call_gemini(config, system, messages, max_tokens, json).await,
+        "openai" => call_openai(config, system, messages, max_tokens, json).await,
+        "anthropic" => call_anthropic(config, system, messages, max_tokens, json).await,
+        "ollama" => call_ollama(config, system, messages, max_tokens, json).await,
+        p => Err(ProviderError {
+            message: format!("Unknown provider: \"{}\". Set a valid provider in Settings (⚙).", p),
+            fallback: true,
+        }),
     }
 }
 
-// ─── Tauri commands ───────────────────────────────────────────────────────────
+/// Call the configured provider, moving down the fallback chain when one fails
+/// in a way another provider might not (see `ProviderError::fallback`).
+pub(crate) async fn call_ai_reply(
+    task: AiTask,
+    config: &AiConfig,
+    system: Option<&str>,
+    messages: &[(String, String)],
+    max_tokens: u32,
+    json: Option<&JsonOutput<'_>>,
+) -> Result<AiReply, String> {
+    let mut fallback_errors = Vec::new();
+    for candidate in provider_chain(config) {
+        if let Err(e) = crate::usage::check_cap(&candidate, &effective_model(&candidate)) {
+            fallback_errors.push(e);
+            continue;
+        }
+        match call_provider(&candidate, system, messages, max_tokens, json).await {
+            Ok((text, usage)) => {
+                let model = effective_model(&candidate);
+                crate::usage::record(task.key(), &candidate, &model, usage);
+                return Ok(AiReply {
+                    text,
+                    model,
+                    provider: candidate.provider,
+                    fallback_errors,
+                })
+            }
+            Err(e) if e.fallback => fallback_errors.push(e.message),
+            Err(e) => {
+                fallback_errors.push(e.message);
+                break;
+            }
+        }
+    }
+    Err(fallback_errors.join("\n"))
+}
 
-#[tauri::command]
-pub async fn summarize_all_genres(
-    ai_config: tauri::State<'_, std::sync::Mutex<AiConfig>>,
-    genres: Vec<GenreInput>,
-    language: String,
-) -> Result<String, String> {
-    let config = ai_config.lock().unwrap().clone();
+/// `(rel_path, abs_path, modified_ms)` as `walk_dir` returns it.
+type StreamFile = (String, PathBuf, u64);
+
+/// Files under a stream's dir, newest first. None when the dir exists outside
+/// the workspace roots: such streams are left out of prompts and previews.
+fn stream_files(dir: &str) -> Option<(PathBuf, Vec<StreamFile>)> {
+    let expanded = crate::files::expand_tilde(dir);
+    if !Path::new(&expanded).exists() {
+        return Some((expanded.into(), Vec::new()));
+    }
+    let root = match crate::sandbox::check(dir) {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("sandbox: {}", e);
+            return None;
+        }
+    };
+    let mut files = crate::files::walk_dir(&root);
+    files.sort_by_key(|f| std::cmp::Reverse(f.2));
+    Some((root, files))
+}
 
+/// The `genre_summary` prompt: file lists per work stream and the chunks of its
+/// files that best match what it worked on last (its name and newest files).
+pub(crate) fn genre_summary_prompt(genres: &[GenreInput], language: &str) -> Rendered {
     let genre_sections: Vec<String> = genres
         .iter()
-        .map(|g| {
-            let expanded = crate::files::expand_tilde(&g.dir);
-            let root = std::path::Path::new(&expanded);
-            let files = if root.exists() {
-                let mut v = crate::files::walk_dir(root);
-                v.sort_by(|a, b| b.2.cmp(&a.2));
-                v
-            } else {
-                Vec::new()
-            };
+        .filter_map(|g| {
+            let (root, files) = stream_files(&g.dir)?;
             if files.is_empty() {
-                return format!("=== {} ===\n(no files)", g.name);
+                return Some(format!("=== {} ===\n(no files)", g.name));
             }
             let file_list = files
                 .iter()
                 .map(|(n, _, _)| format!("  - {}", n))
                 .collect::<Vec<_>>()
                 .join("\n");
-            let snippets = files
-                .iter()
-                .take(2)
-                .filter_map(|(name, path, _)| {
-                    let content = std::fs::read_to_string(path).ok()?;
-                    let mut end = content.len().min(1200);
-                    while !content.is_char_boundary(end) {
-                        end -= 1;
-                    }
-                    Some(format!("--- {}\n{}", name, &content[..end]))
-                })
-                .collect::<Vec<_>>()
-                .join("\n\n");
-            format!(
-                "=== {} ===\nFiles ({} total):\n{}\n\nRecent content:\n{}",
+            let newest: Vec<&str> = files.iter().take(STREAM_QUERY_FILES).map(|(n, _, _)| n.as_str()).collect();
+            let query = format!("{} {}", g.name, newest.join(" "));
+            let hits = crate::retrieval::search(&root, &query, STREAM_HITS);
+            let excerpts = if hits.is_empty() {
+                "(no matching files)".to_string()
+            } else {
+                crate::retrieval::format_hits(&hits, STREAM_CHUNK_CHARS)
+            };
+            Some(format!(
+                "=== {} ===\nFiles ({} total):\n{}\n\nRelevant content:\n{}",
                 g.name,
                 files.len(),
                 file_list,
-                snippets
-            )
+                excerpts
+            ))
         })
         .collect();
 
+    templates::render(
+        Template::GenreSummary,
+        &[("genres", &genre_sections.join("\n\n")), ("language", language)],
+    )
+}
+
+/// The `control_advisor` system prompt. Each stream lists its recent files and,
+/// given the user's `question`, the chunks of its files most relevant to it.
+pub(crate) fn control_advisor_prompt(genres: &[GenreInput], language: &str, question: Option<&str>) -> Rendered {
+    let context = genres
+        .iter()
+        .filter_map(|g| {
+            let (root, files) = stream_files(&g.dir)?;
+            if files.is_empty() {
+                return Some(format!("[{}]: no files yet", g.name));
+            }
+            let recent = files.iter().take(10).map(|(rel, _, _)| format!("  - {}", rel)).collect::<Vec<_>>().join("\n");
+            let hits = question.map(|q| crate::retrieval::search(&root, q, STREAM_HITS)).unwrap_or_default();
+            if hits.is_empty() {
+                return Some(format!("[{}]: {} file(s), newest:\n{}", g.name, files.len(), recent));
+            }
+            let excerpts = crate::retrieval::format_hits(&hits, STREAM_CHUNK_CHARS);
+            Some(format!("[{}]: {} file(s), newest:\n{}\n{}", g.name, files.len(), recent, excerpts))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    templates::render(Template::ControlAdvisor, &[("project_state", &context), ("language", language)])
+}
+
+// ─── Tauri commands ───────────────────────────────────────────────────────────
+
+/// Short theme for a cell, from the first stretch of its output.
+#[tauri::command]
+pub async fn suggest_cell_name(
+    ai_config: tauri::State<'_, std::sync::Mutex<AiConfig>>,
+    output: String,
+    language: String,
+) -> Result<String, String> {
+    let (config, max_tokens) = AiTask::Name.apply(&ai_config.lock().unwrap(), 30);
+    let text = clean_screen(&output);
     let prompt = format!(
-        "You are reviewing AI agent work output across multiple streams \
-        (stimulus=research/input, will=planning, supply=deliverables).\n\
-        Write 2-3 concise sentences summarizing the overall progress: what has been accomplished, \
-        what is in progress, and what comes next.\n\
-        Be specific and chronological. No filler phrases. Plain text only, no markdown, no bullet points.\n\
-        \n\
-        {}\n\
-        \n\
-        Respond in: {}",
-        genre_sections.join("\n\n"),
-        language
+        "Below is the start of a terminal session where an AI agent works on a task.\n\
+        Name the task in 2-4 words, suitable as a short folder-like label. \
+        Reply with the name only: no quotes, no punctuation at the end.\n\
+        Respond in: {}\n\n{}",
+        language,
+        fit_tail(&config, &text, 1500)
     );
+    let name = call_ai(AiTask::Name, &config, &prompt, max_tokens).await?;
+    // The name becomes a folder, so slashes become dashes.
+    let name: String = name
+        .lines()
+        .next()
+        .unwrap_or("")
+        .trim()
+        .trim_matches(['"', '\'', '.', '`'])
+        .replace('/', "-");
+    check_theme(&name)?;
+    Ok(name)
+}
 
-    call_ai(&config, &prompt, 300).await
+#[tauri::command]
+pub async fn summarize_all_genres(
+    ai_config: tauri::State<'_, std::sync::Mutex<AiConfig>>,
+    genres: Vec<GenreInput>,
+    language: String,
+) -> Result<String, String> {
+    let (config, max_tokens) = AiTask::Summarize.apply(&ai_config.lock().unwrap(), 300);
+
+    let prompt = genre_summary_prompt(&genres, &language);
+    call_ai(AiTask::Summarize, &config, &prompt.text, max_tokens).await
 }
 
 #[tauri::command]
@@ -124,60 +342,11 @@ pub async fn chat_control(
     messages: Vec<ChatMessage>,
     genres: Vec<GenreInput>,
     language: String,
-) -> Result<String, String> {
-    let config = ai_config.lock().unwrap().clone();
-
-    let context = genres
-        .iter()
-        .map(|g| {
-            let expanded = crate::files::expand_tilde(&g.dir);
-            let root = std::path::Path::new(&expanded);
-            let files: Vec<(String, String)> = if root.exists() {
-                let walked = crate::files::walk_dir(root);
-                let mut v: Vec<(String, String)> = walked
-                    .into_iter()
-                    .map(|(rel, path, _)| {
-                        let snippet = std::fs::read_to_string(&path).unwrap_or_default();
-                        let mut end = snippet.len().min(600);
-                        while !snippet.is_char_boundary(end) {
-                            end -= 1;
-                        }
-                        (rel, snippet[..end].to_string())
-                    })
-                    .collect();
-                v.sort_by_key(|f| f.0.clone());
-                v
-            } else {
-                Vec::new()
-            };
-            if files.is_empty() {
-                return format!("[{}]: no files yet", g.name);
-            }
-            let items = files
-                .iter()
-                .take(3)
-                .map(|(name, snippet)| {
-                    format!(
-                        "  - {}\n{}",
-                        name,
-                        snippet.lines().take(8).collect::<Vec<_>>().join("\n")
-                    )
-                })
-                .collect::<Vec<_>>()
-                .join("\n");
-            format!("[{}]: {} file(s)\n{}", g.name, files.len(), items)
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n");
+) -> Result<AiReply, String> {
+    let (config, max_tokens) = AiTask::Chat.apply(&ai_config.lock().unwrap(), 600);
 
-    let system = format!(
-        "You are a strategic advisor embedded in Chaos Grid, a multi-agent productivity tool.\n\
-        Work streams: Stimulus (research/input) → Will (planning) → Supply (deliverables).\n\
-        Your role: help the user understand current progress, identify bottlenecks, and decide next actions.\n\
-        Be concise and specific. No filler. Respond in: {}\n\n\
-        Current project state:\n{}",
-        language, context
-    );
+    let question = messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.as_str());
+    let system = control_advisor_prompt(&genres, &language, question);
 
     let msgs: Vec<(String, String)> = messages
         .iter()
@@ -191,5 +360,114 @@ pub async fn chat_control(
         })
         .collect();
 
-    call_ai_messages(&config, Some(&system), &msgs, 600).await
+    call_ai_reply(AiTask::Chat, &config, Some(&system.text), &msgs, max_tokens, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ProviderChoice;
+    use serde_json::json;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn choice(provider: &str, model: Option<&str>) -> ProviderChoice {
+        ProviderChoice { provider: provider.to_string(), model: model.map(str::to_string) }
+    }
+
+    /// Ollama at `url` answering as `model`, then `fallbacks`. Ollama needs no key,
+    /// so each model name stands for a different provider in the chain.
+    fn ollama_chain(url: &str, model: &str, fallbacks: &[&str]) -> AiConfig {
+        AiConfig {
+            provider: "ollama".to_string(),
+            model: Some(model.to_string()),
+            ollama_url: url.to_string(),
+            fallbacks: fallbacks.iter().map(|m| choice("ollama", Some(m))).collect(),
+            ..AiConfig::default()
+        }
+    }
+
+    async fn mount(server: &MockServer, model: &str, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({"model": model})))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    fn answer(text: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({"message": {"content": text}, "eval_count": 1}))
+    }
+
+    async fn ask(config: &AiConfig) -> Result<AiReply, String> {
+        call_ai_reply(AiTask::Chat, config, None, &[("user".to_string(), "hi".to_string())], 10, None).await
+    }
+
+    #[test]
+    fn chain_keeps_order_and_drops_duplicates() {
+        let config = AiConfig {
+            provider: "gemini".to_string(),
+            model: None,
+            fallbacks: vec![
+                choice("gemini", Some(&effective_model(&AiConfig::default()))),
+                choice("ollama", Some("llama3.2")),
+                choice("openai", None),
+                choice("ollama", Some("llama3.2")),
+            ],
+            ..AiConfig::default()
+        };
+        let chain: Vec<(String, String)> =
+            provider_chain(&config).iter().map(|c| (c.provider.clone(), effective_model(c))).collect();
+        assert_eq!(chain.len(), 3, "{:?}", chain);
+        assert_eq!(chain[0].0, "gemini");
+        assert_eq!(chain[1], ("ollama".to_string(), "llama3.2".to_string()));
+        assert_eq!(chain[2].0, "openai");
+    }
+
+    #[tokio::test]
+    async fn falls_back_when_the_first_provider_is_down() {
+        let server = MockServer::start().await;
+        mount(&server, "fallback-down", ResponseTemplate::new(503)).await;
+        mount(&server, "fallback-up", answer("from the second")).await;
+
+        let reply = ask(&ollama_chain(&server.uri(), "fallback-down", &["fallback-up"])).await.unwrap();
+        assert_eq!(reply.text, "from the second");
+        assert_eq!(reply.answered_by(), "ollama/fallback-up");
+        assert_eq!(reply.fallback_errors.len(), 1);
+        assert!(reply.fallback_errors[0].contains("503"), "{:?}", reply.fallback_errors);
+    }
+
+    #[tokio::test]
+    async fn rejected_key_stops_the_chain() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            "stop-unauthorized",
+            ResponseTemplate::new(401).set_body_json(json!({"error": {"message": "invalid api key"}})),
+        )
+        .await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({"model": "stop-never"})))
+            .respond_with(answer("should not be asked"))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let err = ask(&ollama_chain(&server.uri(), "stop-unauthorized", &["stop-never"])).await.unwrap_err();
+        assert!(err.contains("401"), "{}", err);
+        assert!(err.contains("invalid api key"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn reports_every_error_when_all_fail() {
+        let server = MockServer::start().await;
+        mount(&server, "all-missing", ResponseTemplate::new(404)).await;
+        mount(&server, "all-quota", ResponseTemplate::new(402)).await;
+
+        let err = ask(&ollama_chain(&server.uri(), "all-missing", &["all-quota"])).await.unwrap_err();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", err);
+        assert!(lines[0].contains("404") && lines[1].contains("402"), "{}", err);
+    }
 }
@@ -1,7 +1,16 @@
 use crate::storage::AiConfig;
+use crate::usage::Usage;
 
+use super::http::{post_json, ProviderError};
 use super::utils::{extract_error, pull_text};
 
+/// Request JSON matching `schema`, using the provider's native mechanism:
+/// Gemini/OpenAI/Ollama JSON modes, or a forced tool call on Anthropic.
+pub(crate) struct JsonOutput<'a> {
+    pub name: &'a str,
+    pub schema: &'a serde_json::Value,
+}
+
 pub(super) fn effective_model(config: &AiConfig) -> String {
     if let Some(m) = &config.model {
         if !m.is_empty() {
@@ -24,7 +33,7 @@ pub(super) fn active_api_key(config: &AiConfig) -> &str {
     }
 }
 
-pub(super) fn check_key(config: &AiConfig) -> Result<(), String> {
+pub(super) fn check_key(config: &AiConfig) -> Result<(), ProviderError> {
     if config.provider == "ollama" {
         return Ok(());
     }
@@ -35,41 +44,46 @@ pub(super) fn check_key(config: &AiConfig) -> Result<(), String> {
             "anthropic" => "Anthropic",
             _ => "Gemini",
         };
-        return Err(format!(
-            "{} API key is not set. Please configure it in Settings (⚙).",
-            name
-        ));
+        return Err(ProviderError {
+            message: format!("{} API key is not set. Please configure it in Settings (⚙).", name),
+            fallback: true,
+        });
     }
     Ok(())
 }
 
+/// Token counts from a reply: Gemini `usageMetadata`, OpenAI / Anthropic `usage`,
+/// Ollama eval counts. Missing fields count as 0.
+fn parse_usage(provider_name: &str, resp_json: &serde_json::Value) -> Usage {
+    let (input, output) = match provider_name {
+        "Gemini" => ("/usageMetadata/promptTokenCount", "/usageMetadata/candidatesTokenCount"),
+        "OpenAI" => ("/usage/prompt_tokens", "/usage/completion_tokens"),
+        "Anthropic" => ("/usage/input_tokens", "/usage/output_tokens"),
+        _ => ("/prompt_eval_count", "/eval_count"),
+    };
+    let count = |path: &str| resp_json.pointer(path).and_then(|v| v.as_u64()).unwrap_or(0);
+    Usage { input_tokens: count(input), output_tokens: count(output) }
+}
+
 async fn send_request(
     url: &str,
     headers: &[(&str, &str)],
     body: &serde_json::Value,
     provider_name: &str,
     text_path: &str,
-) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let mut req = client.post(url).header("Content-Type", "application/json");
-    for (key, value) in headers {
-        req = req.header(*key, *value);
-    }
-    let resp = req
-        .json(body)
-        .send()
-        .await
-        .map_err(|e| format!("{} request failed: {}", provider_name, e))?;
-    let status = resp.status();
-    let resp_json: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| format!("{} response parse failed: {}", provider_name, e))?;
+) -> Result<(String, Usage), ProviderError> {
+    let (status, resp_json) = post_json(provider_name, url, headers, body).await?;
     if !status.is_success() {
         let msg = extract_error(&resp_json);
-        return Err(format!("{} API error {}: {}", provider_name, status, msg));
+        return Err(ProviderError {
+            message: format!("{} API error {}: {}", provider_name, status, msg),
+            // Billing/quota or unknown model: another provider can still answer. A
+            // rejected key (401) or a bad request is for the user to fix, not to route around.
+            fallback: matches!(status.as_u16(), 402..=404),
+        });
     }
-    pull_text(&resp_json, text_path)
+    let text = pull_text(&resp_json, text_path)?;
+    Ok((text, parse_usage(provider_name, &resp_json)))
 }
 
 pub(super) async fn call_gemini(
@@ -77,7 +91,8 @@ pub(super) async fn call_gemini(
     system: Option<&str>,
     messages: &[(String, String)],
     max_tokens: u32,
-) -> Result<String, String> {
+    json: Option<&JsonOutput<'_>>,
+) -> Result<(String, Usage), ProviderError> {
     let model = effective_model(config);
     let key = active_api_key(config);
     let url = format!(
@@ -97,10 +112,16 @@ pub(super) async fn call_gemini(
         contents.push(serde_json::json!({"role": gemini_role, "parts": [{"text": content}]}));
     }
 
-    let body = serde_json::json!({
+    let mut body = serde_json::json!({
         "contents": contents,
         "generationConfig": {"maxOutputTokens": max_tokens}
     });
+    if let Some(t) = config.temperature {
+        body["generationConfig"]["temperature"] = serde_json::json!(t);
+    }
+    if json.is_some() {
+        body["generationConfig"]["responseMimeType"] = serde_json::json!("application/json");
+    }
 
     send_request(&url, &[], &body, "Gemini", "/candidates/0/content/parts/0/text").await
 }
@@ -110,7 +131,8 @@ pub(super) async fn call_openai(
     system: Option<&str>,
     messages: &[(String, String)],
     max_tokens: u32,
-) -> Result<String, String> {
+    json: Option<&JsonOutput<'_>>,
+) -> Result<(String, Usage), ProviderError> {
     let model = effective_model(config);
     let key = active_api_key(config);
     let auth = format!("Bearer {}", key);
@@ -124,11 +146,17 @@ pub(super) async fn call_openai(
         msgs.push(serde_json::json!({"role": r, "content": content}));
     }
 
-    let body = serde_json::json!({
+    let mut body = serde_json::json!({
         "model": model,
         "messages": msgs,
         "max_tokens": max_tokens
     });
+    if let Some(t) = config.temperature {
+        body["temperature"] = serde_json::json!(t);
+    }
+    if json.is_some() {
+        body["response_format"] = serde_json::json!({"type": "json_object"});
+    }
 
     send_request(
         "https://api.openai.com/v1/chat/completions",
@@ -144,7 +172,8 @@ pub(super) async fn call_anthropic(
     system: Option<&str>,
     messages: &[(String, String)],
     max_tokens: u32,
-) -> Result<String, String> {
+    json: Option<&JsonOutput<'_>>,
+) -> Result<(String, Usage), ProviderError> {
     let model = effective_model(config);
     let key = active_api_key(config);
 
@@ -162,13 +191,28 @@ pub(super) async fn call_anthropic(
     if let Some(sys) = system {
         body["system"] = serde_json::Value::String(sys.to_string());
     }
+    if let Some(t) = config.temperature {
+        body["temperature"] = serde_json::json!(t);
+    }
+    // Forcing the tool makes its input (the structured result) the first content block.
+    let text_path = if let Some(json) = json {
+        body["tools"] = serde_json::json!([{
+            "name": json.name,
+            "description": "Report the result in structured form.",
+            "input_schema": json.schema,
+        }]);
+        body["tool_choice"] = serde_json::json!({"type": "tool", "name": json.name});
+        "/content/0/input"
+    } else {
+        "/content/0/text"
+    };
 
     send_request(
         "https://api.anthropic.com/v1/messages",
         &[("x-api-key", key), ("anthropic-version", "2023-06-01")],
         &body,
         "Anthropic",
-        "/content/0/text",
+        text_path,
     ).await
 }
 
@@ -177,7 +221,8 @@ pub(super) async fn call_ollama(
     system: Option<&str>,
     messages: &[(String, String)],
     max_tokens: u32,
-) -> Result<String, String> {
+    json: Option<&JsonOutput<'_>>,
+) -> Result<(String, Usage), ProviderError> {
     let model = effective_model(config);
     let base_url = if config.ollama_url.is_empty() {
         "http://localhost:11434"
@@ -195,12 +240,62 @@ pub(super) async fn call_ollama(
         msgs.push(serde_json::json!({"role": r, "content": content}));
     }
 
-    let body = serde_json::json!({
+    let mut body = serde_json::json!({
         "model": model,
         "messages": msgs,
         "stream": false,
         "options": {"num_predict": max_tokens}
     });
+    if let Some(t) = config.temperature {
+        body["options"]["temperature"] = serde_json::json!(t);
+    }
+    if json.is_some() {
+        body["format"] = serde_json::json!("json");
+    }
 
     send_request(&url, &[], &body, "Ollama", "/message/content").await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(fixture: &str) -> serde_json::Value {
+        serde_json::from_str(fixture).unwrap()
+    }
+
+    fn counts(usage: Usage) -> (u64, u64) {
+        (usage.input_tokens, usage.output_tokens)
+    }
+
+    #[test]
+    fn usage_from_each_reply_format() {
+        let gemini = reply(include_str!("fixtures/gemini_reply.json"));
+        assert_eq!(counts(parse_usage("Gemini", &gemini)), (1234, 56));
+        let openai = reply(include_str!("fixtures/openai_reply.json"));
+        assert_eq!(counts(parse_usage("OpenAI", &openai)), (812, 9));
+        let anthropic = reply(include_str!("fixtures/anthropic_reply.json"));
+        assert_eq!(counts(parse_usage("Anthropic", &anthropic)), (950, 7));
+        let ollama = reply(include_str!("fixtures/ollama_reply.json"));
+        assert_eq!(counts(parse_usage("Ollama", &ollama)), (430, 4));
+    }
+
+    #[test]
+    fn missing_usage_counts_as_zero() {
+        let ollama = reply(include_str!("fixtures/ollama_reply.json"));
+        assert_eq!(counts(parse_usage("Gemini", &ollama)), (0, 0));
+        assert_eq!(counts(parse_usage("OpenAI", &serde_json::json!({"usage": {}}))), (0, 0));
+    }
+
+    #[test]
+    fn reply_text_paths_match_the_fixtures() {
+        for (fixture, path) in [
+            (include_str!("fixtures/gemini_reply.json"), "/candidates/0/content/parts/0/text"),
+            (include_str!("fixtures/openai_reply.json"), "/choices/0/message/content"),
+            (include_str!("fixtures/anthropic_reply.json"), "/content/0/text"),
+            (include_str!("fixtures/ollama_reply.json"), "/message/content"),
+        ] {
+            assert_eq!(pull_text(&reply(fixture), path).ok().as_deref(), Some("Landing page"));
+        }
+    }
+}
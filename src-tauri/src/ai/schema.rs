@@ -0,0 +1,168 @@
+// Minimal JSON Schema checking for model output: `type` (string or list),
+// `properties`, `required`, `items` and `enum`. Enough to tell a model exactly
+// what to fix in a repair round-trip.
+
+use serde_json::Value;
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let at = if path.is_empty() { "/" } else { path };
+    if let Some(ty) = schema.get("type") {
+        let allowed: Vec<&str> = match ty {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(list) => list.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(t, value)) {
+            errors.push(format!("{}: expected {}", at, allowed.join(" or ")));
+            return;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(value) {
+            let names: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            errors.push(format!("{}: must be one of {}", at, names.join(", ")));
+        }
+    }
+    if let (Some(props), Some(obj)) = (schema.get("properties").and_then(|p| p.as_object()), value.as_object()) {
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !obj.contains_key(key) {
+                    errors.push(format!("{}/{}: missing", path, key));
+                }
+            }
+        }
+        for (key, sub) in props {
+            if let Some(v) = obj.get(key) {
+                check(sub, v, &format!("{}/{}", path, key), errors);
+            }
+        }
+    }
+    if let (Some(items), Some(list)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in list.iter().enumerate() {
+            check(items, item, &format!("{}/{}", path, i), errors);
+        }
+    }
+    if let (Some(extra), Some(obj)) = (schema.get("additionalProperties"), value.as_object()) {
+        if extra.is_object() {
+            let known = schema.get("properties").and_then(|p| p.as_object());
+            for (key, v) in obj {
+                if known.map_or(true, |k| !k.contains_key(key)) {
+                    check(extra, v, &format!("{}/{}", path, key), errors);
+                }
+            }
+        }
+    }
+}
+
+/// Validate `value` against `schema`. Returns one message per problem, with a JSON pointer.
+pub(crate) fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(schema, value, "", &mut errors);
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::analyze::{analysis_schema, parse_checked, prefer_repair, repair_request};
+    use serde_json::json;
+
+    const VALID: &str = include_str!("fixtures/valid.json");
+    const FENCED: &str = include_str!("fixtures/fenced.md");
+    const TRUNCATED: &str = include_str!("fixtures/truncated.txt");
+    const MISSING_FIELDS: &str = include_str!("fixtures/missing_fields.json");
+    const WRONG_ENUM: &str = include_str!("fixtures/wrong_enum.json");
+
+    #[test]
+    fn valid_reply_has_no_problems() {
+        let (value, errors) = parse_checked(VALID, &analysis_schema());
+        assert!(value.is_some());
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn fenced_reply_parses() {
+        let (value, errors) = parse_checked(FENCED, &analysis_schema());
+        assert_eq!(value.unwrap()["flow"]["next"], "Keep going");
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn truncated_reply_does_not_parse() {
+        let (value, errors) = parse_checked(TRUNCATED, &analysis_schema());
+        assert!(value.is_none());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn missing_fields_are_reported_with_pointers() {
+        let (value, errors) = parse_checked(MISSING_FIELDS, &analysis_schema());
+        assert!(value.is_some());
+        assert_eq!(
+            errors,
+            vec![
+                "/ideas: missing",
+                "/flow/stimuli_to_will: missing",
+                "/flow/will_to_supply: missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_enum_values_are_reported() {
+        let (_, errors) = parse_checked(WRONG_ENUM, &analysis_schema());
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("/flow/confidence: must be one of \"high\"")));
+        assert!(errors.iter().any(|e| e.starts_with("/flow/actions/0/type: must be one of")));
+    }
+
+    #[test]
+    fn type_mismatch_stops_at_the_node() {
+        let schema = json!({"type": "object", "properties": {"n": {"type": "integer", "enum": [1, 2]}}});
+        assert_eq!(validate(&schema, &json!({"n": "x"})), vec!["/n: expected integer"]);
+        assert_eq!(validate(&schema, &json!([])), vec!["/: expected object"]);
+        assert!(validate(&json!({"type": ["string", "null"]}), &Value::Null).is_empty());
+    }
+
+    #[test]
+    fn repair_request_lists_every_problem() {
+        let (_, problems) = parse_checked(MISSING_FIELDS, &analysis_schema());
+        let request = repair_request(&problems);
+        for problem in &problems {
+            assert!(request.contains(&format!("- {}", problem)));
+        }
+    }
+
+    #[test]
+    fn repair_replaces_the_reply_only_when_it_is_not_worse() {
+        let schema = analysis_schema();
+        let (first, problems) = parse_checked(WRONG_ENUM, &schema);
+        let (fixed, none) = parse_checked(VALID, &schema);
+        assert!(prefer_repair(first.is_some(), &problems, fixed.is_some(), &none));
+
+        // An unparseable repair never replaces a parsed reply.
+        let (broken, parse_error) = parse_checked(TRUNCATED, &schema);
+        assert!(!prefer_repair(first.is_some(), &problems, broken.is_some(), &parse_error));
+
+        // A repair with more problems keeps the original.
+        let (worse, more) = parse_checked(MISSING_FIELDS, &schema);
+        assert!(more.len() > problems.len());
+        assert!(!prefer_repair(first.is_some(), &problems, worse.is_some(), &more));
+
+        // Anything that parses beats a reply that did not.
+        assert!(prefer_repair(broken.is_some(), &parse_error, worse.is_some(), &more));
+    }
+}
@@ -0,0 +1,248 @@
+// Prompt templates. Each named template has a built-in text (`ai/prompts/*.md`)
+// that a file of the same name in `<app config dir>/prompts/` overrides.
+// Templates reference their variables as `{{name}}`; an override that uses a
+// variable its template does not define is rejected in favour of the built-in.
+
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::Manager;
+
+/// Override directory; unset until `init`, and then only built-ins are used.
+static PROMPTS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Resolve the override directory, moving over `~/.chaos-grid-prompts` from
+/// older versions if the new one doesn't exist yet.
+pub(crate) fn init(app: &tauri::AppHandle) {
+    let Ok(dir) = app.path().app_config_dir().map(|d| d.join("prompts")) else { return };
+    let legacy = dirs::home_dir().map(|h| h.join(".chaos-grid-prompts"));
+    if let Some(legacy) = legacy.filter(|l| l.is_dir() && !dir.exists()) {
+        let moved = dir.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::rename(&legacy, &dir));
+        if let Err(e) = moved {
+            eprintln!("prompt templates: could not move {} to {}: {}", legacy.display(), dir.display(), e);
+        }
+    }
+    let _ = PROMPTS_DIR.set(dir);
+}
+
+fn prompts_dir() -> Option<&'static Path> {
+    PROMPTS_DIR.get().map(PathBuf::as_path)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Template {
+    Analysis,
+    GenreSummary,
+    ControlAdvisor,
+}
+
+const ALL: [Template; 3] = [Template::Analysis, Template::GenreSummary, Template::ControlAdvisor];
+
+impl Template {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Template::Analysis => "analysis",
+            Template::GenreSummary => "genre_summary",
+            Template::ControlAdvisor => "control_advisor",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Result<Template, String> {
+        ALL.into_iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| format!("unknown prompt template: {}", name))
+    }
+
+    /// Variables the template may reference.
+    pub(crate) fn variables(self) -> &'static [&'static str] {
+        match self {
+            Template::Analysis => &[
+                "history",
+                "stimulus_cells",
+                "will_cells",
+                "supply_cells",
+                "time_since",
+                "diffs",
+                "files",
+                "language",
+            ],
+            Template::GenreSummary => &["genres", "language"],
+            Template::ControlAdvisor => &["project_state", "language"],
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            Template::Analysis => include_str!("prompts/analysis.md"),
+            Template::GenreSummary => include_str!("prompts/genre_summary.md"),
+            Template::ControlAdvisor => include_str!("prompts/control_advisor.md"),
+        }
+    }
+
+    pub(crate) fn override_path(self) -> Option<PathBuf> {
+        prompts_dir().map(|dir| dir.join(format!("{}.md", self.name())))
+    }
+}
+
+/// A rendered prompt and where its text came from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rendered {
+    pub text: String,
+    /// Path of the override file, or "built-in".
+    pub source: String,
+    /// Why an override was ignored, if it was.
+    pub warnings: Vec<String>,
+}
+
+fn placeholder() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+fn check(template: Template, text: &str) -> Result<(), String> {
+    let unknown: Vec<&str> = placeholder()
+        .captures_iter(text)
+        .filter_map(|c| c.get(1).map(|m| m.as_str()))
+        .filter(|name| !template.variables().contains(name))
+        .collect();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown variable(s) {} (available: {})",
+            unknown.join(", "),
+            template.variables().join(", ")
+        ))
+    }
+}
+
+/// The override text if present and valid, else the built-in.
+fn load(template: Template) -> (String, String, Vec<String>) {
+    load_from(template, template.override_path())
+}
+
+fn load_from(template: Template, path: Option<PathBuf>) -> (String, String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let Some(path) = path else {
+        return (template.builtin().to_string(), "built-in".to_string(), warnings);
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => match check(template, &text) {
+            Ok(()) => return (text, path.display().to_string(), warnings),
+            Err(e) => warnings.push(format!("{}: {}; using the built-in template", path.display(), e)),
+        },
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warnings.push(format!("{}: {}; using the built-in template", path.display(), e))
+        }
+        Err(_) => {}
+    }
+    (template.builtin().to_string(), "built-in".to_string(), warnings)
+}
+
+/// Render `template` with `vars`; variables the text references but `vars` lacks render empty.
+pub(crate) fn render(template: Template, vars: &[(&str, &str)]) -> Rendered {
+    let (text, source, warnings) = load(template);
+    Rendered { text: fill(&text, vars), source, warnings }
+}
+
+fn fill(text: &str, vars: &[(&str, &str)]) -> String {
+    placeholder()
+        .replace_all(text, |caps: &regex::Captures| {
+            vars.iter()
+                .find(|(name, _)| *name == &caps[1])
+                .map(|(_, value)| value.to_string())
+                .unwrap_or_default()
+        })
+        .trim_end()
+        .to_string()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateInfo {
+    pub name: String,
+    pub variables: Vec<String>,
+    pub override_path: String,
+    pub overridden: bool,
+    pub builtin: String,
+}
+
+pub(crate) fn list() -> Vec<TemplateInfo> {
+    ALL.into_iter()
+        .map(|t| {
+            let path = t.override_path();
+            TemplateInfo {
+                name: t.name().to_string(),
+                variables: t.variables().iter().map(|v| v.to_string()).collect(),
+                overridden: path.as_ref().is_some_and(|p| p.exists()),
+                override_path: path.map(|p| p.display().to_string()).unwrap_or_default(),
+                builtin: t.builtin().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn override_file(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-templates-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("genre_summary.md");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn builtins_use_exactly_their_declared_variables() {
+        for template in ALL {
+            let used: BTreeSet<&str> = placeholder()
+                .captures_iter(template.builtin())
+                .filter_map(|c| c.get(1).map(|m| m.as_str()))
+                .collect();
+            let declared: BTreeSet<&str> = template.variables().iter().copied().collect();
+            assert_eq!(used, declared, "{}", template.name());
+            assert_eq!(Template::from_name(template.name()).unwrap().name(), template.name());
+        }
+        assert!(Template::from_name("nope").is_err());
+    }
+
+    #[test]
+    fn an_override_with_an_unknown_variable_falls_back_to_the_builtin() {
+        let path = override_file("unknown", "Streams: {{genres}} {{ budget }} {{secrets}}");
+        let (text, source, warnings) = load_from(Template::GenreSummary, Some(path.clone()));
+        assert_eq!(text, Template::GenreSummary.builtin());
+        assert_eq!(source, "built-in");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&path.display().to_string()), "{}", warnings[0]);
+        assert!(warnings[0].contains("unknown variable(s) budget, secrets (available: genres, language)"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn a_valid_override_replaces_the_builtin() {
+        let path = override_file("valid", "Summarize {{ genres }} in {{language}}.");
+        let (text, source, warnings) = load_from(Template::GenreSummary, Some(path.clone()));
+        assert_eq!(text, "Summarize {{ genres }} in {{language}}.");
+        assert_eq!(source, path.display().to_string());
+        assert!(warnings.is_empty());
+
+        let missing = path.with_file_name("control_advisor.md");
+        let (text, source, warnings) = load_from(Template::ControlAdvisor, Some(missing));
+        assert_eq!((text.as_str(), source.as_str()), (Template::ControlAdvisor.builtin(), "built-in"));
+        assert!(warnings.is_empty());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn variables_missing_from_vars_render_empty() {
+        let text = "Language: {{ language }}\nStreams: [{{genres}}]\n{{genres}}\n\n";
+        assert_eq!(fill(text, &[("genres", "a, b")]), "Language: \nStreams: [a, b]\na, b");
+        // A value that looks like a placeholder is not expanded again.
+        assert_eq!(fill("{{language}}", &[("language", "{{genres}}"), ("genres", "x")]), "{{genres}}");
+    }
+}
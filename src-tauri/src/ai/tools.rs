@@ -0,0 +1,112 @@
+// Tool calling on top of JSON output. Native function calling differs per
+// provider, so the model instead answers with `{"reply": ..., "tool_calls": [...]}`
+// in JSON mode; the caller runs the requested tools and sends their results
+// back as the next user message.
+
+use serde_json::{json, Value};
+
+use super::analyze::parse_json;
+
+#[derive(Clone, Copy)]
+pub(crate) struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// (argument, meaning); arguments are strings.
+    pub args: &'static [(&'static str, &'static str)],
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ToolCall {
+    pub name: String,
+    pub args: Value,
+}
+
+impl ToolCall {
+    /// A non-empty string argument.
+    pub(crate) fn arg(&self, name: &str) -> Option<&str> {
+        self.args.get(name).and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty())
+    }
+}
+
+/// One model turn: an answer, tool calls to run first, or both.
+pub(crate) struct ToolStep {
+    pub reply: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+pub(crate) fn step_schema(tools: &[ToolSpec]) -> Value {
+    let names: Vec<&str> = tools.iter().map(|t| t.name).collect();
+    json!({
+        "type": "object",
+        "properties": {
+            "reply": {"type": "string"},
+            "tool_calls": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string", "enum": names},
+                        "args": {"type": "object"}
+                    },
+                    "required": ["name"]
+                }
+            }
+        }
+    })
+}
+
+/// System prompt section describing the reply format and the tools.
+pub(crate) fn instructions(tools: &[ToolSpec]) -> String {
+    let list = tools
+        .iter()
+        .map(|t| {
+            let names: Vec<&str> = t.args.iter().map(|(name, _)| *name).collect();
+            let mut line = format!("- {}({}): {}", t.name, names.join(", "), t.description);
+            for (name, meaning) in t.args {
+                line.push_str(&format!("\n    {}: {}", name, meaning));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "## Tools\n\
+         Always reply with a single JSON object, no markdown:\n\
+         - {{\"tool_calls\": [{{\"name\": \"...\", \"args\": {{...}}}}]}} to look something up first; \
+         the results come back in the next message.\n\
+         - {{\"reply\": \"...\"}} to answer the user.\n\
+         Use tools when the answer depends on what the cells are doing right now; \
+         do not guess at screen contents, files or diffs.\n\n\
+         Available tools:\n{}",
+        list
+    )
+}
+
+/// Read a model turn. A reply that is not JSON is taken as the answer itself.
+pub(crate) fn parse_step(text: &str) -> ToolStep {
+    let Ok(value) = parse_json(text) else {
+        return ToolStep { reply: Some(text.trim().to_string()), tool_calls: Vec::new() };
+    };
+    let reply = value
+        .get("reply")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let tool_calls = value
+        .get("tool_calls")
+        .and_then(|v| v.as_array())
+        .map(|calls| {
+            calls
+                .iter()
+                .filter_map(|c| {
+                    Some(ToolCall {
+                        name: c.get("name")?.as_str()?.to_string(),
+                        args: c.get("args").cloned().unwrap_or_else(|| json!({})),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    ToolStep { reply, tool_calls }
+}
@@ -1,10 +1,10 @@
 pub(super) fn pull_text(resp_json: &serde_json::Value, path: &str) -> Result<String, String> {
-    let text = resp_json
-        .pointer(path)
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .trim()
-        .to_string();
+    // Structured results (e.g. tool call input) come back as JSON text.
+    let text = match resp_json.pointer(path) {
+        Some(serde_json::Value::String(s)) => s.trim().to_string(),
+        Some(v @ (serde_json::Value::Object(_) | serde_json::Value::Array(_))) => v.to_string(),
+        _ => String::new(),
+    };
     if text.is_empty() {
         return Err(extract_error(resp_json));
     }
@@ -31,24 +31,72 @@ pub(super) fn extract_json_object(text: &str) -> Option<String> {
     }
 }
 
-pub(super) fn strip_ansi(s: &str) -> String {
-    let mut result = String::new();
-    let mut chars = s.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c == '\x1b' {
-            if chars.peek() == Some(&'[') {
-                chars.next();
-                for nc in chars.by_ref() {
-                    if nc.is_ascii_alphabetic() {
-                        break;
-                    }
+pub(crate) fn strip_ansi(s: &str) -> String {
+    AnsiStripper::default().push(s)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum AnsiState {
+    #[default]
+    Text,
+    Escape,
+    /// CSI: runs to the first letter.
+    Csi,
+    /// OSC (e.g. window title): runs until BEL or ESC \
+    Osc,
+    OscEscape,
+}
+
+/// `strip_ansi` for a stream: an escape sequence cut off at the end of one
+/// chunk is still removed when the rest of it arrives in the next.
+#[derive(Debug, Default)]
+pub(crate) struct AnsiStripper {
+    state: AnsiState,
+}
+
+impl AnsiStripper {
+    pub(crate) fn push(&mut self, s: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        for c in s.chars() {
+            self.state = match (self.state, c) {
+                (AnsiState::Text, '\x1b') => AnsiState::Escape,
+                (AnsiState::Text, '\r') => AnsiState::Text,
+                (AnsiState::Text, c) => {
+                    result.push(c);
+                    AnsiState::Text
                 }
-            } else {
-                chars.next();
-            }
-        } else if c != '\r' {
-            result.push(c);
+                (AnsiState::Escape, '[') => AnsiState::Csi,
+                (AnsiState::Escape, ']') => AnsiState::Osc,
+                (AnsiState::Escape, _) => AnsiState::Text,
+                (AnsiState::Csi, c) if c.is_ascii_alphabetic() => AnsiState::Text,
+                (AnsiState::Csi, _) => AnsiState::Csi,
+                (AnsiState::Osc, '\x07') => AnsiState::Text,
+                (AnsiState::Osc, '\x1b') => AnsiState::OscEscape,
+                (AnsiState::Osc, _) => AnsiState::Osc,
+                (AnsiState::OscEscape, _) => AnsiState::Text,
+            };
         }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_removes_csi_osc_and_carriage_returns() {
+        let raw = "\x1b]0;title\x07\x1b[1;32mok\x1b[0m\r\n\x1b]2;t\x1b\\done";
+        assert_eq!(strip_ansi(raw), "ok\ndone");
+    }
+
+    #[test]
+    fn stripper_handles_sequences_split_across_chunks() {
+        let mut stripper = AnsiStripper::default();
+        let mut out = stripper.push("red: \x1b[3");
+        out += &stripper.push("1mtext\x1b");
+        out += &stripper.push("[0m and \x1b]0;ti");
+        out += &stripper.push("tle\x07end");
+        assert_eq!(out, "red: text and end");
     }
-    result
 }
@@ -0,0 +1,287 @@
+// Queries over the saved analysis history (see `storage::save_analysis`):
+// listing, diffing two analyses and per-cell summary timelines.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::storage::{AnalysisEntry, AnalysisIndexEntry};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisListItem {
+    pub id: String,
+    pub timestamp_ms: u64,
+    /// `timestamp_ms` in the local time zone, for display.
+    pub local_time: String,
+    pub cell_count: usize,
+    pub confidence: Option<String>,
+    pub priority_cell: Option<String>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryChange {
+    pub cell_id: String,
+    pub theme: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisDiff {
+    pub from_id: String,
+    pub to_id: String,
+    pub summaries: Vec<SummaryChange>,
+    pub ideas_added: Vec<String>,
+    pub ideas_removed: Vec<String>,
+    /// Changed scalar flow fields (stuck, next, priority cell, ...).
+    pub flow: Vec<FieldChange>,
+    pub blocked_added: Vec<String>,
+    pub blocked_removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelinePoint {
+    pub analysis_id: String,
+    pub timestamp_ms: u64,
+    pub local_time: String,
+    pub theme: String,
+    pub summary: String,
+    /// False when the summary is the same as in the previous point.
+    pub changed: bool,
+}
+
+/// Newest first.
+pub(crate) fn list(index: &[AnalysisIndexEntry], limit: usize) -> Vec<AnalysisListItem> {
+    index
+        .iter()
+        .rev()
+        .take(limit)
+        .map(|e| AnalysisListItem {
+            id: e.id.clone(),
+            timestamp_ms: e.timestamp_ms,
+            local_time: crate::time::format_local(e.timestamp_ms),
+            cell_count: e.cell_count,
+            confidence: e.confidence.clone(),
+            priority_cell: e.priority_cell.clone(),
+            next: e.next.clone(),
+        })
+        .collect()
+}
+
+fn set_diff(before: &[String], after: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = after.iter().filter(|x| !before.contains(x)).cloned().collect();
+    let removed = before.iter().filter(|x| !after.contains(x)).cloned().collect();
+    (added, removed)
+}
+
+/// Scalar flow fields worth comparing, as (name, value) pairs.
+fn flow_fields(entry: &AnalysisEntry) -> Vec<(&'static str, String)> {
+    let Some(f) = &entry.flow else { return Vec::new() };
+    vec![
+        ("stimuli_to_will", f.stimuli_to_will.clone()),
+        ("will_to_supply", f.will_to_supply.clone()),
+        ("stuck", f.stuck.clone()),
+        ("next", f.next.clone()),
+        ("priority_cell", f.priority_cell.clone().unwrap_or_default()),
+        ("confidence", f.confidence.clone().unwrap_or_default()),
+    ]
+}
+
+pub(crate) fn diff(from: &AnalysisEntry, to: &AnalysisEntry) -> AnalysisDiff {
+    let cell_ids: BTreeSet<&String> = from.summaries.keys().chain(to.summaries.keys()).collect();
+    let summaries = cell_ids
+        .into_iter()
+        .filter_map(|id| {
+            let before = from.summaries.get(id).cloned();
+            let after = to.summaries.get(id).cloned();
+            if before == after {
+                return None;
+            }
+            let theme = to.themes.get(id).or_else(|| from.themes.get(id)).cloned().unwrap_or_default();
+            Some(SummaryChange { cell_id: id.clone(), theme, before, after })
+        })
+        .collect();
+
+    let (ideas_added, ideas_removed) = set_diff(&from.ideas, &to.ideas);
+
+    let before_fields = flow_fields(from);
+    let after_fields = flow_fields(to);
+    let flow = after_fields
+        .iter()
+        .map(|(name, after)| {
+            let before = before_fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default();
+            (name, before, after)
+        })
+        .filter(|(_, before, after)| before != *after)
+        .map(|(name, before, after)| FieldChange { field: name.to_string(), before, after: after.clone() })
+        .collect();
+
+    let blocked = |e: &AnalysisEntry| e.flow.as_ref().and_then(|f| f.blocked_cells.clone()).unwrap_or_default();
+    let (blocked_added, blocked_removed) = set_diff(&blocked(from), &blocked(to));
+
+    AnalysisDiff {
+        from_id: from.id.clone(),
+        to_id: to.id.clone(),
+        summaries,
+        ideas_added,
+        ideas_removed,
+        flow,
+        blocked_added,
+        blocked_removed,
+    }
+}
+
+/// How a cell's summary evolved, oldest first.
+pub(crate) fn cell_timeline(history: &[AnalysisEntry], cell_id: &str) -> Vec<TimelinePoint> {
+    let mut points: Vec<TimelinePoint> = Vec::new();
+    for entry in history {
+        let Some(summary) = entry.summaries.get(cell_id) else { continue };
+        let changed = points.last().map_or(true, |p| &p.summary != summary);
+        points.push(TimelinePoint {
+            analysis_id: entry.id.clone(),
+            timestamp_ms: entry.timestamp_ms,
+            local_time: crate::time::format_local(entry.timestamp_ms),
+            theme: entry.themes.get(cell_id).cloned().unwrap_or_default(),
+            summary: summary.clone(),
+            changed,
+        });
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(id: &str, ms: u64, summaries: serde_json::Value, ideas: &[&str], flow: serde_json::Value) -> AnalysisEntry {
+        serde_json::from_value(json!({
+            "id": id,
+            "timestamp_ms": ms,
+            "summaries": summaries,
+            "themes": {"cell-0": "landing page", "cell-1": "api"},
+            "ideas": ideas,
+            "flow": flow,
+        }))
+        .unwrap()
+    }
+
+    fn flow(next: &str, priority: &str, blocked: &[&str]) -> serde_json::Value {
+        json!({
+            "stimuli_to_will": "steady",
+            "will_to_supply": "steady",
+            "stuck": "",
+            "next": next,
+            "blocked_cells": blocked,
+            "priority_cell": priority,
+            "confidence": "high",
+            "connections": null,
+            "human_questions": null,
+            "changes_since_last": null,
+        })
+    }
+
+    #[test]
+    fn list_is_newest_first_and_limited() {
+        let index: Vec<AnalysisIndexEntry> = (1..=4)
+            .map(|i| AnalysisIndexEntry {
+                id: format!("analysis-{}", i),
+                timestamp_ms: i * 1000,
+                cell_count: i as usize,
+                confidence: None,
+                priority_cell: None,
+                next: Some(format!("step {}", i)),
+            })
+            .collect();
+        let items = list(&index, 2);
+        assert_eq!(items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(), vec!["analysis-4", "analysis-3"]);
+        assert_eq!(items[0].next.as_deref(), Some("step 4"));
+        assert_eq!(items[0].local_time, crate::time::format_local(4000));
+        assert_eq!(list(&index, 50).len(), 4);
+    }
+
+    #[test]
+    fn diff_reports_what_changed_between_two_analyses() {
+        let from = entry(
+            "analysis-1",
+            1000,
+            json!({"cell-0": "drafting hero", "cell-1": "auth done", "cell-2": "idle"}),
+            &["add pricing", "try dark mode"],
+            flow("ship hero", "cell-0", &["cell-2"]),
+        );
+        let to = entry(
+            "analysis-2",
+            2000,
+            json!({"cell-0": "hero shipped", "cell-1": "auth done", "cell-3": "new work"}),
+            &["try dark mode", "write docs"],
+            flow("write docs", "cell-1", &["cell-3"]),
+        );
+        let d = diff(&from, &to);
+        assert_eq!((d.from_id.as_str(), d.to_id.as_str()), ("analysis-1", "analysis-2"));
+
+        let changes: Vec<(&str, &str, Option<&str>, Option<&str>)> = d
+            .summaries
+            .iter()
+            .map(|c| (c.cell_id.as_str(), c.theme.as_str(), c.before.as_deref(), c.after.as_deref()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("cell-0", "landing page", Some("drafting hero"), Some("hero shipped")),
+                ("cell-2", "", Some("idle"), None),
+                ("cell-3", "", None, Some("new work")),
+            ]
+        );
+        assert_eq!(d.ideas_added, vec!["write docs"]);
+        assert_eq!(d.ideas_removed, vec!["add pricing"]);
+        let fields: Vec<(&str, &str, &str)> =
+            d.flow.iter().map(|f| (f.field.as_str(), f.before.as_str(), f.after.as_str())).collect();
+        assert_eq!(fields, vec![("next", "ship hero", "write docs"), ("priority_cell", "cell-0", "cell-1")]);
+        assert_eq!(d.blocked_added, vec!["cell-3"]);
+        assert_eq!(d.blocked_removed, vec!["cell-2"]);
+    }
+
+    #[test]
+    fn diff_against_an_analysis_without_flow() {
+        let from = entry("analysis-1", 1000, json!({}), &[], json!(null));
+        let to = entry("analysis-2", 2000, json!({}), &[], flow("go", "cell-0", &[]));
+        let d = diff(&from, &to);
+        assert!(d.flow.iter().any(|f| f.field == "next" && f.before.is_empty() && f.after == "go"));
+        assert!(diff(&to, &from).flow.is_empty());
+    }
+
+    #[test]
+    fn timeline_follows_one_cell_and_marks_changes() {
+        let history = [
+            entry("analysis-1", 1000, json!({"cell-0": "drafting"}), &[], json!(null)),
+            entry("analysis-2", 2000, json!({"cell-1": "other cell"}), &[], json!(null)),
+            entry("analysis-3", 3000, json!({"cell-0": "drafting"}), &[], json!(null)),
+            entry("analysis-4", 4000, json!({"cell-0": "shipped"}), &[], json!(null)),
+        ];
+        let points = cell_timeline(&history, "cell-0");
+        let seen: Vec<(&str, &str, bool)> =
+            points.iter().map(|p| (p.analysis_id.as_str(), p.summary.as_str(), p.changed)).collect();
+        assert_eq!(
+            seen,
+            vec![("analysis-1", "drafting", true), ("analysis-3", "drafting", false), ("analysis-4", "shipped", true)]
+        );
+        assert!(points.iter().all(|p| p.theme == "landing page"));
+        assert!(cell_timeline(&history, "cell-9").is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+// Bookkeeping for clients attached to a cell's PTY: the GUI plus any external
+// terminals connected through the control API (`chaos-grid attach`).
+//
+// Input: any controlling client may type, but whoever typed last holds the
+// input for INPUT_LEASE; other external clients are rejected until it lapses.
+// The GUI is never blocked and always takes the lease (local user wins).
+//
+// Size: like tmux, the PTY uses the smallest cols/rows of all attached clients.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub(crate) const GUI_CLIENT: &str = "gui";
+const INPUT_LEASE: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AttachMode {
+    Control,
+    View,
+}
+
+impl AttachMode {
+    pub(crate) fn parse(mode: Option<&str>) -> Result<Self, String> {
+        match mode.unwrap_or("control") {
+            "control" => Ok(AttachMode::Control),
+            "view" => Ok(AttachMode::View),
+            m => Err(format!("unknown attach mode: {}", m)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ClientInfo {
+    mode: AttachMode,
+    cols: u16,
+    rows: u16,
+}
+
+#[derive(Default)]
+struct CellClients {
+    clients: HashMap<String, ClientInfo>,
+    input_lease: Option<(String, Instant)>,
+}
+
+impl CellClients {
+    fn effective_size(&self) -> Option<(u16, u16)> {
+        let cols = self.clients.values().map(|c| c.cols).filter(|c| *c > 0).min()?;
+        let rows = self.clients.values().map(|c| c.rows).filter(|r| *r > 0).min()?;
+        Some((cols, rows))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AttachRegistry(Mutex<HashMap<String, CellClients>>);
+
+impl AttachRegistry {
+    /// Register (or update) a client on a cell. Returns the negotiated PTY size.
+    pub(crate) fn attach(
+        &self,
+        cell_id: &str,
+        client_id: &str,
+        mode: AttachMode,
+        cols: u16,
+        rows: u16,
+    ) -> Option<(u16, u16)> {
+        let mut map = self.0.lock().unwrap();
+        let cell = map.entry(cell_id.to_string()).or_default();
+        cell.clients.insert(client_id.to_string(), ClientInfo { mode, cols, rows });
+        cell.effective_size()
+    }
+
+    /// Remove a client from a cell. Returns the size the remaining clients negotiate.
+    pub(crate) fn detach(&self, cell_id: &str, client_id: &str) -> Option<(u16, u16)> {
+        let mut map = self.0.lock().unwrap();
+        let cell = map.get_mut(cell_id)?;
+        cell.clients.remove(client_id);
+        if matches!(&cell.input_lease, Some((holder, _)) if holder == client_id) {
+            cell.input_lease = None;
+        }
+        let size = cell.effective_size();
+        if cell.clients.is_empty() {
+            map.remove(cell_id);
+        }
+        size
+    }
+
+    /// Record a client's terminal size. Returns the newly negotiated PTY size.
+    pub(crate) fn resize(&self, cell_id: &str, client_id: &str, cols: u16, rows: u16) -> Option<(u16, u16)> {
+        let mut map = self.0.lock().unwrap();
+        let cell = map.entry(cell_id.to_string()).or_default();
+        let client = cell
+            .clients
+            .entry(client_id.to_string())
+            .or_insert(ClientInfo { mode: AttachMode::Control, cols, rows });
+        client.cols = cols;
+        client.rows = rows;
+        cell.effective_size()
+    }
+
+    /// Claim the input lease for `client_id`, or explain who currently holds it.
+    pub(crate) fn acquire_input(&self, cell_id: &str, client_id: &str) -> Result<(), String> {
+        let mut map = self.0.lock().unwrap();
+        let cell = map.entry(cell_id.to_string()).or_default();
+        if client_id != GUI_CLIENT {
+            match cell.clients.get(client_id) {
+                Some(c) if c.mode == AttachMode::View => {
+                    return Err(format!("attached to {} in view mode", cell_id));
+                }
+                _ => {}
+            }
+            if let Some((holder, at)) = &cell.input_lease {
+                if holder != client_id && at.elapsed() < INPUT_LEASE {
+                    return Err(format!("input for {} is held by {}", cell_id, holder));
+                }
+            }
+        }
+        cell.input_lease = Some((client_id.to_string(), Instant::now()));
+        Ok(())
+    }
+
+    /// True if any client other than the GUI is attached to any cell.
+    pub(crate) fn has_external_clients(&self) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .values()
+            .any(|cell| cell.clients.keys().any(|id| id != GUI_CLIENT))
+    }
+}
@@ -0,0 +1,625 @@
+// Persistent CONTROL chat threads, stored in `<data>/chat-threads.json`. While
+// answering, the advisor can call read-only grid tools (cell screens, files, git
+// diffs, the latest analysis). Threads that allow actions also let it propose
+// prompts and tasks for cells; those wait in the thread until the user confirms.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+use std::sync::Mutex;
+use tauri::Manager;
+
+use crate::actions::{ActionContext, ActionLogEntry};
+use crate::ai::{AiTask, GenreInput, JsonOutput, ToolCall, ToolSpec};
+use crate::storage::AiConfig;
+use crate::{CellStateMap, FlowAction, FlowActionKind};
+
+const THREADS_FILE: &str = "chat-threads.json";
+const MAX_TOOL_ROUNDS: usize = 4;
+const MAX_CALLS_PER_ROUND: usize = 6;
+/// Earlier messages sent with each turn, newest first, within this budget.
+const HISTORY_TOKENS: usize = 6000;
+const SCREEN_TOKENS: usize = 1500;
+const MAX_TOOL_CHARS: usize = 6000;
+const MAX_LISTED_FILES: usize = 60;
+const SEARCH_HITS: usize = 5;
+const MAX_REPLY_TOKENS: u32 = 800;
+
+/// Serializes read-modify-write of the threads file.
+static STORE: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ThreadSettings {
+    pub language: Option<String>,
+    pub output_dir: Option<String>,
+    pub cols: Option<u32>,
+    /// Work streams described in the system prompt.
+    pub genres: Vec<GenreInput>,
+    /// Let the advisor propose prompts and tasks for cells (each needs confirmation).
+    pub allow_actions: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadMessage {
+    /// "user", "assistant", "tool" (a tool call and its result) or "action"
+    /// (the outcome of a confirmed or rejected action).
+    pub role: String,
+    pub content: String,
+    pub timestamp_ms: u64,
+    /// Tool name and arguments, for "tool" messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// "provider/model", for "assistant" messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answered_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAction {
+    pub id: String,
+    pub action: FlowAction,
+    /// "pending", "applied", "failed" or "rejected".
+    pub status: String,
+    pub created_ms: u64,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatThread {
+    pub id: String,
+    pub title: String,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+    #[serde(default)]
+    pub settings: ThreadSettings,
+    #[serde(default)]
+    pub messages: Vec<ThreadMessage>,
+    #[serde(default)]
+    pub actions: Vec<PendingAction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadInfo {
+    pub id: String,
+    pub title: String,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+    pub messages: usize,
+    pub pending_actions: usize,
+}
+
+/// What one `send` added to the thread.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTurn {
+    pub messages: Vec<ThreadMessage>,
+    pub actions: Vec<PendingAction>,
+    /// Errors from providers tried before the one that answered.
+    pub fallback_errors: Vec<String>,
+}
+
+fn message(role: &str, content: String) -> ThreadMessage {
+    ThreadMessage {
+        role: role.to_string(),
+        content,
+        timestamp_ms: crate::now_millis(),
+        tool: None,
+        answered_by: None,
+    }
+}
+
+// ─── Store ───────────────────────────────────────────────────────────────────
+
+fn load_all(app: &tauri::AppHandle) -> Vec<ChatThread> {
+    crate::storage::load_json(app, THREADS_FILE)
+}
+
+/// Apply `f` to thread `id` and save.
+fn update<R>(
+    app: &tauri::AppHandle,
+    id: &str,
+    f: impl FnOnce(&mut ChatThread) -> Result<R, String>,
+) -> Result<R, String> {
+    let _guard = STORE.lock().unwrap();
+    let mut threads = load_all(app);
+    let thread = threads
+        .iter_mut()
+        .find(|t| t.id == id)
+        .ok_or_else(|| format!("unknown chat thread {}", id))?;
+    let result = f(thread)?;
+    thread.updated_ms = crate::now_millis();
+    crate::storage::save_json(app, THREADS_FILE, &threads)?;
+    Ok(result)
+}
+
+pub(crate) fn create(app: &tauri::AppHandle, title: Option<String>, settings: ThreadSettings) -> Result<ChatThread, String> {
+    let _guard = STORE.lock().unwrap();
+    let mut threads = load_all(app);
+    let now = crate::now_millis();
+    let mut id = format!("thread-{}", now);
+    while threads.iter().any(|t| t.id == id) {
+        id.push('x');
+    }
+    let thread = ChatThread {
+        id,
+        title: title.unwrap_or_default(),
+        created_ms: now,
+        updated_ms: now,
+        settings,
+        messages: Vec::new(),
+        actions: Vec::new(),
+    };
+    threads.push(thread.clone());
+    crate::storage::save_json(app, THREADS_FILE, &threads)?;
+    Ok(thread)
+}
+
+/// Most recently updated first.
+pub(crate) fn list(app: &tauri::AppHandle) -> Vec<ThreadInfo> {
+    let mut threads: Vec<ThreadInfo> = load_all(app)
+        .into_iter()
+        .map(|t| ThreadInfo {
+            pending_actions: t.actions.iter().filter(|a| a.status == "pending").count(),
+            messages: t.messages.len(),
+            id: t.id,
+            title: t.title,
+            created_ms: t.created_ms,
+            updated_ms: t.updated_ms,
+        })
+        .collect();
+    threads.sort_by_key(|t| std::cmp::Reverse(t.updated_ms));
+    threads
+}
+
+pub(crate) fn get(app: &tauri::AppHandle, id: &str) -> Result<ChatThread, String> {
+    load_all(app)
+        .into_iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| format!("unknown chat thread {}", id))
+}
+
+pub(crate) fn delete(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let _guard = STORE.lock().unwrap();
+    let mut threads = load_all(app);
+    let before = threads.len();
+    threads.retain(|t| t.id != id);
+    if threads.len() == before {
+        return Err(format!("unknown chat thread {}", id));
+    }
+    crate::storage::save_json(app, THREADS_FILE, &threads)
+}
+
+pub(crate) fn configure(
+    app: &tauri::AppHandle,
+    id: &str,
+    title: Option<String>,
+    settings: Option<ThreadSettings>,
+) -> Result<ChatThread, String> {
+    update(app, id, |thread| {
+        if let Some(title) = title {
+            thread.title = title;
+        }
+        if let Some(settings) = settings {
+            thread.settings = settings;
+        }
+        Ok(thread.clone())
+    })
+}
+
+// ─── Tools ───────────────────────────────────────────────────────────────────
+
+const READ_TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        name: "list_cells",
+        description: "Every cell with its theme, status, last activity and summary.",
+        args: &[],
+    },
+    ToolSpec {
+        name: "read_cell_screen",
+        description: "The recent terminal output of one cell.",
+        args: &[("cell_id", "cell id, e.g. \"cell-3\"")],
+    },
+    ToolSpec {
+        name: "list_files",
+        description: "Files in a cell's work directory or the output directory, newest first.",
+        args: &[
+            ("cell_id", "optional; list this cell's work directory"),
+            ("path", "optional; directory relative to the output directory"),
+        ],
+    },
+    ToolSpec {
+        name: "search_files",
+        description: "The passages of files most relevant to a query (keyword search), in a cell's work directory or under the output directory.",
+        args: &[
+            ("query", "words to look for"),
+            ("cell_id", "optional; search this cell's work directory"),
+            ("path", "optional; directory relative to the output directory"),
+        ],
+    },
+    ToolSpec {
+        name: "git_diff",
+        description: "Uncommitted changes (status and diff) in a cell's work directory or the output directory.",
+        args: &[
+            ("cell_id", "optional; use this cell's work directory"),
+            ("path", "optional; directory relative to the output directory"),
+        ],
+    },
+    ToolSpec {
+        name: "latest_analysis",
+        description: "The most recent grid analysis: per-cell summaries, ideas and flow.",
+        args: &[],
+    },
+];
+
+const ACTION_TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        name: "send_prompt",
+        description: "Propose typing a prompt into a cell's agent. Runs only after the user confirms it.",
+        args: &[("cell_id", "cell id"), ("prompt", "the prompt"), ("reason", "why, shown to the user")],
+    },
+    ToolSpec {
+        name: "enqueue_task",
+        description: "Propose adding a task to a cell's queue. Runs only after the user confirms it.",
+        args: &[("cell_id", "cell id"), ("task", "the task"), ("reason", "why, shown to the user")],
+    },
+];
+
+fn tools_for(settings: &ThreadSettings) -> Vec<ToolSpec> {
+    let mut tools = READ_TOOLS.to_vec();
+    if settings.allow_actions {
+        tools.extend_from_slice(ACTION_TOOLS);
+    }
+    tools
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n… ({} more bytes)", &text[..end], text.len() - end)
+}
+
+fn find_cell(app: &tauri::AppHandle, cell_id: &str) -> Result<crate::CellState, String> {
+    app.state::<CellStateMap>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(cell_id)
+        .cloned()
+        .ok_or_else(|| format!("unknown cell {}", cell_id))
+}
+
+/// The directory a `cell_id` or `path` argument points at; `path` must stay inside the
+/// output dir, and the result inside a workspace root (see `sandbox`).
+fn resolve_dir(app: &tauri::AppHandle, settings: &ThreadSettings, call: &ToolCall) -> Result<String, String> {
+    let dir = unchecked_dir(app, settings, call)?;
+    Ok(crate::sandbox::check(&dir)?.to_string_lossy().to_string())
+}
+
+fn unchecked_dir(app: &tauri::AppHandle, settings: &ThreadSettings, call: &ToolCall) -> Result<String, String> {
+    let output_dir = settings.output_dir.as_deref().ok_or("this thread has no output directory set")?;
+    let base = crate::files::expand_tilde(output_dir);
+    if let Some(cell_id) = call.arg("cell_id") {
+        let cell = find_cell(app, cell_id)?;
+        return crate::ai::cell_work_dir(&cell, &base, settings.cols.unwrap_or(3) as usize);
+    }
+    match call.arg("path") {
+        None => Ok(base),
+        Some(rel) => {
+            if !Path::new(rel).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+                return Err(format!("path must be relative to the output directory: {}", rel));
+            }
+            Ok(Path::new(&base).join(rel).to_string_lossy().to_string())
+        }
+    }
+}
+
+fn list_cells(app: &tauri::AppHandle) -> Result<String, String> {
+    let mut cells: Vec<crate::CellState> =
+        app.state::<CellStateMap>().0.lock().map_err(|e| e.to_string())?.values().cloned().collect();
+    cells.sort_by(|a, b| a.id.cmp(&b.id));
+    let now = crate::now_millis();
+    let lines: Vec<String> = cells
+        .iter()
+        .map(|c| {
+            let theme = if c.theme.is_empty() { "(no theme)" } else { c.theme.as_str() };
+            let mut line = format!(
+                "[{}] {} — {}, last output {}",
+                c.id,
+                theme,
+                c.status,
+                crate::time::format_relative(c.updated_at, now)
+            );
+            if let Some(summary) = &c.summary {
+                line.push_str(&format!("\n    {}", summary.text.replace('\n', " ")));
+            }
+            line
+        })
+        .collect();
+    if lines.is_empty() {
+        return Ok("(no cells)".to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+fn read_cell_screen(app: &tauri::AppHandle, config: &AiConfig, call: &ToolCall) -> Result<String, String> {
+    let cell_id = call.arg("cell_id").ok_or("cell_id is required")?;
+    let cell = find_cell(app, cell_id)?;
+    let screen = crate::ai::clean_screen(&cell.last_output);
+    if screen.is_empty() {
+        return Ok(format!("({} has no output)", cell_id));
+    }
+    Ok(crate::ai::fit_tail(config, &screen, SCREEN_TOKENS))
+}
+
+fn list_files(dir: &str) -> String {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return format!("({} does not exist)", dir);
+    }
+    let mut files = crate::files::walk_dir(root);
+    if files.is_empty() {
+        return format!("({} has no files)", dir);
+    }
+    files.sort_by_key(|f| std::cmp::Reverse(f.2));
+    let now = crate::now_millis();
+    let mut lines: Vec<String> = files
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|(rel, _, modified)| format!("{}  ({})", rel, crate::time::format_relative(*modified, now)))
+        .collect();
+    if files.len() > MAX_LISTED_FILES {
+        lines.push(format!("… and {} more", files.len() - MAX_LISTED_FILES));
+    }
+    format!("{}:\n{}", dir, lines.join("\n"))
+}
+
+fn search_files(dir: &str, call: &ToolCall) -> Result<String, String> {
+    let query = call.arg("query").ok_or("query is required")?;
+    let hits = crate::retrieval::search(Path::new(dir), query, SEARCH_HITS);
+    if hits.is_empty() {
+        return Ok(format!("(nothing in {} matches)", dir));
+    }
+    Ok(crate::retrieval::format_hits(&hits, 1200))
+}
+
+fn git_diff(dir: &str) -> String {
+    if !Path::new(dir).is_dir() {
+        return format!("({} does not exist)", dir);
+    }
+    let Some(repo) = crate::git::Repo::in_workspace(Path::new(dir)) else {
+        return format!("({} is not a git repo)", dir);
+    };
+    let status = match repo.status() {
+        Ok(entries) => entries.iter().map(|e| e.short()).collect::<Vec<_>>().join("\n"),
+        Err(e) => return format!("(git error: {})", e),
+    };
+    let diff = repo.uncommitted_patch().unwrap_or_else(|e| format!("(git error: {})", e));
+    let diff = diff.trim_end();
+    if status.is_empty() && diff.is_empty() {
+        return "(no changes)".to_string();
+    }
+    format!("Status:\n{}\n\nDiff:\n{}", status, diff)
+}
+
+fn latest_analysis(app: &tauri::AppHandle) -> String {
+    let history = crate::storage::load_recent_analyses(app, 1);
+    let Some(entry) = history.last() else { return "(no analysis yet)".to_string() };
+    let mut out = format!(
+        "Analysis {} ({})",
+        entry.id,
+        crate::time::format_relative(entry.timestamp_ms, crate::now_millis())
+    );
+    let mut ids: Vec<&String> = entry.summaries.keys().collect();
+    ids.sort();
+    for id in ids {
+        let theme = entry.themes.get(id).map(String::as_str).unwrap_or("");
+        out.push_str(&format!("\n[{}] {}: {}", id, theme, entry.summaries[id]));
+    }
+    if !entry.ideas.is_empty() {
+        out.push_str(&format!("\nIdeas:\n- {}", entry.ideas.join("\n- ")));
+    }
+    if let Some(flow) = &entry.flow {
+        out.push_str(&format!(
+            "\nStimuli → Will: {}\nWill → Supply: {}\nStuck: {}\nNext: {}",
+            flow.stimuli_to_will, flow.will_to_supply, flow.stuck, flow.next
+        ));
+        if let Some(priority) = &flow.priority_cell {
+            out.push_str(&format!("\nPriority cell: {}", priority));
+        }
+    }
+    out
+}
+
+fn propose(app: &tauri::AppHandle, call: &ToolCall, id: String) -> Result<PendingAction, String> {
+    let cell_id = call.arg("cell_id").ok_or("cell_id is required")?.to_string();
+    find_cell(app, &cell_id)?;
+    let kind = match call.name.as_str() {
+        "send_prompt" => FlowActionKind::SendPrompt {
+            cell_id,
+            prompt: call.arg("prompt").ok_or("prompt is required")?.to_string(),
+        },
+        _ => FlowActionKind::EnqueueTask {
+            cell_id,
+            task: call.arg("task").ok_or("task is required")?.to_string(),
+        },
+    };
+    Ok(PendingAction {
+        id,
+        action: FlowAction { kind, reason: call.arg("reason").unwrap_or("").to_string() },
+        status: "pending".to_string(),
+        created_ms: crate::now_millis(),
+        detail: None,
+    })
+}
+
+/// Run one tool call; errors become the tool's result so the model can react to them.
+fn run_tool(
+    app: &tauri::AppHandle,
+    config: &AiConfig,
+    thread: &ChatThread,
+    call: &ToolCall,
+    proposed: &mut Vec<PendingAction>,
+) -> String {
+    let settings = &thread.settings;
+    let result = match call.name.as_str() {
+        "list_cells" => list_cells(app),
+        "read_cell_screen" => read_cell_screen(app, config, call),
+        "list_files" => resolve_dir(app, settings, call).map(|dir| list_files(&dir)),
+        "search_files" => resolve_dir(app, settings, call).and_then(|dir| search_files(&dir, call)),
+        "git_diff" => resolve_dir(app, settings, call).map(|dir| git_diff(&dir)),
+        "latest_analysis" => Ok(latest_analysis(app)),
+        "send_prompt" | "enqueue_task" if settings.allow_actions => {
+            let id = format!("a{}", thread.actions.len() + proposed.len() + 1);
+            propose(app, call, id).map(|action| {
+                let text = format!("Proposed as action {}; it runs only once the user confirms it.", action.id);
+                proposed.push(action);
+                text
+            })
+        }
+        other => Err(format!("unknown tool {}", other)),
+    };
+    match result {
+        Ok(text) => truncate(&text, MAX_TOOL_CHARS),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+// ─── Conversation ────────────────────────────────────────────────────────────
+
+/// Earlier user/assistant messages and action outcomes that fit `HISTORY_TOKENS`.
+/// Tool results from earlier turns are left out; the replies that used them remain.
+fn history(config: &AiConfig, thread: &ChatThread) -> Vec<(String, String)> {
+    let mut used = 0;
+    let mut out = Vec::new();
+    for m in thread.messages.iter().rev() {
+        let (role, content) = match m.role.as_str() {
+            "user" => ("user", m.content.clone()),
+            "assistant" => ("assistant", m.content.clone()),
+            "action" => ("user", format!("[Action update] {}", m.content)),
+            _ => continue,
+        };
+        used += crate::ai::estimate_tokens(config, &content);
+        if used > HISTORY_TOKENS {
+            break;
+        }
+        out.push((role.to_string(), content));
+    }
+    out.reverse();
+    out
+}
+
+/// Answer `content` in thread `id`, letting the model call tools first.
+pub(crate) async fn send(app: &tauri::AppHandle, id: &str, content: String) -> Result<ChatTurn, String> {
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err("empty message".to_string());
+    }
+    let thread = get(app, id)?;
+    let (config, max_tokens) =
+        AiTask::Chat.apply(&app.state::<Mutex<AiConfig>>().lock().unwrap(), MAX_REPLY_TOKENS);
+    let language = thread.settings.language.as_deref().unwrap_or("English");
+    let tools = tools_for(&thread.settings);
+    let schema = crate::ai::step_schema(&tools);
+    let json_mode = JsonOutput { name: "respond", schema: &schema };
+    let advisor = crate::ai::control_advisor_prompt(&thread.settings.genres, language, Some(&content));
+    let system = format!("{}\n\n{}", advisor.text, crate::ai::tool_instructions(&tools));
+
+    let mut messages = history(&config, &thread);
+    messages.push(("user".to_string(), content.clone()));
+    let mut added = vec![message("user", content)];
+    let mut proposed = Vec::new();
+    let mut fallback_errors = Vec::new();
+
+    for round in 0..MAX_TOOL_ROUNDS {
+        let reply = crate::ai::call_ai_reply(AiTask::Chat, &config, Some(&system), &messages, max_tokens, Some(&json_mode)).await?;
+        fallback_errors.extend(reply.fallback_errors.iter().cloned());
+        let step = crate::ai::parse_step(&reply.text);
+        let last_round = round + 1 == MAX_TOOL_ROUNDS;
+        if step.tool_calls.is_empty() || last_round {
+            let text = step.reply.unwrap_or_else(|| "(no answer)".to_string());
+            added.push(ThreadMessage { answered_by: Some(reply.answered_by()), ..message("assistant", text) });
+            break;
+        }
+
+        let mut results = Vec::new();
+        for call in step.tool_calls.iter().take(MAX_CALLS_PER_ROUND) {
+            let result = run_tool(app, &config, &thread, call, &mut proposed);
+            let label = format!("{}({})", call.name, call.args);
+            results.push(format!("### {}\n{}", label, result));
+            added.push(ThreadMessage { tool: Some(label), ..message("tool", result) });
+        }
+        let mut note = format!("Tool results:\n\n{}", results.join("\n\n"));
+        if round + 2 == MAX_TOOL_ROUNDS {
+            note.push_str("\n\nNo more tool calls are available: answer now with \"reply\".");
+        }
+        messages.push(("assistant".to_string(), reply.text));
+        messages.push(("user".to_string(), note));
+    }
+
+    update(app, id, |thread| {
+        if thread.title.is_empty() {
+            thread.title = added[0].content.chars().take(60).collect();
+        }
+        thread.messages.extend(added.iter().cloned());
+        thread.actions.extend(proposed.iter().cloned());
+        Ok(())
+    })?;
+    Ok(ChatTurn { messages: added, actions: proposed, fallback_errors })
+}
+
+/// Run or reject a proposed action, recording the outcome in the thread.
+pub(crate) async fn decide(
+    app: &tauri::AppHandle,
+    id: &str,
+    action_id: &str,
+    approve: bool,
+) -> Result<PendingAction, String> {
+    let (action, ctx) = update(app, id, |thread| {
+        let ctx = ActionContext {
+            output_dir: thread.settings.output_dir.clone(),
+            cols: thread.settings.cols,
+            tool_cmd: None,
+        };
+        let pending = thread
+            .actions
+            .iter_mut()
+            .find(|a| a.id == action_id)
+            .ok_or_else(|| format!("unknown action {}", action_id))?;
+        if pending.status != "pending" {
+            return Err(format!("action {} is already {}", action_id, pending.status));
+        }
+        // Claim it before running so a second confirmation cannot run it twice.
+        pending.status = if approve { "applied" } else { "rejected" }.to_string();
+        Ok((pending.action.clone(), ctx))
+    })?;
+
+    let (status, detail) = if approve {
+        match crate::actions::apply(app, action, &ctx, "chat").await {
+            Ok(ActionLogEntry { detail, .. }) => ("applied", detail),
+            Err(e) => ("failed", e),
+        }
+    } else {
+        ("rejected", "rejected by the user".to_string())
+    };
+    update(app, id, |thread| {
+        thread.messages.push(message("action", format!("{}: {}", action_id, detail)));
+        let pending = thread
+            .actions
+            .iter_mut()
+            .find(|a| a.id == action_id)
+            .ok_or_else(|| format!("unknown action {}", action_id))?;
+        pending.status = status.to_string();
+        pending.detail = Some(detail.clone());
+        Ok(pending.clone())
+    })
+}
@@ -0,0 +1,234 @@
+// Command-line entry points that run without opening the app window.
+//
+//   chaos-grid attach <cell-id> [--view]
+//     Attach the current terminal to a running cell through the control API.
+//     Press Ctrl-] to detach; the cell keeps running.
+//
+//   chaos-grid daemon
+//     Run the PTY daemon in the foreground (normally started by the app).
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DETACH_KEY: u8 = 0x1d; // Ctrl-]
+const RESIZE_POLL: Duration = Duration::from_millis(500);
+
+/// Run a CLI subcommand if one was given. Returns the exit code, or None to start the GUI.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(|s| s.as_str()) {
+        Some("attach") => {
+            let Some(cell_id) = args.get(1) else {
+                eprintln!("usage: chaos-grid attach <cell-id> [--view]");
+                return Some(2);
+            };
+            let view = args.iter().any(|a| a == "--view");
+            Some(match attach(cell_id, view) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("chaos-grid: {}", e);
+                    1
+                }
+            })
+        }
+        Some("daemon") => Some(match crate::pty_daemon::serve() {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("chaos-grid daemon: {}", e);
+                1
+            }
+        }),
+        _ => None,
+    }
+}
+
+/// Line-oriented JSON-RPC client shared by the reader, input and resize threads.
+struct RpcClient {
+    stream: Mutex<UnixStream>,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    fn send(&self, method: &str, params: Value) -> Result<u64, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let line = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).to_string();
+        let mut stream = self.stream.lock().unwrap();
+        stream
+            .write_all(format!("{}\n", line).as_bytes())
+            .map_err(|e| e.to_string())?;
+        Ok(id)
+    }
+}
+
+/// Read lines until the response for `id` arrives.
+fn wait_for(reader: &mut impl BufRead, id: u64) -> Result<Value, String> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed".to_string());
+        }
+        let msg: Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+        if msg.get("id").and_then(|v| v.as_u64()) != Some(id) {
+            continue;
+        }
+        if let Some(err) = msg.get("error") {
+            let text = err.get("message").and_then(|m| m.as_str()).unwrap_or("request failed");
+            return Err(text.to_string());
+        }
+        return Ok(msg.get("result").cloned().unwrap_or(Value::Null));
+    }
+}
+
+/// Current terminal size as (cols, rows), read via `stty size`.
+fn terminal_size() -> Option<(u16, u16)> {
+    let out = Command::new("stty").arg("size").stdin(Stdio::inherit()).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let mut parts = text.split_whitespace();
+    let rows = parts.next()?.parse().ok()?;
+    let cols = parts.next()?.parse().ok()?;
+    Some((cols, rows))
+}
+
+/// Puts the terminal in raw mode and restores the previous settings on drop.
+struct RawMode(String);
+
+impl RawMode {
+    fn enable() -> Result<Self, String> {
+        let saved = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .map_err(|e| e.to_string())?;
+        let saved = String::from_utf8_lossy(&saved.stdout).trim().to_string();
+        Command::new("stty")
+            .args(["raw", "-echo"])
+            .stdin(Stdio::inherit())
+            .status()
+            .map_err(|e| e.to_string())?;
+        Ok(RawMode(saved))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.0).stdin(Stdio::inherit()).status();
+    }
+}
+
+fn attach(cell_id: &str, view: bool) -> Result<(), String> {
+    let token = std::fs::read_to_string(crate::control_api::token_path())
+        .map_err(|_| "no API token found; is Chaos Grid running?".to_string())?;
+    let path = crate::control_api::socket_path();
+    let stream = UnixStream::connect(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let client = Arc::new(RpcClient { stream: Mutex::new(stream), next_id: AtomicU64::new(1) });
+
+    let id = client.send("auth", json!({"token": token.trim()}))?;
+    wait_for(&mut reader, id)?;
+
+    let (cols, rows) = terminal_size().unwrap_or((crate::DEFAULT_COLS, crate::DEFAULT_ROWS));
+    let mode = if view { "view" } else { "control" };
+    let id = client.send(
+        "attach",
+        json!({"cellId": cell_id, "cols": cols, "rows": rows, "mode": mode}),
+    )?;
+    let attached = wait_for(&mut reader, id)?;
+
+    let raw = RawMode::enable()?;
+    let mut stdout = std::io::stdout();
+    let screen = attached.get("screen").and_then(|s| s.as_str()).unwrap_or("");
+    let _ = stdout.write_all(screen.as_bytes());
+    let _ = stdout.flush();
+
+    // Output: forward pty-data notifications to stdout until the cell exits.
+    let output = std::thread::spawn(move || {
+        let mut stdout = std::io::stdout();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(n) if n > 0 => {}
+                _ => return "connection closed",
+            }
+            let Ok(msg) = serde_json::from_str::<Value>(&line) else { continue };
+            if msg.get("method").and_then(|m| m.as_str()) != Some("event") {
+                continue;
+            }
+            let params = &msg["params"];
+            match params["event"].as_str() {
+                Some("pty-data") => {
+                    let data = params["payload"]["data"].as_str().unwrap_or("");
+                    let _ = stdout.write_all(data.as_bytes());
+                    let _ = stdout.flush();
+                }
+                Some("pty-exited") => return "cell exited",
+                _ => {}
+            }
+        }
+    });
+
+    // Resize: poll the terminal size and renegotiate when it changes.
+    let resize_client = client.clone();
+    std::thread::spawn(move || {
+        let mut last = (cols, rows);
+        loop {
+            std::thread::sleep(RESIZE_POLL);
+            if let Some(size) = terminal_size() {
+                if size != last {
+                    last = size;
+                    if resize_client.send("resize", json!({"cols": size.0, "rows": size.1})).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    // Input: forward stdin until the detach key. Runs detached so that output
+    // ending (cell exit) can end the session even while stdin is blocked.
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<&'static str>();
+    let input_done = done_tx.clone();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 1024];
+        let mut pending = Vec::new();
+        loop {
+            let n = match stdin.read(&mut buf) {
+                Ok(n) if n > 0 => n,
+                _ => break,
+            };
+            let chunk = &buf[..n];
+            let (chunk, detach) = match chunk.iter().position(|b| *b == DETACH_KEY) {
+                Some(pos) => (&chunk[..pos], true),
+                None => (chunk, false),
+            };
+            if !view {
+                pending.extend_from_slice(chunk);
+                let data = crate::pty_manager::take_utf8(&mut pending);
+                if !data.is_empty() && client.send("input", json!({"data": data})).is_err() {
+                    break;
+                }
+            }
+            if detach {
+                let _ = client.send("detach", Value::Null);
+                let _ = input_done.send("detached");
+                return;
+            }
+        }
+        let _ = input_done.send("input closed");
+    });
+    std::thread::spawn(move || {
+        let reason = output.join().unwrap_or("connection closed");
+        let _ = done_tx.send(reason);
+    });
+
+    let reason = done_rx.recv().unwrap_or("connection closed");
+    drop(raw);
+    eprintln!("\r\n[{}: {}]", cell_id, reason);
+    Ok(())
+}
@@ -1,8 +1,27 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
-use crate::storage::AiConfig;
-use crate::{ai, storage, AnalyzeResult, CellState, CellStateMap};
+use crate::actions::{self, ActionContext, ActionLogEntry};
+use crate::storage::{AiConfig, AnalysisInput, CellSnapshot};
+use crate::ai::{GenreInput, Rendered, Template, TemplateInfo};
+use crate::usage::{self, UsageReport};
+use crate::{ai, storage, AnalyzeResult, CellState, CellStateMap, FlowAction};
+
+const SNAPSHOT_CHARS: usize = 600;
+
+fn snapshot(cell: &CellState) -> CellSnapshot {
+    let output = ai::strip_ansi(&cell.last_output);
+    let mut start = output.len().saturating_sub(SNAPSHOT_CHARS);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    CellSnapshot {
+        id: cell.id.clone(),
+        theme: cell.theme.clone(),
+        status: cell.status.clone(),
+        output: output[start..].to_string(),
+    }
+}
 
 #[tauri::command]
 pub(crate) async fn analyze(
@@ -21,15 +40,42 @@ pub(crate) async fn analyze(
 
     let lang = language.as_deref().unwrap_or("English");
     let cols_count = cols.unwrap_or(3) as usize;
-    let history = storage::load_analysis_history(&app);
-    let result = ai::analyze_cells(&config, &cells, &history, lang, cols_count, output_dir.as_deref()).await?;
+    let history = storage::load_recent_analyses(&app, ai::MAX_HISTORY_ENTRIES);
+    let mut result = ai::analyze_cells(&config, &cells, &history, lang, cols_count, output_dir.as_deref()).await?;
 
-    let themes: HashMap<String, String> = cells.iter().map(|c| (c.id.clone(), c.theme.clone())).collect();
-    storage::save_analysis(&app, &result, themes);
+    let input = AnalysisInput {
+        language: lang.to_string(),
+        cols: cols_count,
+        output_dir,
+        cells: cells.iter().filter(|c| !c.last_output.is_empty()).map(snapshot).collect(),
+    };
+    let retention = storage::load_settings(&app).analysis_retention;
+    match storage::save_analysis(&app, &result, input, retention) {
+        Ok(id) => result.analysis_id = Some(id),
+        Err(e) => eprintln!("analysis history: {}", e),
+    }
 
     Ok(result)
 }
 
+/// Execute one action from `FlowAnalysis.actions` after the user approved it.
+#[tauri::command]
+pub(crate) async fn apply_analysis_action(
+    app: tauri::AppHandle,
+    action: FlowAction,
+    context: Option<ActionContext>,
+) -> Result<ActionLogEntry, String> {
+    actions::apply(&app, action, &context.unwrap_or_default(), "user").await
+}
+
+#[tauri::command]
+pub(crate) async fn get_action_log(
+    app: tauri::AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<ActionLogEntry>, String> {
+    Ok(actions::load_log(&app, limit.unwrap_or(100)))
+}
+
 #[tauri::command]
 pub(crate) async fn get_ai_config(
     ai_config: tauri::State<'_, Mutex<AiConfig>>,
@@ -46,3 +92,78 @@ pub(crate) async fn set_ai_config(
     *ai_config.lock().unwrap() = config;
     Ok(())
 }
+
+/// Tokens and estimated cost of the app's own AI calls over the last `days` days (default 30).
+#[tauri::command]
+pub(crate) async fn get_ai_usage(
+    app: tauri::AppHandle,
+    ai_config: tauri::State<'_, Mutex<AiConfig>>,
+    days: Option<u32>,
+) -> Result<UsageReport, String> {
+    let config = ai_config.lock().unwrap().clone();
+    Ok(usage::report(&app, &config, days.unwrap_or(30).max(1)))
+}
+
+// ─── Prompt templates ────────────────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PromptPreview {
+    pub name: String,
+    #[serde(flatten)]
+    pub rendered: Rendered,
+    /// Estimated tokens for the configured provider.
+    pub tokens: usize,
+    pub context: Option<ai::ContextReport>,
+}
+
+#[tauri::command]
+pub(crate) async fn list_prompt_templates() -> Result<Vec<TemplateInfo>, String> {
+    Ok(ai::list_templates())
+}
+
+/// Live data a preview renders with; unset fields fall back like `analyze` does.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PreviewInput {
+    pub language: Option<String>,
+    pub cols: Option<u32>,
+    pub output_dir: Option<String>,
+    /// Work streams for `genre_summary` and `control_advisor`.
+    #[serde(default)]
+    pub genres: Vec<GenreInput>,
+    /// The user's question, for `control_advisor`'s retrieved excerpts.
+    pub query: Option<String>,
+}
+
+/// Render a prompt template with live data, without calling the model.
+#[tauri::command]
+pub(crate) async fn preview_prompt(
+    app: tauri::AppHandle,
+    ai_config: tauri::State<'_, Mutex<AiConfig>>,
+    cell_states: tauri::State<'_, CellStateMap>,
+    name: String,
+    input: Option<PreviewInput>,
+) -> Result<PromptPreview, String> {
+    let config = ai_config.lock().unwrap().clone();
+    let input = input.unwrap_or_default();
+    let lang = input.language.as_deref().unwrap_or("English");
+    let (rendered, context) = match Template::from_name(&name)? {
+        Template::Analysis => {
+            let cells: Vec<CellState> = cell_states.0.lock().map_err(|e| e.to_string())?.values().cloned().collect();
+            let history = storage::load_recent_analyses(&app, ai::MAX_HISTORY_ENTRIES);
+            let cols = input.cols.unwrap_or(3) as usize;
+            let prompt = ai::analysis_prompt(&config, &cells, &history, lang, cols, input.output_dir.as_deref())
+                .ok_or("no cell has output to analyze yet")?;
+            (prompt.prompt, Some(prompt.ctx.report))
+        }
+        Template::GenreSummary => (ai::genre_summary_prompt(&input.genres, lang), None),
+        Template::ControlAdvisor => (ai::control_advisor_prompt(&input.genres, lang, input.query.as_deref()), None),
+    };
+    Ok(PromptPreview {
+        name,
+        tokens: ai::estimate_tokens(&config, &rendered.text),
+        rendered,
+        context,
+    })
+}
@@ -0,0 +1,43 @@
+use crate::analysis_history::{self, AnalysisDiff, AnalysisListItem, TimelinePoint};
+use crate::storage::{self, AnalysisEntry};
+
+#[tauri::command]
+pub(crate) async fn list_analyses(
+    app: tauri::AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<AnalysisListItem>, String> {
+    let index = storage::analysis_index(&app);
+    Ok(analysis_history::list(&index, limit.unwrap_or(50)))
+}
+
+#[tauri::command]
+pub(crate) async fn get_analysis(
+    app: tauri::AppHandle,
+    id: String,
+) -> Result<AnalysisEntry, String> {
+    storage::load_analysis(&app, &id)
+}
+
+#[tauri::command]
+pub(crate) async fn diff_analyses(
+    app: tauri::AppHandle,
+    from_id: String,
+    to_id: String,
+) -> Result<AnalysisDiff, String> {
+    let from = storage::load_analysis(&app, &from_id)?;
+    let to = storage::load_analysis(&app, &to_id)?;
+    Ok(analysis_history::diff(&from, &to))
+}
+
+/// How many of the newest analyses a timeline covers by default.
+const TIMELINE_ANALYSES: usize = 100;
+
+#[tauri::command]
+pub(crate) async fn get_cell_summary_timeline(
+    app: tauri::AppHandle,
+    cell_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<TimelinePoint>, String> {
+    let history = storage::load_recent_analyses(&app, limit.unwrap_or(TIMELINE_ANALYSES));
+    Ok(analysis_history::cell_timeline(&history, &cell_id))
+}
@@ -1,3 +1,4 @@
+use crate::storage::{self, SavedSession, SessionEntry};
 use crate::{CellState, CellStateMap};
 
 #[tauri::command]
@@ -16,6 +17,7 @@ pub(crate) async fn set_theme(
     cell_id: String,
     theme: String,
 ) -> Result<(), String> {
+    crate::ai::check_theme(&theme)?;
     let mut states = cell_states.0.lock().map_err(|e| e.to_string())?;
     if let Some(state) = states.get_mut(&cell_id) {
         state.theme = theme;
@@ -71,3 +73,17 @@ pub(crate) async fn get_cell_cpu(
     };
     Ok(pid.map(cpu_for_tree).unwrap_or(0.0))
 }
+
+#[tauri::command]
+pub(crate) async fn save_session_state(
+    app: tauri::AppHandle,
+    entries: Vec<SessionEntry>,
+) -> Result<(), String> {
+    storage::save_session(&app, entries);
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) async fn load_session_state(app: tauri::AppHandle) -> Result<Option<SavedSession>, String> {
+    Ok(storage::load_session(&app))
+}
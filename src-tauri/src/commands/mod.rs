@@ -1,4 +1,13 @@
 pub(crate) mod ai_cmds;
+pub(crate) mod analysis;
 pub(crate) mod cell;
+pub(crate) mod chat;
 pub(crate) mod launch;
+pub(crate) mod pipes;
 pub(crate) mod pty;
+pub(crate) mod sandbox;
+pub(crate) mod search;
+pub(crate) mod settings;
+pub(crate) mod summaries;
+pub(crate) mod supervisor;
+pub(crate) mod triggers;
@@ -0,0 +1,27 @@
+use crate::pipes::{self, PipeConfig, Pipes};
+
+#[tauri::command]
+pub(crate) async fn get_pipes(
+    pipes: tauri::State<'_, Pipes>,
+) -> Result<PipeConfig, String> {
+    Ok(pipes.config())
+}
+
+/// Replace the pipe set. Returns it with ids assigned to new pipes.
+#[tauri::command]
+pub(crate) async fn set_pipes(
+    app: tauri::AppHandle,
+    pipes: tauri::State<'_, Pipes>,
+    config: PipeConfig,
+) -> Result<PipeConfig, String> {
+    pipes.set(&app, config)
+}
+
+/// Forward a pipe's current selection immediately, ignoring its interval.
+#[tauri::command]
+pub(crate) async fn run_pipe(
+    app: tauri::AppHandle,
+    pipe_id: String,
+) -> Result<(), String> {
+    pipes::run_now(&app, &pipe_id)
+}
@@ -1,7 +1,42 @@
 use std::io::Write;
+use tauri::Manager;
 
+use crate::attach::{AttachRegistry, GUI_CLIENT};
+use crate::watcher::FileWatcher;
 use crate::{CellStateMap, PtySessions, now_millis, pty_manager};
 
+/// Write to a cell's PTY without input arbitration; callers decide who may type.
+pub(crate) fn write_to_cell(sessions: &PtySessions, cell_id: &str, data: &str) -> Result<(), String> {
+    let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
+    if let Some(session) = map.get_mut(cell_id) {
+        session
+            .writer
+            .write_all(data.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Submit a (possibly multi-line) prompt to a cell's PTY. Bracketed paste keeps
+/// the lines together in agent CLIs and shells; CR submits.
+pub(crate) fn paste_to_cell(sessions: &PtySessions, cell_id: &str, text: &str) -> Result<(), String> {
+    let has_pty = sessions.0.lock().map_err(|e| e.to_string())?.contains_key(cell_id);
+    if !has_pty {
+        return Err(format!("{} has no running session", cell_id));
+    }
+    write_to_cell(sessions, cell_id, &format!("\x1b[200~{}\x1b[201~\r", text))
+}
+
+/// Apply a negotiated size to a cell's PTY, if it has one.
+pub(crate) fn apply_size(sessions: &PtySessions, cell_id: &str, size: Option<(u16, u16)>) -> Result<(), String> {
+    let Some((cols, rows)) = size else { return Ok(()) };
+    let map = sessions.0.lock().map_err(|e| e.to_string())?;
+    if let Some(session) = map.get(cell_id) {
+        pty_manager::resize(session, cols, rows)?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub(crate) async fn spawn_pty(
     app: tauri::AppHandle,
@@ -11,8 +46,20 @@ pub(crate) async fn spawn_pty(
     cols: u16,
     rows: u16,
 ) -> Result<u32, String> {
+    #[cfg(unix)]
+    if crate::pty_daemon::enabled() {
+        let _ = tauri::async_runtime::spawn_blocking(crate::pty_daemon::wait_restored).await;
+    }
     {
         let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
+        // A session restored from the PTY daemon is handed to the first
+        // terminal that asks for it instead of being replaced.
+        if let Some(session) = map.get_mut(&cell_id).filter(|s| s.restored) {
+            session.restored = false;
+            pty_manager::resize(session, cols, rows)?;
+            pty_manager::emit_buffer(&app, &cell_id, session);
+            return Ok(session.pid);
+        }
         if let Some(mut session) = map.remove(&cell_id) {
             pty_manager::kill(&mut session);
         }
@@ -38,6 +85,7 @@ pub(crate) async fn spawn_pty(
             state.updated_at = now_millis();
         }
     }
+    pty_manager::emit_status(&app, &cell_id, "active");
 
     {
         let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
@@ -50,35 +98,29 @@ pub(crate) async fn spawn_pty(
 #[tauri::command]
 pub(crate) async fn write_pty(
     sessions: tauri::State<'_, PtySessions>,
+    attachments: tauri::State<'_, AttachRegistry>,
     cell_id: String,
     data: String,
 ) -> Result<(), String> {
-    let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
-    if let Some(session) = map.get_mut(&cell_id) {
-        session
-            .writer
-            .write_all(data.as_bytes())
-            .map_err(|e| e.to_string())?;
-    }
-    Ok(())
+    attachments.acquire_input(&cell_id, GUI_CLIENT)?;
+    write_to_cell(&sessions, &cell_id, &data)
 }
 
 #[tauri::command]
 pub(crate) async fn resize_pty(
     sessions: tauri::State<'_, PtySessions>,
+    attachments: tauri::State<'_, AttachRegistry>,
     cell_id: String,
     cols: u16,
     rows: u16,
 ) -> Result<(), String> {
-    let map = sessions.0.lock().map_err(|e| e.to_string())?;
-    if let Some(session) = map.get(&cell_id) {
-        pty_manager::resize(session, cols, rows)?;
-    }
-    Ok(())
+    let size = attachments.resize(&cell_id, GUI_CLIENT, cols, rows);
+    apply_size(&sessions, &cell_id, size)
 }
 
 #[tauri::command]
 pub(crate) async fn kill_pty(
+    app: tauri::AppHandle,
     sessions: tauri::State<'_, PtySessions>,
     cell_states: tauri::State<'_, CellStateMap>,
     cell_id: String,
@@ -98,12 +140,15 @@ pub(crate) async fn kill_pty(
             state.updated_at = now_millis();
         }
     }
+    pty_manager::emit_status(&app, &cell_id, "idle");
+    app.state::<FileWatcher>().unwatch_cell(&cell_id);
 
     Ok(())
 }
 
 #[tauri::command]
 pub(crate) async fn kill_all_ptys(
+    app: tauri::AppHandle,
     sessions: tauri::State<'_, PtySessions>,
     cell_states: tauri::State<'_, CellStateMap>,
 ) -> Result<(), String> {
@@ -127,5 +172,9 @@ pub(crate) async fn kill_all_ptys(
             }
         }
     }
+    for id in &killed {
+        pty_manager::emit_status(&app, id, "idle");
+        app.state::<FileWatcher>().unwatch_cell(id);
+    }
     Ok(())
 }
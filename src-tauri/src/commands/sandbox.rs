@@ -0,0 +1,25 @@
+use tauri_plugin_dialog::DialogExt;
+
+use crate::sandbox::{self, WorkspaceRoots};
+
+#[tauri::command]
+pub(crate) async fn get_workspace_roots() -> Result<WorkspaceRoots, String> {
+    Ok(sandbox::workspace_roots())
+}
+
+/// Let the user choose a folder in the native picker and add it to the allowlist.
+/// Returns the canonical path, or `None` if the picker was cancelled.
+#[tauri::command]
+pub(crate) async fn pick_workspace_root(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog().file().pick_folder(move |folder| {
+        let _ = tx.send(folder);
+    });
+    let Some(folder) = rx.await.map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    let path = folder.into_path().map_err(|e| e.to_string())?;
+    let path = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+    sandbox::allow_picked_root(&app, &path)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
@@ -0,0 +1,27 @@
+use crate::retrieval::{self, Hit};
+use crate::search::{self, SearchHit, SearchQuery};
+use crate::{CellState, CellStateMap};
+
+#[tauri::command]
+pub(crate) async fn search_output(
+    app: tauri::AppHandle,
+    cell_states: tauri::State<'_, CellStateMap>,
+    query: SearchQuery,
+) -> Result<Vec<SearchHit>, String> {
+    let mut cells: Vec<CellState> = {
+        let states = cell_states.0.lock().map_err(|e| e.to_string())?;
+        states.values().cloned().collect()
+    };
+    cells.sort_by(|a, b| a.id.cmp(&b.id));
+    search::search(&app, &cells, &query)
+}
+
+/// Passages of the files under `dir` most relevant to `query`, from the local keyword index.
+#[tauri::command]
+pub(crate) async fn search_work_files(dir: String, query: String, limit: Option<usize>) -> Result<Vec<Hit>, String> {
+    let root = crate::sandbox::check(&dir)?;
+    if !root.is_dir() {
+        return Err(format!("{}: not a directory", root.display()));
+    }
+    Ok(retrieval::search(&root, &query, limit.unwrap_or(10)))
+}
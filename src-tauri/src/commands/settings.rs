@@ -0,0 +1,12 @@
+use crate::storage::{self, AppSettings};
+
+#[tauri::command]
+pub(crate) async fn get_app_settings(app: tauri::AppHandle) -> Result<AppSettings, String> {
+    Ok(storage::load_settings(&app))
+}
+
+#[tauri::command]
+pub(crate) async fn set_app_settings(app: tauri::AppHandle, settings: AppSettings) -> Result<AppSettings, String> {
+    storage::save_settings(&app, &settings)?;
+    Ok(settings)
+}
@@ -0,0 +1,27 @@
+use crate::summaries::{self, CellSummary, SummarizerConfig, Summarizer};
+
+/// Summarize one cell now, folding its new output into the previous summary.
+#[tauri::command]
+pub(crate) async fn summarize_cell(
+    app: tauri::AppHandle,
+    cell_id: String,
+    language: Option<String>,
+) -> Result<CellSummary, String> {
+    summaries::summarize(&app, &cell_id, language.as_deref().unwrap_or("English")).await
+}
+
+#[tauri::command]
+pub(crate) async fn get_summarizer_config(
+    summarizer: tauri::State<'_, Summarizer>,
+) -> Result<SummarizerConfig, String> {
+    Ok(summarizer.config())
+}
+
+#[tauri::command]
+pub(crate) async fn set_summarizer_config(
+    app: tauri::AppHandle,
+    summarizer: tauri::State<'_, Summarizer>,
+    config: SummarizerConfig,
+) -> Result<SummarizerConfig, String> {
+    summarizer.set(&app, config)
+}
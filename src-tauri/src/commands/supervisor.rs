@@ -0,0 +1,42 @@
+use crate::supervisor::{self, Supervisor, SupervisorConfig, SupervisorRun};
+
+#[tauri::command]
+pub(crate) async fn get_supervisor_config(
+    supervisor: tauri::State<'_, Supervisor>,
+) -> Result<SupervisorConfig, String> {
+    Ok(supervisor.config())
+}
+
+#[tauri::command]
+pub(crate) async fn set_supervisor_config(
+    app: tauri::AppHandle,
+    supervisor: tauri::State<'_, Supervisor>,
+    config: SupervisorConfig,
+) -> Result<(), String> {
+    supervisor.set(&app, config)
+}
+
+/// Kill switch: stop the supervisor now and keep it disabled.
+#[tauri::command]
+pub(crate) async fn stop_supervisor(
+    app: tauri::AppHandle,
+    supervisor: tauri::State<'_, Supervisor>,
+) -> Result<(), String> {
+    supervisor.kill(&app)
+}
+
+/// Run one cycle immediately. Dispatches only if the supervisor is enabled and not in dry-run.
+#[tauri::command]
+pub(crate) async fn run_supervisor_once(
+    app: tauri::AppHandle,
+) -> Result<SupervisorRun, String> {
+    supervisor::run_once(&app, "manual").await
+}
+
+#[tauri::command]
+pub(crate) async fn get_supervisor_log(
+    app: tauri::AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<SupervisorRun>, String> {
+    Ok(supervisor::load_log(&app, limit.unwrap_or(50)))
+}
@@ -0,0 +1,29 @@
+use crate::triggers::{self, TriggerConfig, TriggerRule, TriggerTest, Triggers};
+
+#[tauri::command]
+pub(crate) async fn get_triggers(
+    triggers: tauri::State<'_, Triggers>,
+) -> Result<TriggerConfig, String> {
+    Ok(triggers.config())
+}
+
+/// Replace the rule set. Returns it with ids assigned to new rules.
+#[tauri::command]
+pub(crate) async fn set_triggers(
+    app: tauri::AppHandle,
+    triggers: tauri::State<'_, Triggers>,
+    config: TriggerConfig,
+) -> Result<TriggerConfig, String> {
+    triggers.set(&app, config)
+}
+
+#[tauri::command]
+pub(crate) async fn test_trigger(
+    triggers: tauri::State<'_, Triggers>,
+    rule: TriggerRule,
+    sample: String,
+    cell_id: Option<String>,
+) -> Result<TriggerTest, String> {
+    let cols = triggers.config().cols;
+    triggers::test_rule(&rule, &sample, cell_id.as_deref(), cols)
+}
@@ -0,0 +1,643 @@
+// Local control API: newline-delimited JSON-RPC 2.0 over a Unix domain socket.
+//
+// The socket lives in the private directory ~/.chaos-grid/. Every connection
+// must call `auth` with the token stored in ~/.chaos-grid-token
+// before any other method. Methods mirror the Tauri commands of the same name;
+// `subscribe` streams app events back as `event` notifications. Each client
+// has a bounded outgoing queue; a client that stops reading is disconnected.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Listener, Manager};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, mpsc, Notify};
+
+use crate::attach::{AttachMode, AttachRegistry};
+use crate::commands::{ai_cmds, cell, launch, pty};
+use crate::{DEFAULT_COLS, DEFAULT_ROWS};
+
+pub(crate) const FORWARDED_EVENTS: &[&str] = &[
+    "pty-data",
+    "pty-exited",
+    "cell-status",
+    "trigger-fired",
+    "task-enqueued",
+    "pipe-fired",
+    "supervisor-run",
+    "cell-summary",
+];
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+/// Replies and notifications queued per client before it is disconnected.
+const CLIENT_QUEUE: usize = 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Directory holding the sockets. It is mode 0700, so a socket is never
+/// reachable by other users, not even between `bind` and a chmod.
+pub(crate) fn runtime_dir() -> PathBuf {
+    home().join(".chaos-grid")
+}
+
+/// Create `runtime_dir`, or make sure an existing one is a private directory.
+pub(crate) fn ensure_runtime_dir() -> Result<PathBuf, String> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let dir = runtime_dir();
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let meta = std::fs::symlink_metadata(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    if !meta.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    if meta.permissions().mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    Ok(dir)
+}
+
+pub(crate) fn socket_path() -> PathBuf {
+    runtime_dir().join("control.sock")
+}
+
+pub(crate) fn token_path() -> PathBuf {
+    home().join(".chaos-grid-token")
+}
+
+/// Compare a presented token with ours in time independent of where they differ.
+pub(crate) fn token_matches(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    given.len() == token.len() && given.iter().zip(token).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn generate_token() -> Result<String, String> {
+    use std::io::Read;
+    let mut bytes = [0u8; 32];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(|e| format!("failed to read /dev/urandom: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Read the API token, creating it (mode 0600) on first use.
+pub(crate) fn load_or_create_token() -> Result<String, String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = token_path();
+    if let Ok(existing) = std::fs::read_to_string(&path) {
+        let existing = existing.trim();
+        if !existing.is_empty() {
+            return Ok(existing.to_string());
+        }
+    }
+    let token = generate_token()?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    file.write_all(token.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(token)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ApiEvent {
+    pub name: String,
+    pub payload: Value,
+}
+
+/// Start the control API in the background. Disabled with CHAOS_GRID_CONTROL_API=0.
+pub(crate) fn start(app: AppHandle) {
+    if std::env::var("CHAOS_GRID_CONTROL_API").map(|v| v == "0").unwrap_or(false) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = serve(app).await {
+            eprintln!("control API disabled: {}", e);
+        }
+    });
+}
+
+async fn serve(app: AppHandle) -> Result<(), String> {
+    let token = load_or_create_token()?;
+    ensure_runtime_dir()?;
+    let path = socket_path();
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(format!("{} is in use by another instance", path.display()));
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = UnixListener::bind(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let (events_tx, _) = broadcast::channel::<ApiEvent>(EVENT_CHANNEL_CAPACITY);
+    for name in FORWARDED_EVENTS {
+        let tx = events_tx.clone();
+        let name = name.to_string();
+        app.listen_any(name.clone(), move |event| {
+            let payload = serde_json::from_str(event.payload()).unwrap_or(Value::Null);
+            let _ = tx.send(ApiEvent { name: name.clone(), payload });
+        });
+    }
+
+    loop {
+        let (stream, _) = listener.accept().await.map_err(|e| e.to_string())?;
+        let app = app.clone();
+        let token = token.clone();
+        let events_tx = events_tx.clone();
+        tauri::async_runtime::spawn(async move {
+            handle_connection(app, stream, token, events_tx).await;
+        });
+    }
+}
+
+fn rpc_result(id: &Value, result: Value) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string()
+}
+
+fn rpc_error(id: &Value, code: i64, message: &str) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}).to_string()
+}
+
+fn rpc_notification(event: &ApiEvent) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "event",
+        "params": {"event": event.name, "payload": event.payload}
+    })
+    .to_string()
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct SubscribeParams {
+    events: Option<Vec<String>>,
+    cell_ids: Option<Vec<String>>,
+}
+
+impl SubscribeParams {
+    fn matches(&self, event: &ApiEvent) -> bool {
+        if let Some(events) = &self.events {
+            if !events.iter().any(|e| e == &event.name) {
+                return false;
+            }
+        }
+        match (&self.cell_ids, event.payload.get("cellId").and_then(|v| v.as_str())) {
+            (Some(ids), Some(cell_id)) => ids.iter().any(|id| id == cell_id),
+            _ => true,
+        }
+    }
+}
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Per-connection state: identity for input arbitration, the attached cell
+/// (if any), the task forwarding `subscribe`d events and the one streaming
+/// the attached cell. The two streams are independent.
+struct Connection {
+    app: AppHandle,
+    client_id: String,
+    events_tx: broadcast::Sender<ApiEvent>,
+    out_tx: Outbox,
+    attached: Option<String>,
+    subscription: Option<tauri::async_runtime::JoinHandle<()>>,
+    attach_stream: Option<tauri::async_runtime::JoinHandle<()>>,
+}
+
+/// A client's outgoing queue. Sending never waits: when the queue is full the
+/// client has stopped reading, and `lagged` tells the connection to close.
+#[derive(Clone)]
+struct Outbox {
+    tx: mpsc::Sender<String>,
+    lagged: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Queue a line; false once the client is gone or has fallen behind.
+    fn send(&self, line: String) -> bool {
+        match self.tx.try_send(line) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.lagged.notify_one();
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Forward events from `rx` that match `filter` to the client.
+fn forward(
+    mut rx: broadcast::Receiver<ApiEvent>,
+    filter: SubscribeParams,
+    tx: Outbox,
+) -> tauri::async_runtime::JoinHandle<()> {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) if filter.matches(&event) => {
+                    if !tx.send(rpc_notification(&event)) {
+                        break;
+                    }
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+impl Connection {
+    fn subscribe(&mut self, filter: SubscribeParams) {
+        if let Some(previous) = self.subscription.take() {
+            previous.abort();
+        }
+        self.subscription = Some(forward(self.events_tx.subscribe(), filter, self.out_tx.clone()));
+    }
+
+    fn attached_cell(&self) -> Result<String, RpcError> {
+        self.attached
+            .clone()
+            .ok_or_else(|| RpcError(INVALID_PARAMS, "not attached to a cell".to_string()))
+    }
+
+    fn detach(&mut self) -> Result<(), RpcError> {
+        if let Some(stream) = self.attach_stream.take() {
+            stream.abort();
+        }
+        if let Some(cell_id) = self.attached.take() {
+            let size = self.app.state::<AttachRegistry>().detach(&cell_id, &self.client_id);
+            pty::apply_size(&self.app.state(), &cell_id, size)?;
+        }
+        Ok(())
+    }
+
+    /// Methods that depend on connection state and therefore run inline.
+    fn handle_stateful(&mut self, method: &str, params: Value) -> Option<Result<Value, RpcError>> {
+        let result = match method {
+            "subscribe" => parse_params(params).map(|filter| {
+                self.subscribe(filter);
+                json!(true)
+            }),
+            "attach" => self.attach(params),
+            "detach" => self.detach().map(|_| Value::Null),
+            "input" => self.input(params),
+            "write_pty" => parse_params::<WriteParams>(params).and_then(|p| {
+                self.app.state::<AttachRegistry>().acquire_input(&p.cell_id, &self.client_id)?;
+                pty::write_to_cell(&self.app.state(), &p.cell_id, &p.data)?;
+                Ok(Value::Null)
+            }),
+            "resize" => self.resize(params),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn attach(&mut self, params: Value) -> Result<Value, RpcError> {
+        let p: AttachParams = parse_params(params)?;
+        let mode = AttachMode::parse(p.mode.as_deref()).map_err(|e| RpcError(INVALID_PARAMS, e))?;
+        self.detach()?;
+        // Subscribe, then snapshot, both under the buffer lock the PTY reader holds
+        // while it appends and emits: every chunk is either in the screen or in
+        // the stream, never both and never neither.
+        let (rx, screen) = {
+            let sessions = self.app.state::<crate::PtySessions>();
+            let map = sessions.0.lock().map_err(|e| e.to_string())?;
+            let session = map.get(&p.cell_id).ok_or_else(|| format!("no PTY for {}", p.cell_id))?;
+            let buffer = session.buffer.lock().unwrap();
+            let rx = self.events_tx.subscribe();
+            (rx, buffer.clone())
+        };
+        let filter = SubscribeParams {
+            events: Some(vec!["pty-data".to_string(), "pty-exited".to_string()]),
+            cell_ids: Some(vec![p.cell_id.clone()]),
+        };
+        self.attach_stream = Some(forward(rx, filter, self.out_tx.clone()));
+        let registry = self.app.state::<AttachRegistry>();
+        let size = registry.attach(&p.cell_id, &self.client_id, mode, p.cols, p.rows);
+        pty::apply_size(&self.app.state(), &p.cell_id, size)?;
+        self.attached = Some(p.cell_id);
+        let (cols, rows) = size.unwrap_or((p.cols, p.rows));
+        Ok(json!({"clientId": self.client_id, "cols": cols, "rows": rows, "screen": screen}))
+    }
+
+    fn input(&mut self, params: Value) -> Result<Value, RpcError> {
+        let cell_id = self.attached_cell()?;
+        let p: InputParams = parse_params(params)?;
+        self.app.state::<AttachRegistry>().acquire_input(&cell_id, &self.client_id)?;
+        pty::write_to_cell(&self.app.state(), &cell_id, &p.data)?;
+        Ok(Value::Null)
+    }
+
+    fn resize(&mut self, params: Value) -> Result<Value, RpcError> {
+        let cell_id = self.attached_cell()?;
+        let p: ResizeParams = parse_params(params)?;
+        let size = self.app.state::<AttachRegistry>().resize(&cell_id, &self.client_id, p.cols, p.rows);
+        pty::apply_size(&self.app.state(), &cell_id, size)?;
+        let (cols, rows) = size.unwrap_or((p.cols, p.rows));
+        Ok(json!({"cols": cols, "rows": rows}))
+    }
+}
+
+async fn handle_connection(
+    app: AppHandle,
+    stream: UnixStream,
+    token: String,
+    events_tx: broadcast::Sender<ApiEvent>,
+) {
+    let (read_half, mut write_half) = stream.into_split();
+    let (tx, mut out_rx) = mpsc::channel::<String>(CLIENT_QUEUE);
+    let lagged = Arc::new(Notify::new());
+    let out_tx = Outbox { tx, lagged: lagged.clone() };
+
+    let writer = tauri::async_runtime::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            if write_half.write_all(line.as_bytes()).await.is_err()
+                || write_half.write_all(b"\n").await.is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut conn = Connection {
+        app: app.clone(),
+        client_id: format!("api-{}", NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed)),
+        events_tx,
+        out_tx: out_tx.clone(),
+        attached: None,
+        subscription: None,
+        attach_stream: None,
+    };
+    let mut lines = BufReader::new(read_half).lines();
+    let mut authed = false;
+
+    let mut fell_behind = false;
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => line,
+                _ => break,
+            },
+            _ = lagged.notified() => {
+                eprintln!("control API: {} stopped reading, disconnecting", conn.client_id);
+                fell_behind = true;
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(e) => {
+                out_tx.send(rpc_error(&Value::Null, PARSE_ERROR, &e.to_string()));
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        if method == "auth" {
+            let given = params.get("token").and_then(|t| t.as_str()).unwrap_or("");
+            authed = token_matches(given, &token);
+            let reply = if authed {
+                rpc_result(&id, json!(true))
+            } else {
+                rpc_error(&id, UNAUTHORIZED, "invalid token")
+            };
+            out_tx.send(reply);
+            continue;
+        }
+        if !authed {
+            out_tx.send(rpc_error(&id, UNAUTHORIZED, "call auth first"));
+            continue;
+        }
+
+        if let Some(result) = conn.handle_stateful(&method, params.clone()) {
+            let reply = match result {
+                Ok(result) => rpc_result(&id, result),
+                Err(RpcError(code, message)) => rpc_error(&id, code, &message),
+            };
+            out_tx.send(reply);
+            continue;
+        }
+
+        let app = app.clone();
+        let tx = out_tx.clone();
+        tauri::async_runtime::spawn(async move {
+            let reply = match dispatch(&app, &method, params).await {
+                Ok(result) => rpc_result(&id, result),
+                Err(RpcError(code, message)) => rpc_error(&id, code, &message),
+            };
+            tx.send(reply);
+        });
+    }
+
+    let _ = conn.detach();
+    if let Some(sub) = conn.subscription.take() {
+        sub.abort();
+    }
+    drop(conn);
+    drop(out_tx);
+    if fell_behind {
+        // Its queue would never drain; close the socket now.
+        writer.abort();
+    } else {
+        let _ = writer.await;
+    }
+}
+
+struct RpcError(i64, String);
+
+impl From<String> for RpcError {
+    fn from(message: String) -> Self {
+        RpcError(SERVER_ERROR, message)
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError(INVALID_PARAMS, e.to_string()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CellParams {
+    cell_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpawnParams {
+    cell_id: String,
+    cols: Option<u16>,
+    rows: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteParams {
+    cell_id: String,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachParams {
+    cell_id: String,
+    cols: u16,
+    rows: u16,
+    mode: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InputParams {
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct ResizeParams {
+    cols: u16,
+    rows: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchParams {
+    cell_ids: Vec<String>,
+    #[serde(default)]
+    work_dirs: Vec<String>,
+    tool_cmd: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnalyzeParams {
+    language: Option<String>,
+    cols: Option<u32>,
+    output_dir: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SummarizeParams {
+    cell_id: String,
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApplyActionParams {
+    action: crate::FlowAction,
+    #[serde(default)]
+    context: crate::actions::ActionContext,
+}
+
+async fn dispatch(app: &AppHandle, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "spawn_pty" => {
+            let p: SpawnParams = parse_params(params)?;
+            let pid = pty::spawn_pty(
+                app.clone(),
+                app.state(),
+                app.state(),
+                p.cell_id,
+                p.cols.unwrap_or(DEFAULT_COLS),
+                p.rows.unwrap_or(DEFAULT_ROWS),
+            )
+            .await?;
+            Ok(json!(pid))
+        }
+        "kill_pty" => {
+            let p: CellParams = parse_params(params)?;
+            pty::kill_pty(app.clone(), app.state(), app.state(), p.cell_id).await?;
+            Ok(Value::Null)
+        }
+        "launch_cells" => {
+            let p: LaunchParams = parse_params(params)?;
+            let launched = launch::launch_cells(
+                app.clone(),
+                app.state(),
+                app.state(),
+                p.cell_ids,
+                p.work_dirs,
+                p.tool_cmd,
+            )
+            .await?;
+            Ok(json!(launched))
+        }
+        "read_pty" => {
+            let p: CellParams = parse_params(params)?;
+            let sessions = app.state::<crate::PtySessions>();
+            let map = sessions.0.lock().map_err(|e| e.to_string())?;
+            let buffer = map
+                .get(&p.cell_id)
+                .map(|s| s.buffer.lock().unwrap().clone())
+                .ok_or_else(|| format!("no PTY for {}", p.cell_id))?;
+            Ok(json!(buffer))
+        }
+        "get_cells" => {
+            let cells = cell::get_cells(app.state()).await?;
+            Ok(json!(cells))
+        }
+        "analyze" => {
+            let p: AnalyzeParams = parse_params(params)?;
+            let result = ai_cmds::analyze(
+                app.clone(),
+                app.state(),
+                app.state(),
+                p.language,
+                p.cols,
+                p.output_dir,
+            )
+            .await?;
+            Ok(json!(result))
+        }
+        "summarize_cell" => {
+            let p: SummarizeParams = parse_params(params)?;
+            let language = p.language.as_deref().unwrap_or("English");
+            let summary = crate::summaries::summarize(app, &p.cell_id, language).await?;
+            Ok(json!(summary))
+        }
+        "apply_analysis_action" => {
+            let p: ApplyActionParams = parse_params(params)?;
+            let entry = crate::actions::apply(app, p.action, &p.context, "api").await?;
+            Ok(json!(entry))
+        }
+        _ => Err(RpcError(METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_matches_only_the_exact_token() {
+        assert!(token_matches("abc123", "abc123"));
+        assert!(!token_matches("abc124", "abc123"));
+        assert!(!token_matches("abc12", "abc123"));
+        assert!(!token_matches("", "abc123"));
+    }
+}
@@ -1,7 +1,13 @@
-use serde::Serialize;
-use std::path::PathBuf;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{Match, WalkBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
 use std::time::UNIX_EPOCH;
 
+use crate::git::{ChangeKind, FileDiffStat, RemoteInfo, Repo, StatusEntry};
+
 // Used for filesystem operations only — not for shell commands (the shell expands ~ itself).
 pub fn expand_tilde(path: &str) -> String {
     if let Some(rest) = path.strip_prefix("~/") {
@@ -18,52 +24,229 @@ const SKIP_DIRS: &[&str] = &[
     "build", ".turbo", "out",
 ];
 
-/// Walk a directory recursively, skipping hidden files/dirs and build artifacts.
+/// Hidden entries and build artifacts, which walks and watches leave out.
+pub(crate) fn is_skipped(name: &str) -> bool {
+    name.starts_with('.') || SKIP_DIRS.contains(&name)
+}
+
+pub(crate) fn modified_ms(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// ─── Walking ─────────────────────────────────────────────────────────────────
+
+const WALK_CONFIG_FILE: &str = "walk.json";
+/// Per-directory ignore file read alongside `.gitignore` and `.ignore`.
+const IGNORE_FILE: &str = ".chaosignore";
+pub(crate) const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", IGNORE_FILE];
+
+/// Limits and extra ignore patterns (`.gitignore` syntax) for directory walks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WalkConfig {
+    pub max_depth: usize,
+    pub max_files: usize,
+    /// Ignored under every walked directory.
+    pub ignore: Vec<String>,
+    pub workspaces: Vec<WorkspaceIgnore>,
+}
+
+/// Patterns for one workspace, relative to its root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceIgnore {
+    pub root: String,
+    pub ignore: Vec<String>,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        WalkConfig { max_depth: 16, max_files: 10_000, ignore: Vec::new(), workspaces: Vec::new() }
+    }
+}
+
+fn walk_config_lock() -> &'static RwLock<WalkConfig> {
+    static CONFIG: OnceLock<RwLock<WalkConfig>> = OnceLock::new();
+    CONFIG.get_or_init(Default::default)
+}
+
+pub(crate) fn load_walk_config(app: &tauri::AppHandle) {
+    *walk_config_lock().write().unwrap() = crate::storage::load_json(app, WALK_CONFIG_FILE);
+}
+
+fn walk_config() -> WalkConfig {
+    walk_config_lock().read().unwrap().clone()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The configured patterns that apply under one walk root. Workspace roots are
+/// compared with the walk root canonically, so `~`, `..` and symlinks in either
+/// don't matter; paths are matched in their canonical form for the same reason.
+struct UserIgnores {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    matchers: Vec<Gitignore>,
+}
+
+impl UserIgnores {
+    fn new(root: &Path, config: &WalkConfig) -> Self {
+        let build = |base: &Path, patterns: &[String]| {
+            let mut builder = GitignoreBuilder::new(base);
+            for pattern in patterns {
+                if let Err(e) = builder.add_line(None, pattern) {
+                    eprintln!("ignore pattern {:?}: {}", pattern, e);
+                }
+            }
+            builder.build().unwrap_or_else(|_| Gitignore::empty())
+        };
+        let canonical_root = canonical(root);
+        let mut matchers = vec![build(&canonical_root, &config.ignore)];
+        for workspace in &config.workspaces {
+            let base = canonical(Path::new(&expand_tilde(&workspace.root)));
+            if canonical_root.starts_with(&base) {
+                matchers.push(build(&base, &workspace.ignore));
+            }
+        }
+        UserIgnores { root: root.to_path_buf(), canonical_root, matchers }
+    }
+
+    /// `path` under the canonical root, or None when it is not under the root.
+    fn canonical_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(self.canonical_root.join(path.strip_prefix(&self.root).ok()?))
+    }
+
+    fn matched(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(path) = self.canonical_path(path) else { return false };
+        self.matchers.iter().any(|m| m.matched(&path, is_dir).is_ignore())
+    }
+
+    fn matched_or_any_parents(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(path) = self.canonical_path(path) else { return false };
+        self.matchers
+            .iter()
+            .any(|m| path.starts_with(m.path()) && m.matched_path_or_any_parents(&path, is_dir).is_ignore())
+    }
+}
+
+/// Walk a directory recursively, skipping symlinks, hidden files/dirs, build
+/// artifacts, anything `.gitignore`, `.ignore` or `.chaosignore` excludes and
+/// the configured patterns. Stops at the configured depth and file count.
 /// Returns Vec<(rel_path, abs_path, modified_ms)>.
-pub fn walk_dir(root: &std::path::Path) -> Vec<(String, PathBuf, u64)> {
+pub fn walk_dir(root: &Path) -> Vec<(String, PathBuf, u64)> {
+    let config = walk_config();
+    let user = UserIgnores::new(root, &config);
+    let walker = WalkBuilder::new(root)
+        .hidden(true)
+        .parents(true)
+        .git_ignore(true)
+        .git_global(true)
+        .git_exclude(true)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        // A link inside a workspace root may point anywhere; its target is not walked.
+        .follow_links(false)
+        .max_depth(Some(config.max_depth))
+        .filter_entry(move |entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            !is_skipped(&entry.file_name().to_string_lossy())
+                && !user.matched(entry.path(), is_dir)
+        })
+        .build();
+
     let mut out = Vec::new();
-    walk_inner(root, root, &mut out);
+    for entry in walker.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        let path = entry.into_path();
+        let rel = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string();
+        out.push((rel, path, modified_ms(&meta)));
+        if out.len() >= config.max_files {
+            break;
+        }
+    }
     out
 }
 
-fn walk_inner(root: &std::path::Path, dir: &std::path::Path, out: &mut Vec<(String, PathBuf, u64)>) {
-    let entries = match std::fs::read_dir(dir) {
-        Ok(e) => e,
-        Err(_) => return,
-    };
-    for entry in entries.filter_map(|e| e.ok()) {
-        let name = entry.file_name();
-        let name_str = name.to_string_lossy();
-        if name_str.starts_with('.') {
-            continue;
+/// The ignore files in `dir` itself.
+fn dir_rules(dir: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for name in IGNORE_FILES {
+        let file = dir.join(name);
+        if file.exists() {
+            builder.add(file);
         }
-        if SKIP_DIRS.contains(&name_str.as_ref()) {
-            continue;
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Whether single paths under a root are left out of walks, for change events.
+/// Reads the ignore files of the root and of every directory below it, like
+/// `walk_dir`; the watcher makes a new filter when one of them changes.
+pub(crate) struct WalkFilter {
+    root: PathBuf,
+    user: UserIgnores,
+    /// Ignore files per directory, read the first time a path below it is checked.
+    dirs: Mutex<HashMap<PathBuf, Gitignore>>,
+}
+
+impl WalkFilter {
+    pub(crate) fn new(root: &Path) -> Self {
+        WalkFilter {
+            root: root.to_path_buf(),
+            user: UserIgnores::new(root, &walk_config()),
+            dirs: Mutex::new(HashMap::new()),
         }
-        let path = entry.path();
-        let meta = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
-        if meta.is_dir() {
-            walk_inner(root, &path, out);
-        } else {
-            let modified_ms = meta
-                .modified()
-                .ok()
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_millis() as u64)
-                .unwrap_or(0);
-            let rel = path
-                .strip_prefix(root)
-                .unwrap_or(&path)
-                .to_string_lossy()
-                .to_string();
-            out.push((rel, path, modified_ms));
+    }
+
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.root) else { return true };
+        if rel.components().any(|c| is_skipped(&c.as_os_str().to_string_lossy())) {
+            return true;
         }
+        let is_dir = path.is_dir();
+        if self.user.matched_or_any_parents(path, is_dir) {
+            return true;
+        }
+        // As in git, the deepest ignore file with a rule for the path decides.
+        let mut dirs = self.dirs.lock().unwrap();
+        for dir in path.ancestors().skip(1).take_while(|d| d.starts_with(&self.root)) {
+            let rules = dirs.entry(dir.to_path_buf()).or_insert_with(|| dir_rules(dir));
+            match rules.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
     }
 }
 
+#[tauri::command]
+pub(crate) async fn get_walk_config() -> Result<WalkConfig, String> {
+    Ok(walk_config())
+}
+
+#[tauri::command]
+pub(crate) async fn set_walk_config(app: tauri::AppHandle, mut config: WalkConfig) -> Result<WalkConfig, String> {
+    config.max_depth = config.max_depth.max(1);
+    config.max_files = config.max_files.max(1);
+    crate::storage::save_json(&app, WALK_CONFIG_FILE, &config)?;
+    *walk_config_lock().write().unwrap() = config.clone();
+    Ok(config)
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileEntry {
@@ -76,8 +259,8 @@ pub struct FileEntry {
 
 #[tauri::command]
 pub async fn list_dir_files(path: String) -> Result<Vec<FileEntry>, String> {
-    let expanded = expand_tilde(&path);
-    let entries = std::fs::read_dir(&expanded).map_err(|e| format!("{}: {}", expanded, e))?;
+    let dir = crate::sandbox::check(&path)?;
+    let entries = std::fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
     let mut files: Vec<FileEntry> = entries
         .filter_map(|e| e.ok())
         .filter_map(|entry| {
@@ -97,52 +280,64 @@ pub async fn list_dir_files(path: String) -> Result<Vec<FileEntry>, String> {
             })
         })
         .collect();
-    files.sort_by(|a, b| b.modified_ms.cmp(&a.modified_ms));
+    files.sort_by_key(|f| std::cmp::Reverse(f.modified_ms));
     files.truncate(200);
     Ok(files)
 }
 
+/// All files under `path`, newest first. The directory is watched from the first
+/// call on, so later calls read the watcher's cache instead of walking.
 #[tauri::command]
-pub async fn list_dir_files_recursive(path: String) -> Result<Vec<FileEntry>, String> {
-    let expanded = expand_tilde(&path);
-    let root = std::path::Path::new(&expanded);
-    let mut files: Vec<FileEntry> = Vec::new();
-
-    if root.exists() {
-        let walked = walk_dir(root);
-        for (rel, abs_path, modified_ms) in walked {
-            let meta = match std::fs::metadata(&abs_path) {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-            files.push(FileEntry {
-                name: rel,
-                path: abs_path.to_string_lossy().to_string(),
-                modified_ms,
-                size_bytes: meta.len(),
-                is_dir: false,
-            });
-        }
-    }
-    files.sort_by(|a, b| b.modified_ms.cmp(&a.modified_ms));
+pub(crate) async fn list_dir_files_recursive(
+    watcher: tauri::State<'_, crate::watcher::FileWatcher>,
+    path: String,
+) -> Result<Vec<FileEntry>, String> {
+    let root = crate::sandbox::check(&path)?;
+    let root = root.as_path();
+    let cached = watcher.watch(root, None).ok().and_then(|_| watcher.files(root));
+    let mut files = match cached {
+        Some(files) => files,
+        None if root.exists() => walk_dir(root)
+            .into_iter()
+            .filter_map(|(rel, abs_path, modified_ms)| {
+                let meta = std::fs::metadata(&abs_path).ok()?;
+                Some(FileEntry {
+                    name: rel,
+                    path: abs_path.to_string_lossy().to_string(),
+                    modified_ms,
+                    size_bytes: meta.len(),
+                    is_dir: false,
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    files.sort_by_key(|f| std::cmp::Reverse(f.modified_ms));
     files.truncate(500);
     Ok(files)
 }
 
 #[tauri::command]
 pub async fn read_file_content(path: String) -> Result<String, String> {
-    let expanded = expand_tilde(&path);
-    let meta = std::fs::metadata(&expanded).map_err(|e| e.to_string())?;
+    let path = crate::sandbox::check(&path)?;
+    let meta = std::fs::metadata(&path).map_err(|e| e.to_string())?;
     if meta.len() > 2_000_000 {
         return Err("File too large (>2MB)".to_string());
     }
-    std::fs::read_to_string(&expanded).map_err(|e| e.to_string())
+    std::fs::read_to_string(&path).map_err(|e| e.to_string())
+}
+
+/// Typed, ranged preview for files `read_file_content` cannot show: large, non-UTF-8 or binary.
+#[tauri::command]
+pub(crate) async fn preview_file(path: String, range: Option<crate::preview::PreviewRange>) -> Result<crate::preview::FilePreview, String> {
+    let path = crate::sandbox::check(&path)?;
+    crate::preview::preview(&path, &range.unwrap_or_default())
 }
 
 #[tauri::command]
 pub async fn open_file(path: String) -> Result<(), String> {
-    let expanded = expand_tilde(&path);
-    open::that(expanded).map_err(|e| e.to_string())
+    let path = crate::sandbox::check_openable(&path)?;
+    open::that(path).map_err(|e| e.to_string())
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -153,7 +348,7 @@ pub struct GitCommit {
     pub message: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GitInfo {
     pub is_git_repo: bool,
@@ -162,66 +357,67 @@ pub struct GitInfo {
     pub staged: Vec<String>,
     pub unstaged: Vec<String>,
     pub file_statuses: std::collections::HashMap<String, String>,
+    /// Typed status, including rename sources and conflicts.
+    pub entries: Vec<StatusEntry>,
+    pub remotes: Vec<RemoteInfo>,
 }
 
 #[tauri::command]
 pub async fn get_git_info(path: String) -> Result<GitInfo, String> {
-    use std::process::Command;
-    let expanded = expand_tilde(&path);
-
-    let is_git = Command::new("git")
-        .args(["-C", &expanded, "rev-parse", "--git-dir"])
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false);
-
-    if !is_git {
-        return Ok(GitInfo { is_git_repo: false, branch: String::new(), commits: vec![], staged: vec![], unstaged: vec![], file_statuses: Default::default() });
-    }
+    let dir = crate::sandbox::check(&path)?;
+    let Some(repo) = Repo::in_workspace(&dir) else {
+        return Ok(GitInfo::default());
+    };
 
-    let branch = Command::new("git")
-        .args(["-C", &expanded, "branch", "--show-current"])
-        .output()
-        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
-        .unwrap_or_default();
-
-    let log_out = Command::new("git")
-        .args(["-C", &expanded, "log", "--format=%h\t%ar\t%s", "-20"])
-        .output()
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .unwrap_or_default();
-
-    let commits = log_out.lines()
-        .filter_map(|line| {
-            let parts: Vec<&str> = line.splitn(3, '\t').collect();
-            if parts.len() == 3 {
-                Some(GitCommit { hash: parts[0].to_string(), time_ago: parts[1].to_string(), message: parts[2].to_string() })
-            } else { None }
-        })
+    let now_ms = crate::time::now_millis();
+    let commits = repo
+        .log(20)?
+        .into_iter()
+        .map(|c| GitCommit { hash: c.short_id, time_ago: crate::time::format_relative(c.time_s.max(0) as u64 * 1000, now_ms), message: c.summary })
         .collect();
 
-    let status_out = Command::new("git")
-        .args(["-C", &expanded, "status", "--porcelain"])
-        .output()
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .unwrap_or_default();
-
+    let entries = repo.status()?;
     let mut staged = Vec::new();
     let mut unstaged = Vec::new();
     let mut file_statuses: std::collections::HashMap<String, String> = Default::default();
-    for line in status_out.lines() {
-        if line.len() < 3 { continue; }
-        let x = line.chars().next().unwrap_or(' ');
-        let y = line.chars().nth(1).unwrap_or(' ');
-        let file = line[3..].to_string();
-        if x != ' ' && x != '?' { staged.push(format!("{} {}", x, file)); }
-        if y != ' ' && y != '?' { unstaged.push(format!("{} {}", y, file)); }
-        if x == '?' { unstaged.push(format!("? {}", file)); }
-        let status_code = if x != ' ' && x != '?' { x.to_string() } else if y != ' ' && y != '?' { y.to_string() } else { "?".to_string() };
-        file_statuses.insert(file, status_code);
+    for entry in &entries {
+        let file = match &entry.old_path {
+            Some(old) => format!("{} -> {}", old, entry.path),
+            None => entry.path.clone(),
+        };
+        let status_code = if entry.is_conflicted() {
+            staged.push(format!("U {}", file));
+            'U'
+        } else {
+            match (entry.staged, entry.unstaged) {
+                (_, Some(ChangeKind::Untracked)) => {
+                    unstaged.push(format!("? {}", file));
+                    '?'
+                }
+                (x, y) => {
+                    if let Some(x) = x {
+                        staged.push(format!("{} {}", x.code(), file));
+                    }
+                    if let Some(y) = y {
+                        unstaged.push(format!("{} {}", y.code(), file));
+                    }
+                    x.or(y).map_or('?', ChangeKind::code)
+                }
+            }
+        };
+        file_statuses.insert(entry.path.clone(), status_code.to_string());
     }
 
-    Ok(GitInfo { is_git_repo: true, branch, commits, staged, unstaged, file_statuses })
+    Ok(GitInfo {
+        is_git_repo: true,
+        branch: repo.branch(),
+        commits,
+        staged,
+        unstaged,
+        file_statuses,
+        entries,
+        remotes: repo.remotes()?,
+    })
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -234,79 +430,157 @@ pub struct ActivityEntry {
     pub message: String,
 }
 
-fn collect_git_log(dir: &str, genre: &str, entries: &mut Vec<ActivityEntry>) {
-    use std::process::Command;
-    let log_out = Command::new("git")
-        .args(["-C", dir, "log", "--format=%h\t%at\t%ar\t%s", "-30"])
-        .output()
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .unwrap_or_default();
-    for line in log_out.lines() {
-        let parts: Vec<&str> = line.splitn(4, '\t').collect();
-        if parts.len() == 4 {
-            let timestamp_ms = parts[1].parse::<u64>().unwrap_or(0) * 1000;
-            entries.push(ActivityEntry {
-                genre: genre.to_string(),
-                hash: parts[0].to_string(),
-                timestamp_ms,
-                time_ago: parts[2].to_string(),
-                message: parts[3].to_string(),
-            });
+fn collect_git_log(repo: &Repo, genre: &str, entries: &mut Vec<ActivityEntry>) {
+    let commits = match repo.log(30) {
+        Ok(commits) => commits,
+        Err(e) => {
+            eprintln!("git log ({}): {}", genre, e);
+            return;
         }
+    };
+    let now_ms = crate::time::now_millis();
+    for c in commits {
+        entries.push(ActivityEntry {
+            genre: genre.to_string(),
+            hash: c.short_id,
+            timestamp_ms: c.time_s.max(0) as u64 * 1000,
+            time_ago: crate::time::format_relative(c.time_s.max(0) as u64 * 1000, now_ms),
+            message: c.summary,
+        });
     }
 }
 
-fn is_git_repo(dir: &str) -> bool {
-    use std::process::Command;
-    Command::new("git")
-        .args(["-C", dir, "rev-parse", "--git-dir"])
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-}
-
 #[tauri::command]
 pub async fn get_all_git_activity(dirs: Vec<String>, genres: Vec<String>) -> Result<Vec<ActivityEntry>, String> {
     let mut entries: Vec<ActivityEntry> = Vec::new();
 
     for (dir, genre) in dirs.iter().zip(genres.iter()) {
-        let expanded = expand_tilde(dir);
+        // Dirs outside the workspace roots (or not created yet) have no activity to show.
+        let Ok(dir) = crate::sandbox::check(dir) else { continue };
 
-        if is_git_repo(&expanded) {
+        if let Some(repo) = Repo::in_workspace(&dir) {
             // Dir is itself a git repo — use directly
-            collect_git_log(&expanded, genre, &mut entries);
+            collect_git_log(&repo, genre, &mut entries);
         } else {
             // Not a git repo — scan one level of subdirs for git repos
-            if let Ok(read) = std::fs::read_dir(&expanded) {
+            if let Ok(read) = std::fs::read_dir(&dir) {
                 for entry in read.filter_map(|e| e.ok()) {
                     if !entry.metadata().map(|m| m.is_dir()).unwrap_or(false) { continue; }
                     let name = entry.file_name();
                     let name_str = name.to_string_lossy();
                     if name_str.starts_with('.') { continue; }
-                    let sub = entry.path().to_string_lossy().to_string();
-                    if is_git_repo(&sub) {
-                        collect_git_log(&sub, genre, &mut entries);
+                    let Ok(sub) = crate::sandbox::check(&entry.path().to_string_lossy()) else { continue };
+                    if let Some(repo) = Repo::in_workspace(&sub) {
+                        collect_git_log(&repo, genre, &mut entries);
                     }
                 }
             }
         }
     }
 
-    entries.sort_by(|a, b| b.timestamp_ms.cmp(&a.timestamp_ms));
+    entries.sort_by_key(|e| std::cmp::Reverse(e.timestamp_ms));
     entries.truncate(100);
     Ok(entries)
 }
 
 #[tauri::command]
 pub async fn get_git_diff(path: String, hash: String) -> Result<String, String> {
-    use std::process::Command;
-    let expanded = expand_tilde(&path);
-    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+    let dir = crate::sandbox::check(&path)?;
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
         return Err("Invalid hash".to_string());
     }
-    let out = Command::new("git")
-        .args(["-C", &expanded, "show", "--patch", "--stat", &hash])
-        .output()
-        .map_err(|e| e.to_string())?;
-    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    let repo = Repo::in_workspace(&dir).ok_or_else(|| format!("{}: not a git repository", dir.display()))?;
+    repo.show(&hash)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UncommittedDiff {
+    pub files: Vec<FileDiffStat>,
+    pub total_insertions: u64,
+    pub total_deletions: u64,
+}
+
+#[tauri::command]
+pub async fn get_uncommitted_diff(path: String) -> Result<UncommittedDiff, String> {
+    let dir = crate::sandbox::check(&path)?;
+    let mut diff = UncommittedDiff { files: vec![], total_insertions: 0, total_deletions: 0 };
+    let Some(repo) = Repo::in_workspace(&dir) else {
+        return Ok(diff);
+    };
+    diff.files = repo.uncommitted_stats()?;
+    diff.total_insertions = diff.files.iter().map(|f| f.insertions).sum();
+    diff.total_deletions = diff.files.iter().map(|f| f.deletions).sum();
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-files-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        canonical(&dir)
+    }
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn filter_honours_nested_ignore_files() {
+        let root = temp_root("nested");
+        write(&root.join(".gitignore"), "*.log\n");
+        write(&root.join("app/.gitignore"), "generated/\n!keep.log\n");
+        for file in ["a.log", "a.txt", "app/keep.log", "app/other.log", "app/generated/x.rs", "app/src/main.rs"] {
+            write(&root.join(file), "");
+        }
+        let filter = WalkFilter::new(&root);
+        assert!(filter.excludes(&root.join("a.log")));
+        assert!(!filter.excludes(&root.join("a.txt")));
+        assert!(!filter.excludes(&root.join("app/keep.log")));
+        assert!(filter.excludes(&root.join("app/other.log")));
+        assert!(filter.excludes(&root.join("app/generated/x.rs")));
+        assert!(!filter.excludes(&root.join("app/src/main.rs")));
+        assert!(filter.excludes(&root.join(".git/config")));
+
+        // Same answer as a walk.
+        let walked: Vec<String> = walk_dir(&root).into_iter().map(|(rel, _, _)| rel).collect();
+        assert!(walked.contains(&"app/keep.log".to_string()));
+        assert!(!walked.contains(&"app/generated/x.rs".to_string()));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn workspace_roots_match_in_canonical_form() {
+        let root = temp_root("workspace");
+        write(&root.join("proj/dist.bin"), "");
+        let config = WalkConfig {
+            workspaces: vec![WorkspaceIgnore {
+                root: root.join("proj/../proj").to_string_lossy().to_string(),
+                ignore: vec!["*.bin".to_string()],
+            }],
+            ..WalkConfig::default()
+        };
+        let user = UserIgnores::new(&root.join("proj/."), &config);
+        assert!(user.matched(&root.join("proj/./dist.bin"), false));
+        assert!(user.matched_or_any_parents(&root.join("proj/./dist.bin"), false));
+
+        #[cfg(unix)]
+        {
+            let link = root.join("link");
+            std::os::unix::fs::symlink(root.join("proj"), &link).unwrap();
+            let user = UserIgnores::new(&link, &config);
+            assert!(user.matched(&link.join("dist.bin"), false));
+        }
+
+        // A sibling with a common name prefix is not inside the workspace.
+        write(&root.join("proj2/dist.bin"), "");
+        let user = UserIgnores::new(&root.join("proj2"), &config);
+        assert!(!user.matched(&root.join("proj2/dist.bin"), false));
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
@@ -0,0 +1,462 @@
+// In-process git access (libgit2) for the Output view, analysis and CONTROL
+// chat. Replaces spawning `git` several times per request and parsing its
+// porcelain: status entries are typed, so renames and conflicts survive, and
+// failures surface as errors instead of empty output. Discovery never looks
+// above the workspace root, so a repository enclosing the root (a dotfiles
+// repo in `~`, say) is not exposed.
+
+use git2::{
+    Delta, DiffFindOptions, DiffFormat, DiffOptions, DiffStatsFormat, Oid, Repository, RepositoryOpenFlags, Sort, Status,
+    StatusOptions,
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// Stat graph width, as `git diff --stat` uses on an 80-column terminal.
+const STAT_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChanged,
+    Untracked,
+    Conflicted,
+}
+
+impl ChangeKind {
+    /// The `git status --short` letter.
+    pub fn code(self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+            ChangeKind::Renamed => 'R',
+            ChangeKind::TypeChanged => 'T',
+            ChangeKind::Untracked => '?',
+            ChangeKind::Conflicted => 'U',
+        }
+    }
+}
+
+/// One path in `git status`. Paths are relative to the repository root.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusEntry {
+    pub path: String,
+    /// Where a renamed file came from.
+    pub old_path: Option<String>,
+    /// Change staged in the index, relative to HEAD.
+    pub staged: Option<ChangeKind>,
+    /// Change in the working tree, relative to the index.
+    pub unstaged: Option<ChangeKind>,
+}
+
+impl StatusEntry {
+    pub fn is_conflicted(&self) -> bool {
+        self.staged == Some(ChangeKind::Conflicted)
+    }
+
+    /// `XY path` (or `XY old -> new`), as `git status --short` prints it.
+    pub fn short(&self) -> String {
+        let (x, y) = match (self.staged, self.unstaged) {
+            (_, Some(ChangeKind::Untracked)) => ('?', '?'),
+            (Some(ChangeKind::Conflicted), _) => ('U', 'U'),
+            (x, y) => (x.map_or(' ', ChangeKind::code), y.map_or(' ', ChangeKind::code)),
+        };
+        match &self.old_path {
+            Some(old) => format!("{}{} {} -> {}", x, y, old, self.path),
+            None => format!("{}{} {}", x, y, self.path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitInfo {
+    pub id: String,
+    pub short_id: String,
+    pub time_s: i64,
+    pub author: String,
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiffStat {
+    pub path: String,
+    pub old_path: Option<String>,
+    pub insertions: u64,
+    pub deletions: u64,
+    pub binary: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteInfo {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+pub(crate) struct Repo(Repository);
+
+fn err(e: git2::Error) -> String {
+    e.message().to_string()
+}
+
+fn path_of(file: git2::DiffFile) -> Option<String> {
+    file.path().map(|p| p.to_string_lossy().to_string())
+}
+
+fn kind(delta: Delta) -> Option<ChangeKind> {
+    match delta {
+        Delta::Added | Delta::Copied => Some(ChangeKind::Added),
+        Delta::Modified => Some(ChangeKind::Modified),
+        Delta::Deleted => Some(ChangeKind::Deleted),
+        Delta::Renamed => Some(ChangeKind::Renamed),
+        Delta::Typechange => Some(ChangeKind::TypeChanged),
+        Delta::Untracked => Some(ChangeKind::Untracked),
+        Delta::Conflicted => Some(ChangeKind::Conflicted),
+        Delta::Unmodified | Delta::Ignored | Delta::Unreadable => None,
+    }
+}
+
+impl Repo {
+    /// The repository containing `dir` at or below `root`, or None when there is none.
+    pub(crate) fn discover(dir: &Path, root: &Path) -> Option<Repo> {
+        // libgit2 stops before entering a ceiling directory, so the ceiling is the root's parent.
+        let ceiling = root.parent()?;
+        Repository::open_ext(dir, RepositoryOpenFlags::empty(), [ceiling]).ok().map(Repo)
+    }
+
+    /// `discover` up to the workspace root containing the canonical `dir`.
+    pub(crate) fn in_workspace(dir: &Path) -> Option<Repo> {
+        Repo::discover(dir, &crate::sandbox::root_of(dir)?)
+    }
+
+    /// The checked-out branch; empty when HEAD is detached.
+    pub(crate) fn branch(&self) -> String {
+        match self.0.head() {
+            Ok(head) if head.is_branch() => head.shorthand().unwrap_or_default().to_string(),
+            Ok(_) => String::new(),
+            // Before the first commit HEAD points at a branch that does not exist yet.
+            Err(_) => self
+                .0
+                .find_reference("HEAD")
+                .ok()
+                .and_then(|r| r.symbolic_target().map(|t| t.trim_start_matches("refs/heads/").to_string()))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn head_tree(&self) -> Option<git2::Tree<'_>> {
+        self.0.head().ok()?.peel_to_tree().ok()
+    }
+
+    /// The newest `limit` commits reachable from HEAD.
+    pub(crate) fn log(&self, limit: usize) -> Result<Vec<CommitInfo>, String> {
+        if self.0.head().is_err() {
+            return Ok(Vec::new());
+        }
+        let mut walk = self.0.revwalk().map_err(err)?;
+        walk.set_sorting(Sort::TIME).map_err(err)?;
+        walk.push_head().map_err(err)?;
+        let mut commits = Vec::new();
+        for oid in walk.take(limit) {
+            let commit = self.0.find_commit(oid.map_err(err)?).map_err(err)?;
+            let id = commit.id().to_string();
+            commits.push(CommitInfo {
+                short_id: id[..7].to_string(),
+                id,
+                time_s: commit.time().seconds(),
+                author: commit.author().name().unwrap_or_default().to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(commits)
+    }
+
+    pub(crate) fn status(&self) -> Result<Vec<StatusEntry>, String> {
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .renames_head_to_index(true)
+            .renames_index_to_workdir(true);
+        let statuses = self.0.statuses(Some(&mut opts)).map_err(err)?;
+        let mut entries = Vec::new();
+        for entry in statuses.iter() {
+            let status = entry.status();
+            if status.contains(Status::CONFLICTED) {
+                entries.push(StatusEntry {
+                    path: entry.path().unwrap_or_default().to_string(),
+                    old_path: None,
+                    staged: Some(ChangeKind::Conflicted),
+                    unstaged: None,
+                });
+                continue;
+            }
+            let staged = entry.head_to_index();
+            let unstaged = entry.index_to_workdir();
+            // The newest name, and the name in HEAD when it differs.
+            let path = unstaged
+                .as_ref()
+                .or(staged.as_ref())
+                .and_then(|d| path_of(d.new_file()))
+                .or_else(|| entry.path().map(str::to_string))
+                .unwrap_or_default();
+            let old_path = [staged.as_ref(), unstaged.as_ref()]
+                .into_iter()
+                .flatten()
+                .find(|d| d.status() == Delta::Renamed)
+                .and_then(|d| path_of(d.old_file()));
+            entries.push(StatusEntry {
+                path,
+                old_path,
+                staged: staged.and_then(|d| kind(d.status())),
+                unstaged: unstaged.and_then(|d| kind(d.status())),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Staged and unstaged changes against HEAD (everything before the first commit).
+    fn worktree_diff(&self) -> Result<git2::Diff<'_>, String> {
+        let mut opts = DiffOptions::new();
+        opts.include_untracked(false);
+        let mut diff = self
+            .0
+            .diff_tree_to_workdir_with_index(self.head_tree().as_ref(), Some(&mut opts))
+            .map_err(err)?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true))).map_err(err)?;
+        Ok(diff)
+    }
+
+    fn file_stats(diff: &git2::Diff) -> Result<Vec<FileDiffStat>, String> {
+        let mut files = Vec::new();
+        for i in 0..diff.deltas().len() {
+            let Some(patch) = git2::Patch::from_diff(diff, i).map_err(err)? else { continue };
+            let delta = patch.delta();
+            let (_, insertions, deletions) = patch.line_stats().map_err(err)?;
+            files.push(FileDiffStat {
+                path: path_of(delta.new_file()).or_else(|| path_of(delta.old_file())).unwrap_or_default(),
+                old_path: (delta.status() == Delta::Renamed).then(|| path_of(delta.old_file())).flatten(),
+                insertions: insertions as u64,
+                deletions: deletions as u64,
+                binary: delta.flags().is_binary(),
+            });
+        }
+        Ok(files)
+    }
+
+    /// Per-file line counts of the uncommitted changes.
+    pub(crate) fn uncommitted_stats(&self) -> Result<Vec<FileDiffStat>, String> {
+        Self::file_stats(&self.worktree_diff()?)
+    }
+
+    /// `git diff --stat HEAD` text; empty when nothing changed.
+    pub(crate) fn uncommitted_stat_text(&self) -> Result<String, String> {
+        let stats = self.worktree_diff()?.stats().map_err(err)?;
+        if stats.files_changed() == 0 {
+            return Ok(String::new());
+        }
+        let buf = stats.to_buf(DiffStatsFormat::FULL, STAT_WIDTH).map_err(err)?;
+        Ok(buf.as_str().unwrap_or_default().trim_end().to_string())
+    }
+
+    /// `git diff HEAD` as a unified patch.
+    pub(crate) fn uncommitted_patch(&self) -> Result<String, String> {
+        patch_text(&self.worktree_diff()?)
+    }
+
+    /// `git show --stat --patch <rev>`: header, stat and patch against the first parent.
+    pub(crate) fn show(&self, rev: &str) -> Result<String, String> {
+        let oid = match Oid::from_str(rev) {
+            Ok(oid) if rev.len() == 40 => oid,
+            _ => self.0.revparse_single(rev).map_err(err)?.id(),
+        };
+        let commit = self.0.find_commit(oid).map_err(err)?;
+        let parent_tree = commit.parents().next().map(|p| p.tree()).transpose().map_err(err)?;
+        let tree = commit.tree().map_err(err)?;
+        let mut diff = self.0.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None).map_err(err)?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true))).map_err(err)?;
+
+        let author = commit.author();
+        let when = crate::time::format_local(commit.time().seconds().max(0) as u64 * 1000);
+        let message: String = commit
+            .message()
+            .unwrap_or_default()
+            .trim_end()
+            .lines()
+            .map(|l| format!("    {}\n", l))
+            .collect();
+        let stat = diff.stats().and_then(|s| s.to_buf(DiffStatsFormat::FULL, STAT_WIDTH)).map_err(err)?;
+        Ok(format!(
+            "commit {}\nAuthor: {} <{}>\nDate:   {}\n\n{}\n{}\n{}",
+            commit.id(),
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default(),
+            when,
+            message,
+            stat.as_str().unwrap_or_default(),
+            patch_text(&diff)?
+        ))
+    }
+
+    pub(crate) fn remotes(&self) -> Result<Vec<RemoteInfo>, String> {
+        let names = self.0.remotes().map_err(err)?;
+        Ok(names
+            .iter()
+            .flatten()
+            .filter_map(|name| {
+                let remote = self.0.find_remote(name).ok()?;
+                Some(RemoteInfo { name: name.to_string(), url: remote.url().map(str::to_string) })
+            })
+            .collect())
+    }
+}
+
+fn patch_text(diff: &git2::Diff) -> Result<String, String> {
+    let mut out = String::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            out.push(line.origin());
+        }
+        out.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })
+    .map_err(err)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{IndexAddOption, Signature};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-git-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn write(dir: &Path, rel: &str, text: &str) {
+        let path = dir.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    /// Stage everything in the work tree and commit it on HEAD.
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap()
+    }
+
+    fn init(dir: &Path) -> (Repository, Repo) {
+        (Repository::init(dir).unwrap(), Repo(Repository::open(dir).unwrap()))
+    }
+
+    #[test]
+    fn discovery_stops_at_the_workspace_root() {
+        let base = temp_dir("ceiling");
+        Repository::init(&base).unwrap();
+        std::fs::create_dir_all(base.join("work/sub")).unwrap();
+        let work = base.join("work");
+        assert!(Repository::discover(work.join("sub")).is_ok());
+        assert!(Repo::discover(&work.join("sub"), &work).is_none());
+        assert!(Repo::discover(&work, &work).is_none());
+
+        Repository::init(&work).unwrap();
+        let repo = Repo::discover(&work.join("sub"), &work).unwrap();
+        assert_eq!(repo.0.workdir().unwrap().canonicalize().unwrap(), work);
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn staged_rename_keeps_the_old_path() {
+        let dir = temp_dir("rename");
+        let (git, repo) = init(&dir);
+        write(&dir, "notes.md", "line one\nline two\nline three\n");
+        commit_all(&git, "add notes");
+        std::fs::rename(dir.join("notes.md"), dir.join("docs.md")).unwrap();
+        let mut index = git.index().unwrap();
+        index.remove_path(Path::new("notes.md")).unwrap();
+        index.add_path(Path::new("docs.md")).unwrap();
+        index.write().unwrap();
+
+        let entries = repo.status().unwrap();
+        assert_eq!(entries.len(), 1, "{:?}", entries);
+        assert_eq!(entries[0].path, "docs.md");
+        assert_eq!(entries[0].old_path.as_deref(), Some("notes.md"));
+        assert_eq!(entries[0].staged, Some(ChangeKind::Renamed));
+        assert_eq!(entries[0].short(), "R  notes.md -> docs.md");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn merge_conflicts_are_reported_once() {
+        let dir = temp_dir("conflict");
+        let (git, repo) = init(&dir);
+        write(&dir, "plan.txt", "base\n");
+        let base = commit_all(&git, "base");
+        write(&dir, "plan.txt", "ours\n");
+        commit_all(&git, "ours");
+
+        // A sibling commit of "ours" changing the same line.
+        let blob = git.blob(b"theirs\n").unwrap();
+        let base_commit = git.find_commit(base).unwrap();
+        let mut builder = git.treebuilder(Some(&base_commit.tree().unwrap())).unwrap();
+        builder.insert("plan.txt", blob, 0o100644).unwrap();
+        let tree = git.find_tree(builder.write().unwrap()).unwrap();
+        let sig = Signature::now("Test", "test@example.com").unwrap();
+        let theirs = git.commit(None, &sig, &sig, "theirs", &tree, &[&base_commit]).unwrap();
+        git.merge(&[&git.find_annotated_commit(theirs).unwrap()], None, None).unwrap();
+
+        let entries = repo.status().unwrap();
+        assert_eq!(entries.len(), 1, "{:?}", entries);
+        assert!(entries[0].is_conflicted());
+        assert_eq!(entries[0].path, "plan.txt");
+        assert_eq!(entries[0].short(), "UU plan.txt");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn uncommitted_patch_covers_staged_and_unstaged_changes() {
+        let dir = temp_dir("patch");
+        let (git, repo) = init(&dir);
+        write(&dir, "a.txt", "one\ntwo\n");
+        write(&dir, "b.txt", "keep\n");
+        commit_all(&git, "init");
+        assert_eq!(repo.uncommitted_patch().unwrap(), "");
+
+        write(&dir, "a.txt", "one\n2\n");
+        write(&dir, "b.txt", "keep\nadded\n");
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new("b.txt")).unwrap();
+        index.write().unwrap();
+        write(&dir, "untracked.txt", "not in the diff\n");
+
+        let patch = repo.uncommitted_patch().unwrap();
+        assert!(patch.contains("diff --git a/a.txt b/a.txt"), "{}", patch);
+        assert!(patch.contains("-two\n+2\n"), "{}", patch);
+        assert!(patch.contains("diff --git a/b.txt b/b.txt"), "{}", patch);
+        assert!(patch.contains(" keep\n+added\n"), "{}", patch);
+        assert!(!patch.contains("untracked"), "{}", patch);
+
+        let stats = repo.uncommitted_stats().unwrap();
+        let counts: Vec<(&str, u64, u64)> = stats.iter().map(|f| (f.path.as_str(), f.insertions, f.deletions)).collect();
+        assert_eq!(counts, vec![("a.txt", 1, 1), ("b.txt", 1, 0)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
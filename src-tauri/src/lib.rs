@@ -1,21 +1,58 @@
+mod actions;
 mod ai;
+mod analysis_history;
+mod attach;
+mod chat;
+#[cfg(unix)]
+mod cli;
 mod commands;
+#[cfg(unix)]
+mod control_api;
 pub mod files;
+mod git;
+#[cfg(unix)]
+mod pty_daemon;
+mod pipes;
 mod pty_manager;
+mod preview;
+mod retrieval;
+mod sandbox;
+mod search;
 mod storage;
+mod summaries;
+mod supervisor;
+mod time;
+mod triggers;
+mod usage;
+mod watcher;
 
-use crate::ai::{summarize_all_genres, chat_control};
+use crate::ai::{summarize_all_genres, chat_control, suggest_cell_name};
 use crate::commands::pty::{spawn_pty, write_pty, resize_pty, kill_pty, kill_all_ptys};
-use crate::commands::cell::{get_cells, set_theme, get_cell_cpu};
+use crate::commands::analysis::{list_analyses, get_analysis, diff_analyses, get_cell_summary_timeline};
+use crate::commands::cell::{get_cells, set_theme, get_cell_cpu, save_session_state, load_session_state};
+use crate::commands::settings::{get_app_settings, set_app_settings};
+use crate::commands::chat::{create_chat_thread, list_chat_threads, get_chat_thread, delete_chat_thread,
+                            update_chat_thread, send_chat_message, confirm_chat_action};
 use crate::commands::launch::{launch_all, launch_cell, launch_cells};
-use crate::commands::ai_cmds::{analyze, get_ai_config, set_ai_config};
-use crate::files::{list_dir_files, list_dir_files_recursive, read_file_content, open_file,
-                   get_git_info, get_all_git_activity, get_git_diff, get_uncommitted_diff};
+use crate::commands::ai_cmds::{analyze, apply_analysis_action, get_action_log, get_ai_config, set_ai_config,
+                                get_ai_usage, list_prompt_templates, preview_prompt};
+use crate::commands::pipes::{get_pipes, set_pipes, run_pipe};
+use crate::commands::sandbox::{get_workspace_roots, pick_workspace_root};
+use crate::commands::search::{search_output, search_work_files};
+use crate::commands::summaries::{summarize_cell, get_summarizer_config, set_summarizer_config};
+use crate::commands::supervisor::{get_supervisor_config, set_supervisor_config, stop_supervisor,
+                                  run_supervisor_once, get_supervisor_log};
+use crate::commands::triggers::{get_triggers, set_triggers, test_trigger};
+use crate::files::{list_dir_files, list_dir_files_recursive, read_file_content, preview_file, open_file,
+                   get_git_info, get_all_git_activity, get_git_diff, get_uncommitted_diff,
+                   get_walk_config, set_walk_config};
+
+pub(crate) use crate::time::now_millis;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tauri::Manager;
+use tauri::{Manager, RunEvent, WindowEvent};
 
 pub(crate) const MAX_CELLS: usize = 30;
 pub(crate) const DEFAULT_COLS: u16 = 80;
@@ -32,6 +69,11 @@ pub struct CellState {
     pub last_output: String,
     pub status: String,
     pub updated_at: u64,
+    /// Bytes of output read since the app started.
+    #[serde(default)]
+    pub output_bytes: u64,
+    #[serde(default)]
+    pub summary: Option<summaries::CellSummary>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +81,18 @@ pub struct AnalyzeResult {
     pub summaries: HashMap<String, String>,
     pub ideas: Vec<String>,
     pub flow: Option<FlowAnalysis>,
+    /// History id, set once the result is saved.
+    #[serde(default)]
+    pub analysis_id: Option<String>,
+    /// Problems met while parsing the model's reply; non-empty means the result may be partial.
+    #[serde(default)]
+    pub diagnostics: Vec<String>,
+    /// Token budget used for the prompt and what was truncated to fit it.
+    #[serde(default)]
+    pub context: Option<ai::ContextReport>,
+    /// "provider/model" that produced the result (see `AiConfig::fallbacks`).
+    #[serde(default)]
+    pub answered_by: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,25 +115,46 @@ pub struct FlowAnalysis {
     pub connections: Option<Vec<FlowConnection>>,
     pub human_questions: Option<Vec<String>>,
     pub changes_since_last: Option<String>,
+    #[serde(default)]
+    pub actions: Option<Vec<FlowAction>>,
 }
 
-pub(crate) struct PtySessions(pub(crate) Mutex<HashMap<String, pty_manager::PtySession>>);
-pub(crate) struct CellStateMap(pub(crate) Arc<Mutex<HashMap<String, CellState>>>);
+/// A concrete step proposed by the analysis, executed only once approved
+/// (see `actions::apply`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowAction {
+    #[serde(flatten)]
+    pub kind: FlowActionKind,
+    #[serde(default)]
+    pub reason: String,
+}
 
-pub(crate) fn now_millis() -> u64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FlowActionKind {
+    /// Submit a prompt to the agent running in a cell.
+    SendPrompt { cell_id: String, prompt: String },
+    /// Queue a task to run when the cell is free.
+    EnqueueTask { cell_id: String, task: String },
+    /// Interrupt the cell's agent (Esc) so it stops and waits for input.
+    PauseCell { cell_id: String },
+    /// Start the configured CLI tool in the cell's work dir.
+    LaunchCell { cell_id: String },
+    /// Create a new file under the output dir, e.g. `will/plan.md`.
+    CreateFile { path: String, content: String },
 }
 
+pub(crate) struct PtySessions(pub(crate) Mutex<HashMap<String, pty_manager::PtySession>>);
+pub(crate) struct CellStateMap(pub(crate) Arc<Mutex<HashMap<String, CellState>>>);
+
 fn init_cell_states(app: &tauri::AppHandle) -> HashMap<String, CellState> {
     let saved_outputs = storage::load_cell_outputs(app);
+    let mut saved_summaries = summaries::load_saved(app);
     let mut states = HashMap::new();
     for i in 0..MAX_CELLS {
         let id = format!("cell-{}", i);
         let last_output = saved_outputs.get(&id).cloned().unwrap_or_default();
+        let summary = saved_summaries.remove(&id);
         states.insert(
             id.clone(),
             CellState {
@@ -89,12 +164,27 @@ fn init_cell_states(app: &tauri::AppHandle) -> HashMap<String, CellState> {
                 last_output,
                 status: "idle".to_string(),
                 updated_at: now_millis(),
+                output_bytes: 0,
+                summary,
             },
         );
     }
     states
 }
 
+/// Handle CLI subcommands (e.g. `attach`). Returns the exit code, or None to start the GUI.
+pub fn run_cli() -> Option<i32> {
+    #[cfg(unix)]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        cli::run(&args)
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
 pub fn run() {
     dotenvy::dotenv().ok();
     if let Some(home) = dirs::home_dir() {
@@ -106,25 +196,84 @@ pub fn run() {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
-            let cell_states = init_cell_states(&app.handle());
+            let cell_states = init_cell_states(app.handle());
             let states_arc = Arc::new(Mutex::new(cell_states));
             let ai_config = storage::load_ai_config();
+            usage::init(app.handle());
+            ai::init_templates(app.handle());
+            storage::init_settings(app.handle());
             app.manage(PtySessions(Mutex::new(HashMap::new())));
             app.manage(CellStateMap(states_arc));
+            app.manage(attach::AttachRegistry::default());
+            app.manage(search::OutputHistory::default());
+            files::load_walk_config(app.handle());
+            sandbox::load(app.handle());
+            app.manage(watcher::FileWatcher::default());
+            watcher::start(app.handle());
+            app.manage(triggers::Triggers::load(app.handle()));
+            app.manage(pipes::Pipes::load(app.handle()));
+            pipes::start(app.handle().clone());
+            app.manage(supervisor::Supervisor::load(app.handle()));
+            supervisor::start(app.handle().clone());
+            app.manage(summaries::Summarizer::load(app.handle()));
+            summaries::start(app.handle().clone());
             app.manage(Mutex::new(ai_config));
+            #[cfg(unix)]
+            {
+                if pty_daemon::enabled() {
+                    pty_daemon::restore_sessions(app.handle().clone());
+                }
+                control_api::start(app.handle().clone());
+            }
             Ok(())
         })
+        .on_window_event(|window, event| {
+            // Keep cells alive for attached terminals: hide instead of quitting.
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                if window.state::<attach::AttachRegistry>().has_external_clients() {
+                    api.prevent_close();
+                    let _ = window.hide();
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             spawn_pty, write_pty, resize_pty, kill_pty, kill_all_ptys,
-            analyze, get_cells, set_theme,
+            analyze, apply_analysis_action, get_action_log, get_cells, set_theme,
+            list_analyses, get_analysis, diff_analyses, get_cell_summary_timeline,
             launch_all, launch_cell, launch_cells,
-            list_dir_files, list_dir_files_recursive, read_file_content, open_file,
+            list_dir_files, list_dir_files_recursive, read_file_content, preview_file, open_file,
             get_git_info, get_all_git_activity, get_git_diff, get_uncommitted_diff,
-            summarize_all_genres, chat_control,
-            get_ai_config, set_ai_config,
-            get_cell_cpu
+            get_walk_config, set_walk_config,
+            get_workspace_roots, pick_workspace_root,
+            summarize_all_genres, chat_control, suggest_cell_name,
+            create_chat_thread, list_chat_threads, get_chat_thread, delete_chat_thread,
+            update_chat_thread, send_chat_message, confirm_chat_action,
+            get_ai_config, set_ai_config, get_ai_usage,
+            get_app_settings, set_app_settings,
+            list_prompt_templates, preview_prompt,
+            get_cell_cpu, save_session_state, load_session_state,
+            search_output, search_work_files,
+            get_triggers, set_triggers, test_trigger,
+            get_pipes, set_pipes, run_pipe,
+            get_supervisor_config, set_supervisor_config, stop_supervisor,
+            run_supervisor_once, get_supervisor_log,
+            summarize_cell, get_summarizer_config, set_summarizer_config
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::ExitRequested { api, .. } = &event {
+                if app.state::<attach::AttachRegistry>().has_external_clients() {
+                    api.prevent_exit();
+                }
+            }
+            #[cfg(target_os = "macos")]
+            if let RunEvent::Reopen { .. } = &event {
+                for window in app.webview_windows().values() {
+                    let _ = window.show();
+                }
+            }
+        });
 }
@@ -1,5 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if let Some(code) = chaos_grid_lib::run_cli() {
+        std::process::exit(code);
+    }
     chaos_grid_lib::run()
 }
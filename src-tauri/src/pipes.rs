@@ -0,0 +1,612 @@
+// Cross-cell pipes: forward selected output of one cell into another cell.
+//
+// A pipe selects from its source cell either
+//   - the last response: the output burst the cell printed before going quiet,
+//   - regex matches on output lines (capture group 1 if present), or
+//   - files written to the cell's work dir, as reported by the file watcher,
+// optionally has the AI summarize it, and delivers it to the target cell's
+// PTY (pasted as a prompt) or its task queue. Pipes live in `<data>/pipes.json`.
+//
+// Enabled pipes may not form a loop (a → b → a, or longer): a cell's reply to
+// piped input would be piped back to it without end, so such sets are rejected.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+const PIPES_FILE: &str = "pipes.json";
+const TICK: Duration = Duration::from_millis(500);
+/// Output silence after which a burst counts as a finished response.
+const SETTLE: Duration = Duration::from_secs(3);
+const DEFAULT_MIN_INTERVAL_MS: u64 = 10_000;
+const MAX_SELECTION_CHARS: usize = 8000;
+const DEFAULT_TEMPLATE: &str = "[from {from}]\n{text}";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum PipeSource {
+    LastResponse,
+    Regex { pattern: String },
+    /// Files under the source cell's work dir whose relative path matches `pattern` (all if None).
+    File { pattern: Option<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PipeTarget {
+    Pty,
+    Queue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pipe {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub from_cell: String,
+    pub to_cell: String,
+    pub source: PipeSource,
+    pub target: PipeTarget,
+    /// Summarize the selection with the configured AI provider before sending.
+    #[serde(default)]
+    pub summarize: bool,
+    /// Message sent to the target; `{from}` and `{text}` are substituted.
+    pub template: Option<String>,
+    /// Minimum time between deliveries of this pipe; selections arriving sooner are dropped.
+    pub min_interval_ms: Option<u64>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipeConfig {
+    /// Output directory root and grid width, used to locate cell work dirs.
+    pub output_dir: String,
+    pub cols: u32,
+    pub pipes: Vec<Pipe>,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        PipeConfig { output_dir: "~/chaos-grid-output".to_string(), cols: 3, pipes: Vec::new() }
+    }
+}
+
+struct Burst {
+    text: String,
+    last_at: Instant,
+}
+
+struct State {
+    config: PipeConfig,
+    /// Compiled regex per pipe (Regex pattern or File pattern), same order as `config.pipes`.
+    patterns: Vec<Option<Regex>>,
+    bursts: HashMap<String, Burst>,
+    last_response: HashMap<String, String>,
+    last_sent: HashMap<String, Instant>,
+}
+
+pub(crate) struct Pipes(Mutex<State>);
+
+fn compile(pipe: &Pipe) -> Result<Option<Regex>, String> {
+    let pattern = match &pipe.source {
+        PipeSource::Regex { pattern } => Some(pattern),
+        PipeSource::File { pattern } => pattern.as_ref(),
+        PipeSource::LastResponse => None,
+    };
+    pattern
+        .map(|p| Regex::new(p).map_err(|e| format!("pipe '{}': invalid pattern: {}", pipe.name, e)))
+        .transpose()
+}
+
+fn validate(pipe: &Pipe) -> Result<Option<Regex>, String> {
+    if pipe.from_cell == pipe.to_cell {
+        return Err(format!("pipe '{}': source and target are the same cell", pipe.name));
+    }
+    compile(pipe)
+}
+
+/// Indices of enabled pipes that form a loop (a → b → … → a), if any.
+fn find_cycle(pipes: &[Pipe]) -> Option<Vec<usize>> {
+    fn visit(
+        cell: &str,
+        pipes: &[Pipe],
+        path: &mut Vec<usize>,
+        on_path: &mut Vec<String>,
+        done: &mut HashSet<String>,
+    ) -> Option<Vec<usize>> {
+        // path[i] is the pipe leaving on_path[i].
+        if let Some(pos) = on_path.iter().position(|c| c == cell) {
+            return Some(path[pos..].to_vec());
+        }
+        if done.contains(cell) {
+            return None;
+        }
+        on_path.push(cell.to_string());
+        for (i, pipe) in pipes.iter().enumerate() {
+            if !pipe.enabled || pipe.from_cell != cell {
+                continue;
+            }
+            path.push(i);
+            if let Some(cycle) = visit(&pipe.to_cell, pipes, path, on_path, done) {
+                return Some(cycle);
+            }
+            path.pop();
+        }
+        on_path.pop();
+        done.insert(cell.to_string());
+        None
+    }
+
+    let mut done = HashSet::new();
+    pipes
+        .iter()
+        .filter(|p| p.enabled)
+        .find_map(|p| visit(&p.from_cell, pipes, &mut Vec::new(), &mut Vec::new(), &mut done))
+}
+
+fn describe_cycle(pipes: &[Pipe], cycle: &[usize]) -> String {
+    let mut cells: Vec<&str> = cycle.iter().map(|&i| pipes[i].from_cell.as_str()).collect();
+    cells.push(&pipes[cycle[0]].from_cell);
+    format!("pipes form a loop: {}", cells.join(" → "))
+}
+
+fn truncate_tail(text: &str, limit: usize) -> String {
+    if text.len() <= limit {
+        return text.to_string();
+    }
+    let mut start = text.len() - limit;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
+
+/// The last `limit` bytes of a file as text, read without loading the rest.
+fn read_tail(path: &Path, limit: usize) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(limit as u64);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(limit as u64).read_to_end(&mut bytes)?;
+    // A cut through a multi-byte character leaves its continuation bytes first.
+    let skip = if start > 0 { bytes.iter().take(3).take_while(|&&b| b & 0xC0 == 0x80).count() } else { 0 };
+    Ok(truncate_tail(&String::from_utf8_lossy(&bytes[skip..]), limit))
+}
+
+impl Pipes {
+    pub(crate) fn load(app: &tauri::AppHandle) -> Self {
+        let mut config: PipeConfig = crate::storage::load_json(app, PIPES_FILE);
+        let mut patterns = Vec::new();
+        config.pipes.retain(|pipe| match validate(pipe) {
+            Ok(re) => {
+                patterns.push(re);
+                true
+            }
+            Err(e) => {
+                eprintln!("pipes: {}", e);
+                false
+            }
+        });
+        // Sets saved before loops were rejected: disable pipes until none is left.
+        while let Some(cycle) = find_cycle(&config.pipes) {
+            eprintln!("pipes: {}; disabling '{}'", describe_cycle(&config.pipes, &cycle), config.pipes[cycle[0]].name);
+            config.pipes[cycle[0]].enabled = false;
+        }
+        watch_sources(app, &config);
+        Pipes(Mutex::new(State {
+            config,
+            patterns,
+            bursts: HashMap::new(),
+            last_response: HashMap::new(),
+            last_sent: HashMap::new(),
+        }))
+    }
+
+    pub(crate) fn config(&self) -> PipeConfig {
+        self.0.lock().unwrap().config.clone()
+    }
+
+    /// Validate, persist and activate a new pipe set.
+    pub(crate) fn set(&self, app: &tauri::AppHandle, mut config: PipeConfig) -> Result<PipeConfig, String> {
+        let patterns = config.pipes.iter().map(validate).collect::<Result<Vec<_>, _>>()?;
+        if let Some(cycle) = find_cycle(&config.pipes) {
+            return Err(describe_cycle(&config.pipes, &cycle));
+        }
+        let stamp = crate::now_millis();
+        for (i, pipe) in config.pipes.iter_mut().enumerate() {
+            if pipe.id.is_empty() {
+                pipe.id = format!("pipe-{}-{}", stamp, i);
+            }
+        }
+        crate::storage::save_json(app, PIPES_FILE, &config)?;
+        watch_sources(app, &config);
+        let mut state = self.0.lock().unwrap();
+        state.config = config.clone();
+        state.patterns = patterns;
+        Ok(config)
+    }
+}
+
+/// The work dir of `cell_id` as the file watcher knows it (canonical), if it exists.
+/// An error when it lies outside the workspace roots.
+fn source_dir(cell: &crate::CellState, output_dir: &str, cols: usize) -> Result<Option<PathBuf>, String> {
+    let dir = crate::ai::cell_work_dir(cell, output_dir, cols)?;
+    if !Path::new(&crate::files::expand_tilde(&dir)).exists() {
+        return Ok(None);
+    }
+    crate::sandbox::check(&dir).map(Some)
+}
+
+/// Make sure the work dir of every File pipe's source cell is watched.
+fn watch_sources(app: &tauri::AppHandle, config: &PipeConfig) {
+    let cells = app.state::<crate::CellStateMap>().0.lock().unwrap().clone();
+    let watcher = app.state::<crate::watcher::FileWatcher>();
+    for pipe in &config.pipes {
+        if !pipe.enabled || !matches!(pipe.source, PipeSource::File { .. }) {
+            continue;
+        }
+        let Some(cell) = cells.get(&pipe.from_cell) else { continue };
+        let dir = match source_dir(cell, &config.output_dir, config.cols as usize) {
+            Ok(Some(dir)) => dir,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("pipe '{}': {}", pipe.name, e);
+                continue;
+            }
+        };
+        if let Err(e) = watcher.watch(&dir, Some(&pipe.from_cell)) {
+            eprintln!("pipe '{}': {}", pipe.name, e);
+        }
+    }
+}
+
+// ─── Selection ───────────────────────────────────────────────────────────────
+
+/// Feed completed output lines of `cell_id`: grows its current burst and
+/// forwards regex selections immediately.
+pub(crate) fn observe(app: &tauri::AppHandle, cell_id: &str, lines: &[String]) {
+    let selections = {
+        let pipes = app.state::<Pipes>();
+        let mut state = pipes.0.lock().unwrap();
+        if !state.config.pipes.iter().any(|p| p.enabled && p.from_cell == cell_id) {
+            return;
+        }
+        let burst = state
+            .bursts
+            .entry(cell_id.to_string())
+            .or_insert_with(|| Burst { text: String::new(), last_at: Instant::now() });
+        for line in lines {
+            burst.text.push_str(line);
+            burst.text.push('\n');
+        }
+        burst.text = truncate_tail(&burst.text, MAX_SELECTION_CHARS);
+        burst.last_at = Instant::now();
+
+        let mut selections = Vec::new();
+        for (pipe, re) in state.config.pipes.iter().zip(state.patterns.iter()) {
+            let (PipeSource::Regex { .. }, Some(re)) = (&pipe.source, re) else { continue };
+            if !pipe.enabled || pipe.from_cell != cell_id {
+                continue;
+            }
+            for line in lines {
+                if let Some(caps) = re.captures(line) {
+                    let m = caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str()).unwrap_or("");
+                    selections.push((pipe.clone(), m.to_string()));
+                }
+            }
+        }
+        selections
+    };
+    for (pipe, text) in selections {
+        deliver(app, &pipe, text, false);
+    }
+}
+
+/// Background loop: closes settled bursts into responses.
+pub(crate) fn start(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(TICK);
+        for (pipe, text) in settled_responses(&app) {
+            deliver(&app, &pipe, text, false);
+        }
+    });
+}
+
+fn settled_responses(app: &tauri::AppHandle) -> Vec<(Pipe, String)> {
+    let pipes = app.state::<Pipes>();
+    let mut state = pipes.0.lock().unwrap();
+    let settled: Vec<String> = state
+        .bursts
+        .iter()
+        .filter(|(_, b)| b.last_at.elapsed() >= SETTLE)
+        .map(|(cell, _)| cell.clone())
+        .collect();
+    let mut out = Vec::new();
+    for cell in settled {
+        let Some(burst) = state.bursts.remove(&cell) else { continue };
+        let text = burst.text.trim().to_string();
+        if text.is_empty() {
+            continue;
+        }
+        for pipe in &state.config.pipes {
+            if pipe.enabled && pipe.from_cell == cell && matches!(pipe.source, PipeSource::LastResponse) {
+                out.push((pipe.clone(), text.clone()));
+            }
+        }
+        state.last_response.insert(cell, text);
+    }
+    out
+}
+
+/// Forward files the watcher reports as added or modified under a cell's work
+/// dir to the File pipes reading that cell. One batch becomes one message.
+pub(crate) fn files_changed(app: &tauri::AppHandle, change: &crate::watcher::FilesChanged) {
+    let (file_pipes, output_dir, cols) = {
+        let pipes = app.state::<Pipes>();
+        let state = pipes.0.lock().unwrap();
+        let file_pipes: Vec<(Pipe, Option<Regex>)> = state
+            .config
+            .pipes
+            .iter()
+            .zip(state.patterns.iter())
+            .filter(|(p, _)| p.enabled && matches!(p.source, PipeSource::File { .. }))
+            .map(|(p, re)| (p.clone(), re.clone()))
+            .collect();
+        (file_pipes, state.config.output_dir.clone(), state.config.cols as usize)
+    };
+    if file_pipes.is_empty() {
+        return;
+    }
+    let root = Path::new(&change.root);
+    let cells = app.state::<crate::CellStateMap>().0.lock().unwrap().clone();
+
+    for (pipe, re) in file_pipes {
+        let Some(cell) = cells.get(&pipe.from_cell) else { continue };
+        if source_dir(cell, &output_dir, cols).ok().flatten().as_deref() != Some(root) {
+            continue;
+        }
+        let text: Vec<String> = change
+            .added
+            .iter()
+            .chain(&change.modified)
+            .filter(|rel| re.as_ref().map_or(true, |re| re.is_match(rel)))
+            .filter_map(|rel| {
+                let content = read_tail(&root.join(rel), MAX_SELECTION_CHARS).ok()?;
+                Some(format!("{}:\n{}", rel, content))
+            })
+            .collect();
+        if !text.is_empty() {
+            deliver(app, &pipe, truncate_tail(&text.join("\n\n"), MAX_SELECTION_CHARS), false);
+        }
+    }
+}
+
+// ─── Delivery ────────────────────────────────────────────────────────────────
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PipeFiredPayload {
+    pipe_id: String,
+    from_cell: String,
+    to_cell: String,
+    chars: usize,
+}
+
+fn deliver(app: &tauri::AppHandle, pipe: &Pipe, text: String, force: bool) {
+    {
+        let pipes = app.state::<Pipes>();
+        let mut state = pipes.0.lock().unwrap();
+        let interval = Duration::from_millis(pipe.min_interval_ms.unwrap_or(DEFAULT_MIN_INTERVAL_MS));
+        if !force && state.last_sent.get(&pipe.id).is_some_and(|at| at.elapsed() < interval) {
+            return;
+        }
+        state.last_sent.insert(pipe.id.clone(), Instant::now());
+    }
+    let app = app.clone();
+    let pipe = pipe.clone();
+    tauri::async_runtime::spawn(async move {
+        let text = if pipe.summarize {
+            match summarize(&app, &pipe, &text).await {
+                Ok(summary) => summary,
+                Err(e) => {
+                    eprintln!("pipe '{}': summary failed, sending raw output: {}", pipe.name, e);
+                    text
+                }
+            }
+        } else {
+            text
+        };
+        if let Err(e) = send(&app, &pipe, &text) {
+            eprintln!("pipe '{}': {}", pipe.name, e);
+        }
+    });
+}
+
+async fn summarize(app: &tauri::AppHandle, pipe: &Pipe, text: &str) -> Result<String, String> {
+    let (config, max_tokens) =
+        crate::ai::AiTask::Summarize.apply(&app.state::<Mutex<crate::storage::AiConfig>>().lock().unwrap(), 1024);
+    let prompt = format!(
+        "The following is output from terminal cell {}. Summarize what matters for the agent \
+         in cell {} to act on it: results, decisions, open problems. Reply with the summary only.\n\n{}",
+        pipe.from_cell, pipe.to_cell, text
+    );
+    crate::ai::call_ai(crate::ai::AiTask::Summarize, &config, &prompt, max_tokens).await
+}
+
+fn send(app: &tauri::AppHandle, pipe: &Pipe, text: &str) -> Result<(), String> {
+    let message = pipe
+        .template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE)
+        .replace("{from}", &pipe.from_cell)
+        .replace("{text}", text.trim());
+    match pipe.target {
+        PipeTarget::Pty => {
+            crate::commands::pty::paste_to_cell(&app.state::<crate::PtySessions>(), &pipe.to_cell, &message)?;
+        }
+        PipeTarget::Queue => crate::pty_manager::emit_task(app, &pipe.to_cell, &message),
+    }
+    let _ = app.emit(
+        "pipe-fired",
+        PipeFiredPayload {
+            pipe_id: pipe.id.clone(),
+            from_cell: pipe.from_cell.clone(),
+            to_cell: pipe.to_cell.clone(),
+            chars: message.len(),
+        },
+    );
+    Ok(())
+}
+
+/// Forward a pipe's current selection right away: the last (or in-progress)
+/// response, the newest regex match in recent output, or the newest matching file.
+pub(crate) fn run_now(app: &tauri::AppHandle, pipe_id: &str) -> Result<(), String> {
+    let (pipe, re, output_dir, cols) = {
+        let pipes = app.state::<Pipes>();
+        let state = pipes.0.lock().unwrap();
+        let i = state
+            .config
+            .pipes
+            .iter()
+            .position(|p| p.id == pipe_id)
+            .ok_or_else(|| format!("no pipe {}", pipe_id))?;
+        (
+            state.config.pipes[i].clone(),
+            state.patterns[i].clone(),
+            state.config.output_dir.clone(),
+            state.config.cols as usize,
+        )
+    };
+    let cell = app
+        .state::<crate::CellStateMap>()
+        .0
+        .lock()
+        .unwrap()
+        .get(&pipe.from_cell)
+        .cloned()
+        .ok_or_else(|| format!("unknown cell {}", pipe.from_cell))?;
+
+    let text = match &pipe.source {
+        PipeSource::LastResponse => {
+            let pipes = app.state::<Pipes>();
+            let state = pipes.0.lock().unwrap();
+            state
+                .bursts
+                .get(&pipe.from_cell)
+                .map(|b| b.text.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .or_else(|| state.last_response.get(&pipe.from_cell).cloned())
+                .unwrap_or_else(|| crate::ai::strip_ansi(&cell.last_output).trim().to_string())
+        }
+        PipeSource::Regex { .. } => {
+            let re = re.ok_or("pipe has no pattern")?;
+            let output = crate::ai::strip_ansi(&cell.last_output);
+            output
+                .lines()
+                .rev()
+                .find_map(|line| {
+                    let caps = re.captures(line)?;
+                    caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str().to_string())
+                })
+                .ok_or("no match in recent output")?
+        }
+        PipeSource::File { .. } => {
+            let dir = source_dir(&cell, &output_dir, cols)?.ok_or("work dir does not exist")?;
+            // The watcher's cached list when the dir is watched; a walk otherwise.
+            let files: Vec<(String, u64)> = match app.state::<crate::watcher::FileWatcher>().files(&dir) {
+                Some(files) => files.into_iter().map(|f| (f.name, f.modified_ms)).collect(),
+                None => crate::files::walk_dir(&dir).into_iter().map(|(rel, _, m)| (rel, m)).collect(),
+            };
+            let (rel, _) = files
+                .into_iter()
+                .filter(|(rel, _)| re.as_ref().map_or(true, |re| re.is_match(rel)))
+                .max_by_key(|(_, modified)| *modified)
+                .ok_or("no matching file in work dir")?;
+            let content = read_tail(&dir.join(&rel), MAX_SELECTION_CHARS).map_err(|e| e.to_string())?;
+            format!("{}:\n{}", rel, content)
+        }
+    };
+    if text.is_empty() {
+        return Err("nothing to send".to_string());
+    }
+    deliver(app, &pipe, text, true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipe(from: &str, to: &str) -> Pipe {
+        Pipe {
+            id: String::new(),
+            name: format!("{}->{}", from, to),
+            from_cell: from.to_string(),
+            to_cell: to.to_string(),
+            source: PipeSource::LastResponse,
+            target: PipeTarget::Queue,
+            summarize: false,
+            template: None,
+            min_interval_ms: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn chains_are_not_loops() {
+        let pipes = [pipe("a", "b"), pipe("b", "c"), pipe("a", "c")];
+        assert_eq!(find_cycle(&pipes), None);
+    }
+
+    #[test]
+    fn finds_direct_and_longer_loops() {
+        let pipes = [pipe("a", "b"), pipe("b", "a")];
+        assert_eq!(find_cycle(&pipes), Some(vec![0, 1]));
+
+        let pipes = [pipe("x", "a"), pipe("a", "b"), pipe("b", "c"), pipe("c", "a")];
+        let cycle = find_cycle(&pipes).unwrap();
+        assert_eq!(cycle, vec![1, 2, 3]);
+        assert_eq!(describe_cycle(&pipes, &cycle), "pipes form a loop: a → b → c → a");
+    }
+
+    #[test]
+    fn disabled_pipes_do_not_close_a_loop() {
+        let mut back = pipe("b", "a");
+        back.enabled = false;
+        assert_eq!(find_cycle(&[pipe("a", "b"), back]), None);
+    }
+
+    #[test]
+    fn file_sources_read_only_the_tail() {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-pipes-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("agent.log");
+
+        std::fs::write(&path, "short log\n").unwrap();
+        assert_eq!(read_tail(&path, 100).unwrap(), "short log\n");
+
+        // 3-byte characters, so a cut at most byte offsets lands mid-character.
+        let text = format!("{}{}", "é一".repeat(40_000), "the end");
+        std::fs::write(&path, &text).unwrap();
+        for limit in [100, 101, 102, 8000] {
+            let tail = read_tail(&path, limit).unwrap();
+            assert!(tail.len() <= limit && tail.len() + 4 > limit, "{} for {}", tail.len(), limit);
+            assert!(text.ends_with(&tail));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
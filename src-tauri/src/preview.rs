@@ -0,0 +1,530 @@
+// File previews for the Files tab. The type is sniffed from magic bytes and
+// a sample of the content, never the extension alone. Text is read a window
+// at a time (a byte range or a line range) and decoded lossily with the
+// detected encoding, so multi-GB logs and Shift_JIS CSVs preview as well as
+// UTF-8 source. Binaries get a hexdump of their first bytes instead.
+
+use base64::Engine;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes sniffed for the type and encoding.
+const SNIFF_BYTES: usize = 8192;
+/// Text window when no range is asked for.
+const DEFAULT_TEXT_BYTES: u64 = 64 * 1024;
+const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+const DEFAULT_HEX_BYTES: u64 = 512;
+const MAX_HEX_BYTES: u64 = 64 * 1024;
+/// Images up to this size come back inline as a data URL.
+const MAX_INLINE_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+const SCAN_BLOCK: usize = 64 * 1024;
+
+/// (prefix, format, mime). Checked in order; `None` mime means an opaque binary.
+const MAGIC: &[(&[u8], &str, Option<&str>)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG", Some("image/png")),
+    (b"\xff\xd8\xff", "JPEG", Some("image/jpeg")),
+    (b"GIF87a", "GIF", Some("image/gif")),
+    (b"GIF89a", "GIF", Some("image/gif")),
+    (b"BM", "BMP", Some("image/bmp")),
+    (b"\x00\x00\x01\x00", "ICO", Some("image/x-icon")),
+    (b"%PDF-", "PDF", Some("application/pdf")),
+    (b"PK\x03\x04", "ZIP", None),
+    (b"\x1f\x8b", "gzip", None),
+    (b"\x7fELF", "ELF", None),
+    (b"\xcf\xfa\xed\xfe", "Mach-O", None),
+    (b"MZ", "PE", None),
+    (b"\x00asm", "WebAssembly", None),
+    (b"SQLite format 3\x00", "SQLite", None),
+    (b"\x28\xb5\x2f\xfd", "zstd", None),
+    (b"7z\xbc\xaf\x27\x1c", "7z", None),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewKind {
+    Text,
+    Image,
+    Pdf,
+    Binary,
+}
+
+/// Which part of a text file to read: `offset`/`length` in bytes, or
+/// `startLine` (1-based) and `lineCount`. Binaries only honour the byte range.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PreviewRange {
+    pub offset: Option<u64>,
+    pub length: Option<u64>,
+    pub start_line: Option<usize>,
+    pub line_count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextPreview {
+    pub content: String,
+    /// WHATWG name, e.g. "UTF-8", "Shift_JIS", "UTF-16LE".
+    pub encoding: String,
+    pub has_bom: bool,
+    /// "lf", "crlf", "cr", "mixed", or "none" when the window has no line break.
+    pub line_ending: String,
+    /// Byte range of the file that `content` covers.
+    pub start_byte: u64,
+    pub end_byte: u64,
+    /// 1-based line `content` starts at, when known (line ranges and reads from the start).
+    pub start_line: Option<usize>,
+    pub lines: usize,
+    /// Some bytes could not be decoded and were replaced.
+    pub had_errors: bool,
+    pub eof: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HexPreview {
+    pub offset: u64,
+    pub length: u64,
+    /// `hexdump -C` style rows.
+    pub dump: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilePreview {
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified_ms: u64,
+    pub kind: PreviewKind,
+    /// Detected container format for images, PDFs and known binaries ("PNG", "ELF", ...).
+    pub format: Option<String>,
+    pub mime: Option<String>,
+    pub text: Option<TextPreview>,
+    pub hex: Option<HexPreview>,
+    /// `data:` URL for images small enough to inline.
+    pub data_url: Option<String>,
+}
+
+// ─── Sniffing ────────────────────────────────────────────────────────────────
+
+fn magic(head: &[u8]) -> Option<(&'static str, Option<&'static str>)> {
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some(("WebP", Some("image/webp")));
+    }
+    MAGIC
+        .iter()
+        .find(|(prefix, _, _)| head.starts_with(prefix))
+        .map(|(_, format, mime)| (*format, *mime))
+}
+
+/// UTF-16 without a BOM: mostly-Latin text has a NUL in every other byte,
+/// the high byte of each code unit.
+fn utf16_without_bom(head: &[u8]) -> Option<&'static Encoding> {
+    let pairs = head.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let (mut even, mut odd) = (0, 0);
+    for pair in head.chunks_exact(2) {
+        even += (pair[0] == 0) as usize;
+        odd += (pair[1] == 0) as usize;
+    }
+    if odd * 2 >= pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 2 >= pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// The encoding of `head` and the length of its BOM, or None when it does not look like text.
+fn detect_encoding(head: &[u8], truncated: bool) -> Option<(&'static Encoding, usize)> {
+    if let Some((encoding, bom)) = Encoding::for_bom(head) {
+        return Some((encoding, bom));
+    }
+    let encoding = if head.contains(&0) {
+        // Other text encodings have no NUL bytes; binaries that look like UTF-16
+        // fail the control character check below.
+        utf16_without_bom(head)?
+    } else {
+        // A multi-byte character cut off at the end of the sample is still UTF-8.
+        let valid = match std::str::from_utf8(head) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none() && truncated,
+        };
+        if valid {
+            UTF_8
+        } else {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(head, !truncated);
+            detector.guess(None, true)
+        }
+    };
+    let (sample, _) = encoding.decode_without_bom_handling(head);
+    let controls = sample
+        .chars()
+        .filter(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c' | '\x1b'))
+        .count();
+    if controls * 10 > sample.chars().count().max(1) {
+        return None;
+    }
+    Some((encoding, 0))
+}
+
+fn line_ending(text: &str) -> &'static str {
+    let bytes = text.as_bytes();
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    for (i, b) in bytes.iter().enumerate() {
+        match b {
+            b'\n' if i > 0 && bytes[i - 1] == b'\r' => crlf += 1,
+            b'\n' => lf += 1,
+            b'\r' if bytes.get(i + 1) != Some(&b'\n') => cr += 1,
+            _ => {}
+        }
+    }
+    match (lf > 0, crlf > 0, cr > 0) {
+        (false, false, false) => "none",
+        (true, false, false) => "lf",
+        (false, true, false) => "crlf",
+        (false, false, true) => "cr",
+        _ => "mixed",
+    }
+}
+
+// ─── Reading ─────────────────────────────────────────────────────────────────
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    let mut buf = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+/// Width of a code unit and the bytes of `\n` in it.
+fn newline_unit(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_16LE {
+        b"\n\x00"
+    } else if encoding == UTF_16BE {
+        b"\x00\n"
+    } else {
+        b"\n"
+    }
+}
+
+/// Byte offset where 1-based line `line` starts (scanning from `from`, which is
+/// the start of line 1), or the file size when the file has fewer lines.
+fn line_offset(file: &mut (impl Read + Seek), from: u64, line: usize, unit: &[u8]) -> Result<u64, String> {
+    let mut remaining = line.saturating_sub(1);
+    let mut pos = from;
+    file.seek(SeekFrom::Start(from)).map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; SCAN_BLOCK];
+    // A code unit split across two reads is carried over to the next.
+    let mut carry = Vec::new();
+    while remaining > 0 {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        carry.extend_from_slice(&buf[..n]);
+        let whole = carry.len() - carry.len() % unit.len();
+        let block_start = pos;
+        for (i, chunk) in carry[..whole].chunks_exact(unit.len()).enumerate() {
+            if chunk == unit {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Ok(block_start + ((i + 1) * unit.len()) as u64);
+                }
+            }
+        }
+        pos += whole as u64;
+        carry.drain(..whole);
+    }
+    Ok(if remaining == 0 { pos } else { file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())? })
+}
+
+/// Drop UTF-8 continuation bytes at the start of a window that begins mid-character.
+fn align_utf8_start(bytes: &[u8]) -> usize {
+    bytes.iter().take(3).take_while(|b| (**b & 0xc0) == 0x80).count()
+}
+
+/// Bytes at the end of `bytes` that start an incomplete character, to leave for the next window.
+fn incomplete_tail(bytes: &[u8], encoding: &'static Encoding) -> usize {
+    if encoding == UTF_8 {
+        match std::str::from_utf8(bytes) {
+            Err(e) if e.error_len().is_none() => bytes.len() - e.valid_up_to(),
+            _ => 0,
+        }
+    } else if encoding == UTF_16LE || encoding == UTF_16BE {
+        bytes.len() % 2
+    } else {
+        0
+    }
+}
+
+fn preview_text(
+    file: &mut File,
+    size: u64,
+    encoding: &'static Encoding,
+    bom: usize,
+    range: &PreviewRange,
+) -> Result<TextPreview, String> {
+    let unit = newline_unit(encoding);
+    let (mut start, start_line) = match (range.start_line, range.offset) {
+        (Some(line), _) => (line_offset(file, bom as u64, line.max(1), unit)?, Some(line.max(1))),
+        (None, Some(offset)) if offset > bom as u64 => (offset.min(size), None),
+        _ => (bom as u64, Some(1)),
+    };
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        start -= (start - bom as u64) % 2;
+    }
+    let len = match (range.start_line, range.line_count) {
+        (Some(_), Some(count)) => line_offset(file, start, 1 + count, unit)? - start,
+        _ => range.length.unwrap_or(DEFAULT_TEXT_BYTES),
+    }
+    .min(MAX_TEXT_BYTES);
+    let mut bytes = read_at(file, start, len)?;
+    if encoding == UTF_8 {
+        let skip = align_utf8_start(&bytes);
+        bytes.drain(..skip);
+        start += skip as u64;
+    }
+    let end_byte = start + bytes.len() as u64;
+    let tail = if end_byte < size { incomplete_tail(&bytes, encoding) } else { 0 };
+    bytes.truncate(bytes.len() - tail);
+    let end_byte = end_byte - tail as u64;
+
+    let (content, had_errors) = encoding.decode_without_bom_handling(&bytes);
+    let content = content.into_owned();
+    Ok(TextPreview {
+        encoding: encoding.name().to_string(),
+        has_bom: bom > 0,
+        line_ending: line_ending(&content).to_string(),
+        start_byte: start,
+        end_byte,
+        start_line,
+        lines: content.lines().count(),
+        had_errors,
+        eof: end_byte >= size,
+        content,
+    })
+}
+
+fn hexdump(bytes: &[u8], offset: u64) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = (0..16)
+                .map(|j| row.get(j).map_or("  ".to_string(), |b| format!("{:02x}", b)))
+                .collect();
+            let ascii: String = row
+                .iter()
+                .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+                .collect();
+            format!(
+                "{:08x}  {}  {}  |{}|",
+                offset + (i * 16) as u64,
+                hex[..8].join(" "),
+                hex[8..].join(" "),
+                ascii
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Preview `path`, which the caller has already checked against the sandbox.
+pub(crate) fn preview(path: &Path, range: &PreviewRange) -> Result<FilePreview, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let meta = file.metadata().map_err(|e| e.to_string())?;
+    if meta.is_dir() {
+        return Err(format!("{}: is a directory", path.display()));
+    }
+    let size = meta.len();
+    let head = read_at(&mut file, 0, SNIFF_BYTES as u64)?;
+    let mut preview = FilePreview {
+        path: path.to_string_lossy().to_string(),
+        size_bytes: size,
+        modified_ms: crate::files::modified_ms(&meta),
+        kind: PreviewKind::Binary,
+        format: None,
+        mime: None,
+        text: None,
+        hex: None,
+        data_url: None,
+    };
+
+    if let Some((format, mime)) = magic(&head) {
+        preview.format = Some(format.to_string());
+        preview.mime = mime.map(str::to_string);
+        preview.kind = match mime {
+            Some("application/pdf") => PreviewKind::Pdf,
+            Some(_) => PreviewKind::Image,
+            None => PreviewKind::Binary,
+        };
+    } else if let Some((encoding, bom)) = detect_encoding(&head, size > head.len() as u64) {
+        preview.kind = PreviewKind::Text;
+        preview.mime = Some("text/plain".to_string());
+        preview.text = Some(preview_text(&mut file, size, encoding, bom, range)?);
+        return Ok(preview);
+    }
+
+    if preview.kind == PreviewKind::Image && size <= MAX_INLINE_IMAGE_BYTES {
+        let bytes = read_at(&mut file, 0, size)?;
+        preview.data_url = Some(format!(
+            "data:{};base64,{}",
+            preview.mime.as_deref().unwrap_or("application/octet-stream"),
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ));
+    }
+    let offset = range.offset.unwrap_or(0).min(size);
+    let len = range.length.unwrap_or(DEFAULT_HEX_BYTES).min(MAX_HEX_BYTES);
+    let bytes = read_at(&mut file, offset, len)?;
+    preview.hex = Some(HexPreview { offset, length: bytes.len() as u64, dump: hexdump(&bytes, offset) });
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-preview-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn cleanup(path: &Path) {
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    /// Returns at most `max` bytes per read, so code units split across reads.
+    struct ShortReads(Cursor<Vec<u8>>, usize);
+
+    impl Read for ShortReads {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.1);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    impl Seek for ShortReads {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn line_offset_carries_a_split_utf16_unit() {
+        let bytes = utf16le("ab\ncd\nef");
+        let unit = newline_unit(UTF_16LE);
+        for max in [1, 3, 5, 64] {
+            let mut reader = ShortReads(Cursor::new(bytes.clone()), max);
+            assert_eq!(line_offset(&mut reader, 0, 1, unit).unwrap(), 0);
+            assert_eq!(line_offset(&mut reader, 0, 2, unit).unwrap(), 6, "reads of {}", max);
+            assert_eq!(line_offset(&mut reader, 0, 3, unit).unwrap(), 12, "reads of {}", max);
+            assert_eq!(line_offset(&mut reader, 0, 9, unit).unwrap(), bytes.len() as u64);
+        }
+        // `00 0a` spanning two code units is not a newline.
+        let mut reader = ShortReads(Cursor::new(utf16be("\u{0100}\u{0a41}b")), 3);
+        assert_eq!(line_offset(&mut reader, 0, 2, newline_unit(UTF_16BE)).unwrap(), 6);
+    }
+
+    #[test]
+    fn line_offset_across_scan_blocks() {
+        let mut text = "x".repeat(SCAN_BLOCK - 1);
+        text.push('\n');
+        text.push_str("second\nthird\n");
+        let path = temp_file("blocks", text.as_bytes());
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(line_offset(&mut file, 0, 2, b"\n").unwrap(), SCAN_BLOCK as u64);
+        assert_eq!(line_offset(&mut file, 0, 3, b"\n").unwrap(), SCAN_BLOCK as u64 + 7);
+
+        // The same in UTF-16LE after a BOM, two bytes per character.
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16le(&text[1..]));
+        std::fs::write(&path, &bytes).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(line_offset(&mut file, 2, 2, newline_unit(UTF_16LE)).unwrap(), 2 + 2 * (SCAN_BLOCK as u64 - 1));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn window_edges_skip_partial_characters() {
+        let e = "é".as_bytes();
+        assert_eq!(align_utf8_start(&[e[1], b'x']), 1);
+        assert_eq!(align_utf8_start(&[0x80, 0x80, 0x80, 0x80]), 3);
+        assert_eq!(align_utf8_start(b"plain"), 0);
+
+        assert_eq!(incomplete_tail("aé".as_bytes(), UTF_8), 0);
+        assert_eq!(incomplete_tail(&"aé".as_bytes()[..2], UTF_8), 1);
+        assert_eq!(incomplete_tail(&"a€".as_bytes()[..3], UTF_8), 2);
+        // Invalid bytes are not an incomplete character; the decoder replaces them.
+        assert_eq!(incomplete_tail(&[0xff, b'a'], UTF_8), 0);
+        assert_eq!(incomplete_tail(&[b'a', 0, b'b'], UTF_16LE), 1);
+        assert_eq!(incomplete_tail(&[0, b'a'], UTF_16BE), 0);
+        assert_eq!(incomplete_tail(&[0x82], encoding_rs::SHIFT_JIS), 0);
+    }
+
+    #[test]
+    fn utf16_windows_start_on_a_code_unit() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16le("hello\nworld\n"));
+        let path = temp_file("odd", &bytes);
+        let range = PreviewRange { offset: Some(5), length: Some(7), ..PreviewRange::default() };
+        let text = preview(&path, &range).unwrap().text.unwrap();
+        assert_eq!(text.start_byte, 4);
+        assert_eq!(text.end_byte, 10);
+        assert_eq!(text.content, "ell");
+        assert!(!text.had_errors);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(line_ending("a\nb\n"), "lf");
+        assert_eq!(line_ending("a\r\nb\r\n"), "crlf");
+        assert_eq!(line_ending("a\rb\r"), "cr");
+        assert_eq!(line_ending("a\r\nb\n"), "mixed");
+        assert_eq!(line_ending("a\rb\n"), "mixed");
+        assert_eq!(line_ending("one line"), "none");
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_text() {
+        for (bytes, name) in [(utf16le("hello\nworld\n"), "UTF-16LE"), (utf16be("hello\nworld\n"), "UTF-16BE")] {
+            let path = temp_file(name, &bytes);
+            let preview = preview(&path, &PreviewRange::default()).unwrap();
+            assert_eq!(preview.kind, PreviewKind::Text, "{}", name);
+            let text = preview.text.unwrap();
+            assert_eq!(text.encoding, name);
+            assert_eq!(text.content, "hello\nworld\n");
+            assert_eq!(text.lines, 2);
+            cleanup(&path);
+        }
+    }
+
+    #[test]
+    fn binaries_with_nul_bytes_are_not_text() {
+        let bytes: Vec<u8> = (0..64u32).flat_map(|i| i.to_le_bytes()).collect();
+        assert!(detect_encoding(&bytes, false).is_none());
+        let path = temp_file("binary", &bytes);
+        assert_eq!(preview(&path, &PreviewRange::default()).unwrap().kind, PreviewKind::Binary);
+        cleanup(&path);
+    }
+}
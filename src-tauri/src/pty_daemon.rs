@@ -0,0 +1,437 @@
+// Background PTY host that outlives the app (`chaos-grid daemon`).
+//
+// Enabled with CHAOS_GRID_DAEMON=1. The app then spawns shells through the
+// daemon instead of owning them, and on the next start reconnects to whatever
+// is still running. Protocol: newline-delimited JSON-RPC on
+// ~/.chaos-grid/daemon.sock, authenticated with the control API token.
+// `stream` turns its connection into a raw byte stream of the cell's output,
+// preceded by one JSON line carrying the current screen. Each stream has its
+// own bounded queue and writer thread; a client that falls behind is dropped
+// rather than stalling the PTY or the other clients.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::pty_manager::{self, ShellParts};
+
+const SCREEN_LIMIT: usize = 8000;
+const STARTUP_WAIT: Duration = Duration::from_secs(3);
+/// Output chunks (up to 4 KiB each) queued per stream before the client is dropped.
+const STREAM_QUEUE: usize = 256;
+/// How long `spawn_pty` waits for `restore_sessions` before spawning anyway.
+const RESTORE_WAIT: Duration = Duration::from_secs(10);
+
+pub(crate) fn enabled() -> bool {
+    std::env::var("CHAOS_GRID_DAEMON").map(|v| v == "1").unwrap_or(false)
+}
+
+fn socket_path() -> PathBuf {
+    crate::control_api::runtime_dir().join("daemon.sock")
+}
+
+// ─── Daemon side ─────────────────────────────────────────────────────────────
+
+/// Its own lock, so a shell that stops reading blocks only writes to itself.
+type PtyWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+struct HostedPty {
+    writer: PtyWriter,
+    child: Box<dyn portable_pty::Child + Send + Sync>,
+    master: Box<dyn portable_pty::MasterPty + Send>,
+    pid: u32,
+    screen: Arc<Mutex<Screen>>,
+}
+
+/// Recent output; the lock also guards `streams` so a new stream sees no gap or overlap.
+#[derive(Default)]
+struct Screen {
+    text: String,
+    /// UTF-8 sequence cut off by the last read, completed by the next one.
+    partial: Vec<u8>,
+    streams: Vec<SyncSender<Vec<u8>>>,
+}
+
+type Hosted = Arc<Mutex<HashMap<String, HostedPty>>>;
+
+/// Run the daemon in the foreground until the process is killed.
+pub(crate) fn serve() -> Result<(), String> {
+    let token = crate::control_api::load_or_create_token()?;
+    crate::control_api::ensure_runtime_dir()?;
+    let path = socket_path();
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(format!("daemon already running on {}", path.display()));
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = UnixListener::bind(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let hosted: Hosted = Arc::new(Mutex::new(HashMap::new()));
+    for stream in listener.incoming().filter_map(|s| s.ok()) {
+        let hosted = hosted.clone();
+        let token = token.clone();
+        std::thread::spawn(move || handle_client(stream, &token, hosted));
+    }
+    Ok(())
+}
+
+fn reply(stream: &mut UnixStream, id: &Value, result: Result<Value, String>) {
+    if id.is_null() {
+        return; // notification: no response expected
+    }
+    let msg = match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(message) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32000, "message": message}}),
+    };
+    let _ = stream.write_all(format!("{}\n", msg).as_bytes());
+}
+
+fn str_param<'a>(params: &'a Value, key: &str) -> Result<&'a str, String> {
+    params.get(key).and_then(|v| v.as_str()).ok_or_else(|| format!("missing {}", key))
+}
+
+fn size_params(params: &Value) -> (u16, u16) {
+    let get = |key: &str, default: u16| {
+        params.get(key).and_then(|v| v.as_u64()).map(|v| v as u16).unwrap_or(default)
+    };
+    (get("cols", crate::DEFAULT_COLS), get("rows", crate::DEFAULT_ROWS))
+}
+
+fn handle_client(stream: UnixStream, token: &str, hosted: Hosted) {
+    let Ok(read_half) = stream.try_clone() else { return };
+    let mut stream = stream;
+    let mut lines = BufReader::new(read_half).lines();
+    let mut authed = false;
+
+    while let Some(Ok(line)) = lines.next() {
+        let Ok(request) = serde_json::from_str::<Value>(&line) else { continue };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        if method == "auth" {
+            let given = params.get("token").and_then(|t| t.as_str()).unwrap_or("");
+            authed = crate::control_api::token_matches(given, token);
+            let result = if authed { Ok(json!(true)) } else { Err("invalid token".to_string()) };
+            reply(&mut stream, &id, result);
+            continue;
+        }
+        if !authed {
+            reply(&mut stream, &id, Err("call auth first".to_string()));
+            continue;
+        }
+
+        let result = match method {
+            "spawn" => str_param(&params, "cellId").and_then(|cell_id| {
+                let (cols, rows) = size_params(&params);
+                host_spawn(&hosted, cell_id, cols, rows).map(|pid| json!(pid))
+            }),
+            "write" => str_param(&params, "cellId").and_then(|cell_id| {
+                let data = str_param(&params, "data")?;
+                let writer = hosted
+                    .lock()
+                    .unwrap()
+                    .get(cell_id)
+                    .map(|pty| pty.writer.clone())
+                    .ok_or_else(|| format!("no PTY for {}", cell_id))?;
+                // The map is unlocked here: a full PTY input buffer must not stall other sessions.
+                let mut writer = writer.lock().unwrap();
+                writer.write_all(data.as_bytes()).map_err(|e| e.to_string())?;
+                Ok(Value::Null)
+            }),
+            "resize" => str_param(&params, "cellId").and_then(|cell_id| {
+                let (cols, rows) = size_params(&params);
+                let map = hosted.lock().unwrap();
+                let pty = map.get(cell_id).ok_or_else(|| format!("no PTY for {}", cell_id))?;
+                pty.master
+                    .resize(portable_pty::PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+                    .map_err(|e| e.to_string())?;
+                Ok(Value::Null)
+            }),
+            "kill" => str_param(&params, "cellId").map(|cell_id| {
+                if let Some(mut pty) = hosted.lock().unwrap().remove(cell_id) {
+                    let _ = pty.child.kill();
+                }
+                Value::Null
+            }),
+            "list" => {
+                let map = hosted.lock().unwrap();
+                let cells: Vec<Value> = map
+                    .iter()
+                    .map(|(cell_id, pty)| json!({"cellId": cell_id, "pid": pty.pid}))
+                    .collect();
+                Ok(json!(cells))
+            }
+            "stream" => {
+                // Hand the connection over to the output fan-out; stop reading requests.
+                let result = str_param(&params, "cellId").and_then(|cell_id| {
+                    let map = hosted.lock().unwrap();
+                    let pty = map.get(cell_id).ok_or_else(|| format!("no PTY for {}", cell_id))?;
+                    let out = stream.try_clone().map_err(|e| e.to_string())?;
+                    let mut screen = pty.screen.lock().unwrap();
+                    let msg = json!({"jsonrpc": "2.0", "id": id, "result": {"screen": screen.text, "pid": pty.pid}});
+                    let (tx, rx) = mpsc::sync_channel(STREAM_QUEUE);
+                    let _ = tx.try_send(format!("{}\n", msg).into_bytes());
+                    std::thread::spawn(move || write_stream(out, rx));
+                    screen.streams.push(tx);
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    reply(&mut stream, &id, Err(e));
+                }
+                return;
+            }
+            m => Err(format!("unknown method: {}", m)),
+        };
+        reply(&mut stream, &id, result);
+    }
+}
+
+/// Drain one client's queue onto its socket. Ends when the PTY exits or the
+/// client is dropped (its sender is gone) or stops accepting writes.
+fn write_stream(mut out: UnixStream, rx: mpsc::Receiver<Vec<u8>>) {
+    for chunk in rx {
+        if out.write_all(&chunk).is_err() {
+            break;
+        }
+    }
+    let _ = out.shutdown(std::net::Shutdown::Both);
+}
+
+fn host_spawn(hosted: &Hosted, cell_id: &str, cols: u16, rows: u16) -> Result<u32, String> {
+    if let Some(mut old) = hosted.lock().unwrap().remove(cell_id) {
+        let _ = old.child.kill();
+    }
+    let ShellParts { mut reader, writer, child, master, pid } = pty_manager::open_shell(cols, rows)?;
+    let screen = Arc::new(Mutex::new(Screen::default()));
+
+    let screen_for_reader = screen.clone();
+    let hosted_for_reader = hosted.clone();
+    let cell_id_owned = cell_id.to_string();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    let mut screen = screen_for_reader.lock().unwrap();
+                    let Screen { text, partial, streams } = &mut *screen;
+                    partial.extend_from_slice(&buf[..n]);
+                    pty_manager::push_bounded(text, &pty_manager::take_utf8(partial), SCREEN_LIMIT);
+                    // A full queue means the client stopped reading: drop it.
+                    streams.retain(|tx| tx.try_send(buf[..n].to_vec()).is_ok());
+                }
+                _ => break,
+            }
+        }
+        // Dropping the senders lets each writer flush its queue and close.
+        screen_for_reader.lock().unwrap().streams.clear();
+        let mut map = hosted_for_reader.lock().unwrap();
+        if map.get(&cell_id_owned).map(|p| p.pid) == Some(pid) {
+            map.remove(&cell_id_owned);
+        }
+    });
+
+    let writer = Arc::new(Mutex::new(writer));
+    hosted.lock().unwrap().insert(cell_id.to_string(), HostedPty { writer, child, master, pid, screen });
+    Ok(pid)
+}
+
+// ─── App side ────────────────────────────────────────────────────────────────
+
+fn connect() -> Result<(UnixStream, BufReader<UnixStream>), String> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path).map_err(|e| format!("PTY daemon: {}", e))?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let token = crate::control_api::load_or_create_token()?;
+    send(&mut stream, 0, "auth", json!({"token": token}))?;
+    read_response(&mut reader)?;
+    Ok((stream, reader))
+}
+
+fn send(stream: &mut UnixStream, id: u64, method: &str, params: Value) -> Result<(), String> {
+    let msg = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+    stream
+        .write_all(format!("{}\n", msg).as_bytes())
+        .map_err(|e| format!("PTY daemon: {}", e))
+}
+
+fn read_response(reader: &mut BufReader<UnixStream>) -> Result<Value, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| format!("PTY daemon: {}", e))?;
+    let msg: Value = serde_json::from_str(&line).map_err(|e| format!("PTY daemon: {}", e))?;
+    if let Some(message) = msg.pointer("/error/message").and_then(|m| m.as_str()) {
+        return Err(format!("PTY daemon: {}", message));
+    }
+    Ok(msg.get("result").cloned().unwrap_or(Value::Null))
+}
+
+/// One-shot request on a fresh connection.
+fn request(method: &str, params: Value) -> Result<Value, String> {
+    let (mut stream, mut reader) = connect()?;
+    send(&mut stream, 1, method, params)?;
+    read_response(&mut reader)
+}
+
+/// Start the daemon if it is not already listening, and wait until it is.
+pub(crate) fn ensure_running() -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+
+    if UnixStream::connect(socket_path()).is_ok() {
+        return Ok(());
+    }
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    std::process::Command::new(exe)
+        .arg("daemon")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0) // don't die with the app's terminal or process group
+        .spawn()
+        .map_err(|e| format!("failed to start PTY daemon: {}", e))?;
+
+    let started = std::time::Instant::now();
+    while started.elapsed() < STARTUP_WAIT {
+        if UnixStream::connect(socket_path()).is_ok() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Err("PTY daemon did not start".to_string())
+}
+
+/// Cells the daemon is currently hosting, as (cell_id, pid).
+pub(crate) fn list() -> Result<Vec<(String, u32)>, String> {
+    let cells = request("list", Value::Null)?;
+    Ok(cells
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|c| {
+                    let cell_id = c.get("cellId")?.as_str()?.to_string();
+                    let pid = c.get("pid")?.as_u64()? as u32;
+                    Some((cell_id, pid))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+pub(crate) fn spawn(cell_id: &str, cols: u16, rows: u16) -> Result<u32, String> {
+    ensure_running()?;
+    let pid = request("spawn", json!({"cellId": cell_id, "cols": cols, "rows": rows}))?;
+    pid.as_u64().map(|p| p as u32).ok_or_else(|| "PTY daemon: bad spawn reply".to_string())
+}
+
+pub(crate) fn resize(cell_id: &str, cols: u16, rows: u16) -> Result<(), String> {
+    request("resize", json!({"cellId": cell_id, "cols": cols, "rows": rows})).map(|_| ())
+}
+
+pub(crate) fn kill(cell_id: &str) -> Result<(), String> {
+    request("kill", json!({"cellId": cell_id})).map(|_| ())
+}
+
+/// Open an output stream for a cell. Returns (reader, current screen, pid).
+pub(crate) fn open_stream(cell_id: &str) -> Result<(BufReader<UnixStream>, String, u32), String> {
+    let (mut stream, mut reader) = connect()?;
+    send(&mut stream, 1, "stream", json!({"cellId": cell_id}))?;
+    let result = read_response(&mut reader)?;
+    let screen = result.get("screen").and_then(|s| s.as_str()).unwrap_or("").to_string();
+    let pid = result.get("pid").and_then(|p| p.as_u64()).unwrap_or(0) as u32;
+    // Any bytes already buffered past the JSON line stay in `reader`.
+    Ok((reader, screen, pid))
+}
+
+/// Forwards PTY input to the daemon as `write` notifications.
+pub(crate) struct DaemonWriter {
+    stream: UnixStream,
+    cell_id: String,
+    /// Start of a UTF-8 sequence split across `write` calls.
+    partial: Vec<u8>,
+}
+
+impl DaemonWriter {
+    pub(crate) fn connect(cell_id: &str) -> Result<Self, String> {
+        let (stream, _) = connect()?;
+        Ok(DaemonWriter { stream, cell_id: cell_id.to_string(), partial: Vec::new() })
+    }
+}
+
+impl Write for DaemonWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let data = pty_manager::take_utf8(&mut self.partial);
+        if !data.is_empty() {
+            let msg = json!({
+                "jsonrpc": "2.0",
+                "method": "write",
+                "params": {"cellId": self.cell_id, "data": data}
+            });
+            self.stream.write_all(format!("{}\n", msg).as_bytes())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Set once `restore_sessions` has finished, successfully or not.
+static RESTORED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+/// Block until startup restore is done, so a terminal asking for its PTY
+/// gets the restored session instead of replacing it with a new shell.
+pub(crate) fn wait_restored() {
+    let (done, cvar) = &RESTORED;
+    let guard = done.lock().unwrap();
+    let _ = cvar.wait_timeout_while(guard, RESTORE_WAIT, |done| !*done);
+}
+
+/// Reconnect, on a background thread, to cells the daemon kept running while
+/// the app was closed. A cell that fails to reconnect is logged and skipped.
+pub(crate) fn restore_sessions(app: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        if let Err(e) = restore_all(&app) {
+            eprintln!("PTY daemon: {}", e);
+        }
+        let (done, cvar) = &RESTORED;
+        *done.lock().unwrap() = true;
+        cvar.notify_all();
+    });
+}
+
+fn restore_all(app: &tauri::AppHandle) -> Result<(), String> {
+    ensure_running()?;
+    for (cell_id, pid) in list()? {
+        if let Err(e) = restore_one(app, &cell_id, pid) {
+            eprintln!("PTY daemon: could not reconnect {}: {}", cell_id, e);
+        }
+    }
+    Ok(())
+}
+
+fn restore_one(app: &tauri::AppHandle, cell_id: &str, pid: u32) -> Result<(), String> {
+    use tauri::Manager;
+
+    let sessions = app.state::<crate::PtySessions>();
+    let cell_states = app.state::<crate::CellStateMap>();
+    let session = pty_manager::reconnect(app.clone(), cell_id, cell_states.0.clone())?;
+    {
+        let mut states = cell_states.0.lock().map_err(|e| e.to_string())?;
+        if let Some(state) = states.get_mut(cell_id) {
+            state.pid = Some(pid);
+            state.status = "active".to_string();
+            state.last_output = session.buffer.lock().unwrap().clone();
+            state.updated_at = crate::now_millis();
+        }
+    }
+    let mut map = sessions.0.lock().map_err(|e| e.to_string())?;
+    map.insert(cell_id.to_string(), session);
+    Ok(())
+}
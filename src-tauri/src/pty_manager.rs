@@ -7,10 +7,30 @@ use tauri::Emitter;
 const BUFFER_LIMIT: usize = 2000;
 
 pub struct PtySession {
+    pub writer: Box<dyn Write + Send>,
+    pub handle: PtyHandle,
+    pub buffer: Arc<Mutex<String>>,
+    pub pid: u32,
+    /// Reconnected from the daemon at startup and not yet claimed by a `spawn_pty`.
+    pub restored: bool,
+}
+
+/// Who owns the PTY: this process, or the background daemon (see `pty_daemon`).
+pub enum PtyHandle {
+    Local {
+        child: Box<dyn portable_pty::Child + Send + Sync>,
+        master: Box<dyn portable_pty::MasterPty + Send>,
+    },
+    #[cfg(unix)]
+    Daemon { cell_id: String },
+}
+
+/// A freshly opened login shell, before any reader thread is attached.
+pub struct ShellParts {
+    pub reader: Box<dyn Read + Send>,
     pub writer: Box<dyn Write + Send>,
     pub child: Box<dyn portable_pty::Child + Send + Sync>,
     pub master: Box<dyn portable_pty::MasterPty + Send>,
-    pub buffer: Arc<Mutex<String>>,
     pub pid: u32,
 }
 
@@ -21,14 +41,93 @@ struct PtyDataPayload {
     data: String,
 }
 
-pub fn spawn(
-    app: tauri::AppHandle,
-    cell_id: &str,
-    cols: u16,
-    rows: u16,
-    cell_states: Arc<Mutex<HashMap<String, crate::CellState>>>,
-    app_handle_for_storage: tauri::AppHandle,
-) -> Result<PtySession, String> {
+#[derive(serde::Serialize, Clone)]
+struct CellStatusPayload {
+    #[serde(rename = "cellId")]
+    cell_id: String,
+    status: String,
+}
+
+/// Notify listeners (frontend and control API subscribers) of a cell status change.
+pub fn emit_status(app: &tauri::AppHandle, cell_id: &str, status: &str) {
+    let _ = app.emit(
+        "cell-status",
+        CellStatusPayload { cell_id: cell_id.to_string(), status: status.to_string() },
+    );
+}
+
+#[derive(serde::Serialize, Clone)]
+struct TaskPayload {
+    #[serde(rename = "cellId")]
+    cell_id: String,
+    task: String,
+}
+
+/// Append a task to a cell's task queue (kept by the frontend, which sends it when the cell is free).
+pub fn emit_task(app: &tauri::AppHandle, cell_id: &str, task: &str) {
+    let _ = app.emit(
+        "task-enqueued",
+        TaskPayload { cell_id: cell_id.to_string(), task: task.to_string() },
+    );
+}
+
+/// Re-send a cell's buffered output, e.g. so a freshly mounted terminal shows the current screen.
+pub fn emit_buffer(app: &tauri::AppHandle, cell_id: &str, session: &PtySession) {
+    let data = session.buffer.lock().unwrap().clone();
+    if !data.is_empty() {
+        let _ = app.emit("pty-data", PtyDataPayload { cell_id: cell_id.to_string(), data });
+    }
+}
+
+/// Append to a rolling output buffer, keeping roughly the last `limit` bytes.
+pub fn push_bounded(buffer: &mut String, data: &str, limit: usize) {
+    buffer.push_str(data);
+    if buffer.len() > limit * 2 {
+        let mut start = buffer.len() - limit;
+        while !buffer.is_char_boundary(start) {
+            start += 1;
+        }
+        *buffer = buffer[start..].to_string();
+    }
+}
+
+/// Decode the complete characters at the front of `pending`, leaving a
+/// multi-byte sequence cut off by the read buffer for the next read.
+/// Invalid bytes become U+FFFD as they would with `from_utf8_lossy`.
+pub fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let mut out = String::new();
+    let mut rest = pending.as_slice();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                out.push_str(text);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                out.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    // Incomplete sequence at the end: wait for the rest of it.
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let keep = rest.len();
+    pending.drain(..pending.len() - keep);
+    out
+}
+
+/// Open a PTY running the user's login shell with a cleaned-up environment.
+pub fn open_shell(cols: u16, rows: u16) -> Result<ShellParts, String> {
     let pty_system = native_pty_system();
 
     let pair = pty_system
@@ -69,14 +168,88 @@ pub fn spawn(
 
     let pid = child.process_id().unwrap_or(0);
 
-    let mut reader = pair.master.try_clone_reader().map_err(|e| e.to_string())?;
+    let reader = pair.master.try_clone_reader().map_err(|e| e.to_string())?;
     let writer = pair.master.take_writer().map_err(|e| e.to_string())?;
 
     // Drop the slave side - we don't need it after spawning
     drop(pair.slave);
 
+    Ok(ShellParts { reader, writer, child, master: pair.master, pid })
+}
+
+pub fn spawn(
+    app: tauri::AppHandle,
+    cell_id: &str,
+    cols: u16,
+    rows: u16,
+    cell_states: Arc<Mutex<HashMap<String, crate::CellState>>>,
+    app_handle_for_storage: tauri::AppHandle,
+) -> Result<PtySession, String> {
+    #[cfg(unix)]
+    if crate::pty_daemon::enabled() {
+        let pid = crate::pty_daemon::spawn(cell_id, cols, rows)?;
+        let (reader, screen, _) = crate::pty_daemon::open_stream(cell_id)?;
+        let writer = crate::pty_daemon::DaemonWriter::connect(cell_id)?;
+        let buffer = Arc::new(Mutex::new(String::new()));
+        if !screen.is_empty() {
+            // Output produced between spawn and stream setup
+            let mut buffer = buffer.lock().unwrap();
+            push_bounded(&mut buffer, &screen, BUFFER_LIMIT);
+            let _ = app.emit("pty-data", PtyDataPayload { cell_id: cell_id.to_string(), data: screen });
+        }
+        start_reader(app, cell_id, Box::new(reader), buffer.clone(), cell_states, app_handle_for_storage);
+        return Ok(PtySession {
+            writer: Box::new(writer),
+            handle: PtyHandle::Daemon { cell_id: cell_id.to_string() },
+            buffer,
+            pid,
+            restored: false,
+        });
+    }
+
+    let shell = open_shell(cols, rows)?;
+    let buffer = Arc::new(Mutex::new(String::new()));
+    start_reader(app, cell_id, shell.reader, buffer.clone(), cell_states, app_handle_for_storage);
+
+    Ok(PtySession {
+        writer: shell.writer,
+        handle: PtyHandle::Local { child: shell.child, master: shell.master },
+        buffer,
+        pid: shell.pid,
+        restored: false,
+    })
+}
+
+/// Reattach to a PTY the daemon kept alive across an app restart.
+#[cfg(unix)]
+pub fn reconnect(
+    app: tauri::AppHandle,
+    cell_id: &str,
+    cell_states: Arc<Mutex<HashMap<String, crate::CellState>>>,
+) -> Result<PtySession, String> {
+    let (reader, screen, pid) = crate::pty_daemon::open_stream(cell_id)?;
+    let writer = crate::pty_daemon::DaemonWriter::connect(cell_id)?;
     let buffer = Arc::new(Mutex::new(String::new()));
-    let buffer_clone = buffer.clone();
+    push_bounded(&mut buffer.lock().unwrap(), &screen, BUFFER_LIMIT);
+    start_reader(app.clone(), cell_id, Box::new(reader), buffer.clone(), cell_states, app);
+    Ok(PtySession {
+        writer: Box::new(writer),
+        handle: PtyHandle::Daemon { cell_id: cell_id.to_string() },
+        buffer,
+        pid,
+        restored: true,
+    })
+}
+
+fn start_reader(
+    app: tauri::AppHandle,
+    cell_id: &str,
+    mut reader: Box<dyn Read + Send>,
+    buffer: Arc<Mutex<String>>,
+    cell_states: Arc<Mutex<HashMap<String, crate::CellState>>>,
+    app_handle_for_storage: tauri::AppHandle,
+) {
+    let buffer_clone = buffer;
     let cell_id_clone = cell_id.to_string();
     let cell_id_for_state = cell_id.to_string();
 
@@ -86,42 +259,50 @@ pub fn spawn(
         const SAVE_THROTTLE: Duration = Duration::from_secs(2);
 
         let mut buf = [0u8; 4096];
+        let mut partial = Vec::new(); // UTF-8 sequence cut off by the last read
         let mut last_save = Instant::now() - SAVE_THROTTLE; // allow immediate first save
         loop {
             match reader.read(&mut buf) {
                 Ok(n) if n > 0 => {
-                    let data = String::from_utf8_lossy(&buf[..n]).to_string();
+                    partial.extend_from_slice(&buf[..n]);
+                    let data = take_utf8(&mut partial);
+                    if data.is_empty() {
+                        continue;
+                    }
 
-                    // Update buffer
+                    // Update the buffer and emit under one lock, so a snapshot taken
+                    // under that lock (control API attach) lines up with the stream.
                     {
-                        let mut b = buffer_clone.lock().unwrap();
-                        b.push_str(&data);
-                        if b.len() > BUFFER_LIMIT * 2 {
-                            let mut start = b.len() - BUFFER_LIMIT;
-                            while !b.is_char_boundary(start) {
-                                start += 1;
-                            }
-                            *b = b[start..].to_string();
-                        }
+                        let mut buffer = buffer_clone.lock().unwrap();
+                        push_bounded(&mut buffer, &data, BUFFER_LIMIT);
+                        let payload = PtyDataPayload {
+                            cell_id: cell_id_clone.clone(),
+                            data: data.clone(),
+                        };
+                        let _ = app.emit("pty-data", payload);
                     }
-
-                    // Emit event to frontend
-                    let payload = PtyDataPayload {
-                        cell_id: cell_id_clone.clone(),
-                        data: data.clone(),
-                    };
-                    let _ = app.emit("pty-data", payload);
+                    let (lines, pending) = crate::search::record(&app, &cell_id_for_state, &data);
 
                     // Update cell state
                     {
                         let buffer_content = buffer_clone.lock().unwrap().clone();
                         let mut states = cell_states.lock().unwrap();
+                        let mut became_active = false;
                         if let Some(state) = states.get_mut(&cell_id_for_state) {
                             state.last_output = buffer_content.clone();
-                            state.status = "active".to_string();
+                            state.output_bytes += data.len() as u64;
+                            became_active = becomes_active(&state.status);
+                            if became_active {
+                                state.status = "active".to_string();
+                            }
                             state.updated_at = crate::now_millis();
                         }
                         drop(states);
+                        if became_active {
+                            emit_status(&app, &cell_id_for_state, "active");
+                        }
+                        crate::triggers::evaluate(&app, &cell_id_for_state, &lines, &pending);
+                        crate::pipes::observe(&app, &cell_id_for_state, &lines);
 
                         // Persist output (throttled: at most once per 2 seconds)
                         if last_save.elapsed() >= SAVE_THROTTLE {
@@ -136,6 +317,7 @@ pub fn spawn(
                 }
                 _ => {
                     // Final save on exit to ensure latest output is persisted
+                    crate::search::flush(&app, &cell_id_for_state);
                     {
                         let buffer_content = buffer_clone.lock().unwrap().clone();
                         crate::storage::save_cell_output(
@@ -153,34 +335,69 @@ pub fn spawn(
                         state.status = "idle".to_string();
                         state.updated_at = crate::now_millis();
                     }
+                    drop(states);
+                    emit_status(&app, &cell_id_for_state, "idle");
                     break;
                 }
             }
         }
     });
+}
 
-    Ok(PtySession {
-        writer,
-        child,
-        master: pair.master,
-        buffer,
-        pid,
-    })
+/// Whether output moves a cell in `status` to "active". "error" (set by a
+/// trigger) sticks until the PTY exits or respawns.
+pub(crate) fn becomes_active(status: &str) -> bool {
+    status != "active" && status != "error"
 }
 
 pub fn resize(session: &PtySession, cols: u16, rows: u16) -> Result<(), String> {
-    session
-        .master
-        .resize(PtySize {
-            rows,
-            cols,
-            pixel_width: 0,
-            pixel_height: 0,
-        })
-        .map_err(|e| e.to_string())
+    match &session.handle {
+        PtyHandle::Local { master, .. } => master
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(|e| e.to_string()),
+        #[cfg(unix)]
+        PtyHandle::Daemon { cell_id } => crate::pty_daemon::resize(cell_id, cols, rows),
+    }
 }
 
 pub fn kill(session: &mut PtySession) {
-    let _ = session.child.kill();
+    match &mut session.handle {
+        PtyHandle::Local { child, .. } => {
+            let _ = child.kill();
+        }
+        #[cfg(unix)]
+        PtyHandle::Daemon { cell_id } => {
+            let _ = crate::pty_daemon::kill(cell_id);
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_utf8_carries_a_split_character() {
+        let bytes = "héllo ✓".as_bytes();
+        let mut pending = bytes[..2].to_vec(); // "h" and the first byte of "é"
+        assert_eq!(take_utf8(&mut pending), "h");
+        assert_eq!(pending, &bytes[1..2]);
+        pending.extend_from_slice(&bytes[2..bytes.len() - 1]);
+        assert_eq!(take_utf8(&mut pending), "éllo ");
+        pending.extend_from_slice(&bytes[bytes.len() - 1..]);
+        assert_eq!(take_utf8(&mut pending), "✓");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn take_utf8_replaces_invalid_bytes() {
+        let mut pending = b"a\xffb".to_vec();
+        assert_eq!(take_utf8(&mut pending), "a\u{fffd}b");
+        assert!(pending.is_empty());
+    }
+}
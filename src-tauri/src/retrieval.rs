@@ -0,0 +1,411 @@
+// Local keyword retrieval over work-dir files. Each directory gets an
+// in-memory BM25 index of line-based chunks. Every query walks the tree and
+// re-reads only the files whose mtime changed, so the index stays current
+// without a watcher and nothing leaves the machine. Only the most recently
+// searched directories keep an index.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+const CHUNK_LINES: usize = 40;
+const CHUNK_CHARS: usize = 1500;
+/// Larger files (logs, data dumps) are listed by `walk_dir` but not indexed.
+const MAX_FILE_BYTES: u64 = 1_000_000;
+const MAX_HITS_PER_FILE: usize = 2;
+/// Directories with an index in memory; the least recently searched is dropped first.
+const MAX_INDEXES: usize = 16;
+/// A match in the file path counts this many times.
+const PATH_WEIGHT: u32 = 2;
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+const STOPWORDS: &[&str] = &[
+    "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on", "or",
+    "that", "the", "this", "to", "was", "with",
+];
+
+/// A chunk of a file that matched a query.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hit {
+    /// Path relative to the searched directory.
+    pub path: String,
+    /// 1-based line the chunk starts at.
+    pub start_line: usize,
+    pub text: String,
+    pub score: f64,
+}
+
+struct Chunk {
+    start_line: usize,
+    text: String,
+    terms: HashMap<String, u32>,
+    len: u32,
+}
+
+struct IndexedFile {
+    modified_ms: u64,
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Default)]
+struct DirIndex {
+    files: HashMap<String, IndexedFile>,
+    /// Chunks containing each term.
+    doc_freq: HashMap<String, u32>,
+    chunks: usize,
+    total_len: u64,
+    /// When a query last used it.
+    last_used: Option<Instant>,
+}
+
+fn indexes() -> &'static Mutex<HashMap<PathBuf, DirIndex>> {
+    static INDEXES: OnceLock<Mutex<HashMap<PathBuf, DirIndex>>> = OnceLock::new();
+    INDEXES.get_or_init(Default::default)
+}
+
+// ─── Tokens ──────────────────────────────────────────────────────────────────
+
+/// Lowercase words of two or more characters, plus the parts of snake_case
+/// identifiers. Words in scripts written without spaces (CJK) become
+/// character bigrams.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let word = word.trim_matches('_').to_lowercase();
+        if word.is_empty() {
+            continue;
+        }
+        if !word.is_ascii() {
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len() == 1 {
+                out.push(word);
+            } else {
+                out.extend(chars.windows(2).map(|pair| pair.iter().collect::<String>()));
+            }
+            continue;
+        }
+        if word.len() < 2 || STOPWORDS.contains(&word.as_str()) {
+            continue;
+        }
+        if word.contains('_') {
+            out.extend(word.split('_').filter(|part| part.len() >= 2).map(str::to_string));
+        }
+        out.push(word);
+    }
+    out
+}
+
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+// ─── Indexing ────────────────────────────────────────────────────────────────
+
+fn make_chunk(rel: &str, start_line: usize, lines: &[&str]) -> Chunk {
+    let text = truncate(&lines.join("\n"), CHUNK_CHARS * 2).to_string();
+    let mut terms: HashMap<String, u32> = HashMap::new();
+    for term in tokenize(&text) {
+        *terms.entry(term).or_default() += 1;
+    }
+    for term in tokenize(rel) {
+        *terms.entry(term).or_default() += PATH_WEIGHT;
+    }
+    let len = terms.values().sum();
+    Chunk { start_line, text, terms, len }
+}
+
+/// Split a file into chunks of up to `CHUNK_LINES` lines / `CHUNK_CHARS` characters.
+/// Binary and oversized files get no chunks.
+fn index_file(rel: &str, path: &Path, modified_ms: u64) -> IndexedFile {
+    let mut file = IndexedFile { modified_ms, chunks: Vec::new() };
+    if std::fs::metadata(path).map_or(true, |m| m.len() > MAX_FILE_BYTES) {
+        return file;
+    }
+    let Ok(bytes) = std::fs::read(path) else { return file };
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        return file;
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines: Vec<&str> = Vec::new();
+    let mut chars = 0;
+    let mut start_line = 1;
+    for (i, line) in text.lines().enumerate() {
+        if !lines.is_empty() && (lines.len() >= CHUNK_LINES || chars + line.len() > CHUNK_CHARS) {
+            file.chunks.push(make_chunk(rel, start_line, &lines));
+            lines.clear();
+            chars = 0;
+            start_line = i + 1;
+        }
+        lines.push(line);
+        chars += line.len() + 1;
+    }
+    if lines.iter().any(|l| !l.trim().is_empty()) {
+        file.chunks.push(make_chunk(rel, start_line, &lines));
+    }
+    file
+}
+
+impl DirIndex {
+    fn add(&mut self, rel: String, file: IndexedFile) {
+        for chunk in &file.chunks {
+            for term in chunk.terms.keys() {
+                *self.doc_freq.entry(term.clone()).or_default() += 1;
+            }
+            self.chunks += 1;
+            self.total_len += chunk.len as u64;
+        }
+        self.files.insert(rel, file);
+    }
+
+    fn remove(&mut self, rel: &str) {
+        let Some(file) = self.files.remove(rel) else { return };
+        for chunk in &file.chunks {
+            for term in chunk.terms.keys() {
+                if let Some(df) = self.doc_freq.get_mut(term) {
+                    *df -= 1;
+                    if *df == 0 {
+                        self.doc_freq.remove(term);
+                    }
+                }
+            }
+            self.chunks -= 1;
+            self.total_len -= chunk.len as u64;
+        }
+    }
+
+    /// Re-index files that are new or whose mtime changed, and drop deleted ones.
+    fn refresh(&mut self, root: &Path) {
+        let walked = crate::files::walk_dir(root);
+        for (rel, path, modified_ms) in &walked {
+            if self.files.get(rel).is_some_and(|f| f.modified_ms == *modified_ms) {
+                continue;
+            }
+            self.remove(rel);
+            self.add(rel.clone(), index_file(rel, path, *modified_ms));
+        }
+        let present: HashSet<&String> = walked.iter().map(|(rel, _, _)| rel).collect();
+        let gone: Vec<String> = self.files.keys().filter(|rel| !present.contains(rel)).cloned().collect();
+        for rel in gone {
+            self.remove(&rel);
+        }
+    }
+
+    fn rank(&self, terms: &[String], limit: usize) -> Vec<Hit> {
+        if self.chunks == 0 {
+            return Vec::new();
+        }
+        let n = self.chunks as f64;
+        let avg_len = (self.total_len as f64 / n).max(1.0);
+        let idf: Vec<(&String, f64)> = terms
+            .iter()
+            .filter_map(|t| {
+                let df = *self.doc_freq.get(t)? as f64;
+                Some((t, (1.0 + (n - df + 0.5) / (df + 0.5)).ln()))
+            })
+            .collect();
+        if idf.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(f64, &String, &Chunk)> = Vec::new();
+        for (rel, file) in &self.files {
+            for chunk in &file.chunks {
+                let norm = K1 * (1.0 - B + B * chunk.len as f64 / avg_len);
+                let score: f64 = idf
+                    .iter()
+                    .filter_map(|(term, idf)| {
+                        let tf = *chunk.terms.get(*term)? as f64;
+                        Some(idf * tf * (K1 + 1.0) / (tf + norm))
+                    })
+                    .sum();
+                if score > 0.0 {
+                    scored.push((score, rel, chunk));
+                }
+            }
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+        let mut per_file: HashMap<&String, usize> = HashMap::new();
+        let mut hits = Vec::new();
+        for (score, rel, chunk) in scored {
+            let count = per_file.entry(rel).or_default();
+            if *count >= MAX_HITS_PER_FILE {
+                continue;
+            }
+            *count += 1;
+            hits.push(Hit {
+                path: rel.clone(),
+                start_line: chunk.start_line,
+                text: chunk.text.clone(),
+                score,
+            });
+            if hits.len() >= limit {
+                break;
+            }
+        }
+        hits
+    }
+}
+
+// ─── Queries ─────────────────────────────────────────────────────────────────
+
+/// The `limit` chunks under `root` most relevant to `query`, best first.
+pub(crate) fn search(root: &Path, query: &str, limit: usize) -> Vec<Hit> {
+    let mut terms = tokenize(query);
+    let mut seen = HashSet::new();
+    terms.retain(|t| seen.insert(t.clone()));
+    if terms.is_empty() || limit == 0 || !root.is_dir() {
+        return Vec::new();
+    }
+    let mut indexes = indexes().lock().unwrap();
+    if !indexes.contains_key(root) {
+        make_room(&mut indexes, MAX_INDEXES - 1);
+    }
+    let index = indexes.entry(root.to_path_buf()).or_default();
+    index.last_used = Some(Instant::now());
+    index.refresh(root);
+    index.rank(&terms, limit)
+}
+
+/// Drop the least recently used indexes until at most `keep` remain.
+fn make_room(indexes: &mut HashMap<PathBuf, DirIndex>, keep: usize) {
+    while indexes.len() > keep {
+        let Some(oldest) = indexes.iter().min_by_key(|(_, index)| index.last_used).map(|(root, _)| root.clone()) else {
+            return;
+        };
+        indexes.remove(&oldest);
+    }
+}
+
+/// Hits as prompt text: a header per chunk, each chunk cut to `max_chars`.
+pub(crate) fn format_hits(hits: &[Hit], max_chars: usize) -> String {
+    hits.iter()
+        .map(|h| format!("--- {} (line {})\n{}", h.path, h.start_line, truncate(&h.text, max_chars)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-retrieval-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(root: &Path, rel: &str, text: &str) {
+        let path = root.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn paths(hits: &[Hit]) -> Vec<&str> {
+        hits.iter().map(|h| h.path.as_str()).collect()
+    }
+
+    #[test]
+    fn tokenize_splits_identifiers_and_drops_stopwords() {
+        let tokens = tokenize("The parse_json_file() of a WORLD, x 42");
+        assert_eq!(tokens, vec!["parse", "json", "file", "parse_json_file", "world", "42"]);
+        assert_eq!(tokenize("__init__"), vec!["init"]);
+        assert_eq!(tokenize("日本語 字"), vec!["日本", "本語", "字"]);
+    }
+
+    #[test]
+    fn files_are_chunked_by_lines_and_characters() {
+        let root = temp_root("chunks");
+        let lines: Vec<String> = (1..=100).map(|i| format!("line {}", i)).collect();
+        write(&root, "long.txt", &lines.join("\n"));
+        let file = index_file("long.txt", &root.join("long.txt"), 0);
+        let starts: Vec<usize> = file.chunks.iter().map(|c| c.start_line).collect();
+        assert_eq!(starts, vec![1, 41, 81]);
+        assert!(file.chunks[0].text.starts_with("line 1\n") && file.chunks[0].text.ends_with("line 40"));
+
+        let wide = vec!["w".repeat(600); 5].join("\n");
+        write(&root, "wide.txt", &wide);
+        let file = index_file("wide.txt", &root.join("wide.txt"), 0);
+        assert_eq!(file.chunks.iter().map(|c| c.start_line).collect::<Vec<_>>(), vec![1, 3, 5]);
+
+        write(&root, "blob.bin", "head\0tail");
+        assert!(index_file("blob.bin", &root.join("blob.bin"), 0).chunks.is_empty());
+        write(&root, "blank.txt", "\n  \n");
+        assert!(index_file("blank.txt", &root.join("blank.txt"), 0).chunks.is_empty());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn rare_terms_and_path_matches_rank_first() {
+        let root = temp_root("rank");
+        write(&root, "a.txt", "common words here\ncommon again");
+        write(&root, "b.txt", "common words and a zeppelin");
+        write(&root, "c.txt", "nothing relevant");
+        write(&root, "zeppelin/notes.txt", "common");
+        let hits = search(&root, "common zeppelin", 10);
+        assert_eq!(paths(&hits)[..2], ["zeppelin/notes.txt", "b.txt"]);
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(!paths(&hits).contains(&"c.txt"));
+        assert_eq!(paths(&search(&root, "common zeppelin", 1)), vec!["zeppelin/notes.txt"]);
+        assert!(search(&root, "the of", 10).is_empty());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn at_most_two_hits_per_file() {
+        let root = temp_root("per-file");
+        let text = (0..200).map(|_| "kumquat").collect::<Vec<_>>().join("\n");
+        write(&root, "many.txt", &text);
+        write(&root, "one.txt", "kumquat");
+        let hits = search(&root, "kumquat", 10);
+        assert_eq!(hits.iter().filter(|h| h.path == "many.txt").count(), MAX_HITS_PER_FILE);
+        assert_eq!(hits.len(), MAX_HITS_PER_FILE + 1);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn refresh_follows_mtime_changes_and_deletions() {
+        let root = temp_root("refresh");
+        write(&root, "notes.txt", "first draft mentions walrus");
+        write(&root, "other.txt", "walrus facts");
+        assert_eq!(search(&root, "walrus", 10).len(), 2);
+
+        write(&root, "notes.txt", "second draft mentions narwhal");
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(root.join("notes.txt")).unwrap().set_modified(later).unwrap();
+        assert_eq!(paths(&search(&root, "narwhal", 10)), vec!["notes.txt"]);
+        assert_eq!(paths(&search(&root, "walrus", 10)), vec!["other.txt"]);
+
+        std::fs::remove_file(root.join("other.txt")).unwrap();
+        assert!(search(&root, "walrus", 10).is_empty());
+        let index = &indexes().lock().unwrap()[&root];
+        assert_eq!(index.files.len(), 1);
+        assert!(!index.doc_freq.contains_key("walrus"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn least_recently_used_indexes_are_dropped() {
+        let now = Instant::now();
+        let mut indexes: HashMap<PathBuf, DirIndex> = HashMap::new();
+        for i in 0..5u64 {
+            let index = DirIndex { last_used: Some(now + std::time::Duration::from_secs(i)), ..DirIndex::default() };
+            indexes.insert(PathBuf::from(format!("/root-{}", i)), index);
+        }
+        make_room(&mut indexes, 3);
+        let mut left: Vec<&PathBuf> = indexes.keys().collect();
+        left.sort();
+        assert_eq!(left, vec![Path::new("/root-2"), Path::new("/root-3"), Path::new("/root-4")]);
+    }
+}
@@ -0,0 +1,255 @@
+// Confines file commands called from the webview to workspace roots: the
+// allowlist in `<data>/sandbox.json`. The webview cannot change it; roots are
+// added by editing that file or by choosing a folder in the native picker.
+// Paths are canonicalized before the check, so `..` and symlinks cannot lead
+// out of a root. Roots themselves may not be the home directory, one of its
+// ancestors, or a hidden directory such as `~/.ssh`.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::files::expand_tilde;
+
+const SANDBOX_FILE: &str = "sandbox.json";
+/// Never opened with the system handler, even inside a root.
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "app", "command", "sh", "bash", "zsh", "fish", "tool", "pkg", "dmg", "scpt", "applescript",
+    "workflow", "terminal", "jar", "exe", "bat", "cmd", "com", "msi", "ps1", "vbs", "desktop", "appimage",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SandboxConfig {
+    pub allowed_roots: Vec<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig { allowed_roots: vec!["~/chaos-grid-output".to_string()] }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceRoots {
+    pub allowed_roots: Vec<String>,
+}
+
+fn state() -> &'static Mutex<SandboxConfig> {
+    static STATE: OnceLock<Mutex<SandboxConfig>> = OnceLock::new();
+    STATE.get_or_init(|| Mutex::new(SandboxConfig::default()))
+}
+
+pub(crate) fn load(app: &tauri::AppHandle) {
+    *state().lock().unwrap() = crate::storage::load_json(app, SANDBOX_FILE);
+}
+
+/// Canonical form of a root, refusing ones that would expose the home directory or secrets.
+fn validate_root(root: &str) -> Result<PathBuf, String> {
+    let expanded = expand_tilde(root);
+    let path = Path::new(&expanded);
+    let canonical = match path.canonicalize() {
+        Ok(canonical) if canonical.is_dir() => canonical,
+        Ok(_) => return Err(format!("{}: not a directory", expanded)),
+        // Not created yet (e.g. an output dir before the first launch): check it as written.
+        Err(_) if path.is_absolute() && !path.components().any(|c| c == Component::ParentDir) => path.to_path_buf(),
+        Err(e) => return Err(format!("{}: {}", expanded, e)),
+    };
+    if canonical.parent().is_none() {
+        return Err("the filesystem root cannot be a workspace root".to_string());
+    }
+    if let Some(home) = dirs::home_dir().and_then(|h| h.canonicalize().ok()) {
+        if home.starts_with(&canonical) {
+            return Err(format!("{} contains the home directory and cannot be a workspace root", expanded));
+        }
+        if let Ok(rel) = canonical.strip_prefix(&home) {
+            let hidden = rel
+                .components()
+                .any(|c| matches!(c, Component::Normal(name) if name.to_string_lossy().starts_with('.')));
+            if hidden {
+                return Err(format!("{} is a hidden directory and cannot be a workspace root", expanded));
+            }
+        }
+    }
+    Ok(canonical)
+}
+
+/// Canonical roots that currently exist.
+fn roots() -> Vec<PathBuf> {
+    state()
+        .lock()
+        .unwrap()
+        .allowed_roots
+        .iter()
+        .filter_map(|r| validate_root(r).ok())
+        .filter(|r| r.exists())
+        .collect()
+}
+
+/// The outermost workspace root containing the canonical `path`.
+pub(crate) fn root_of(path: &Path) -> Option<PathBuf> {
+    roots().into_iter().filter(|root| path.starts_with(root)).min_by_key(|root| root.components().count())
+}
+
+/// Resolve `path` and make sure it lies inside a workspace root.
+pub(crate) fn check(path: &str) -> Result<PathBuf, String> {
+    check_in(path, &roots())
+}
+
+/// `check` against the given canonical roots.
+fn check_in(path: &str, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let expanded = expand_tilde(path);
+    let canonical = Path::new(&expanded)
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", expanded, e))?;
+    // Component-wise, so `/work2` is not inside `/work`.
+    if roots.iter().any(|root| canonical.starts_with(root)) {
+        Ok(canonical)
+    } else {
+        Err(format!(
+            "{} is outside the workspace roots; choose its folder in the picker or add it to allowedRoots in {}",
+            expanded, SANDBOX_FILE
+        ))
+    }
+}
+
+/// Like `check`, and refuse files the system would run rather than display.
+pub(crate) fn check_openable(path: &str) -> Result<PathBuf, String> {
+    let canonical = check(path)?;
+    let ext = canonical.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if EXECUTABLE_EXTENSIONS.contains(&ext.as_str()) {
+        return Err(format!("{}: refusing to open an executable", canonical.display()));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let meta = std::fs::metadata(&canonical).map_err(|e| e.to_string())?;
+        if meta.is_file() && meta.permissions().mode() & 0o111 != 0 {
+            return Err(format!("{}: refusing to open an executable", canonical.display()));
+        }
+    }
+    Ok(canonical)
+}
+
+pub(crate) fn workspace_roots() -> WorkspaceRoots {
+    WorkspaceRoots { allowed_roots: state().lock().unwrap().allowed_roots.clone() }
+}
+
+/// Add a folder the user chose in the native picker to the allowlist.
+pub(crate) fn allow_picked_root(app: &tauri::AppHandle, root: &Path) -> Result<WorkspaceRoots, String> {
+    let canonical = validate_root(&root.to_string_lossy())?;
+    let mut config = state().lock().unwrap().clone();
+    let known = config
+        .allowed_roots
+        .iter()
+        .any(|r| validate_root(r).is_ok_and(|existing| existing == canonical));
+    if !known {
+        config.allowed_roots.push(canonical.to_string_lossy().to_string());
+        crate::storage::save_json(app, SANDBOX_FILE, &config)?;
+        *state().lock().unwrap() = config;
+    }
+    Ok(workspace_roots())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// `<tmp>/work`, `<tmp>/work2` and `<tmp>/outside`, each holding `f.txt`.
+    fn tree(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("chaos-grid-sandbox-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        for dir in ["work/sub", "work2", "outside"] {
+            fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for file in ["work/f.txt", "work/sub/f.txt", "work2/f.txt", "outside/f.txt"] {
+            fs::write(base.join(file), "x").unwrap();
+        }
+        base
+    }
+
+    fn root(path: &Path) -> Vec<PathBuf> {
+        vec![validate_root(&path.to_string_lossy()).unwrap()]
+    }
+
+    fn s(path: PathBuf) -> String {
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn parent_components_cannot_leave_a_root() {
+        let base = tree("dotdot");
+        let roots = root(&base.join("work"));
+        assert!(check_in(&s(base.join("work/sub/../f.txt")), &roots).is_ok());
+        assert!(check_in(&s(base.join("work/../outside/f.txt")), &roots).is_err());
+        assert!(check_in(&s(base.join("work/sub/../../outside/f.txt")), &roots).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_leave_a_root() {
+        let base = tree("symlink");
+        std::os::unix::fs::symlink(base.join("outside"), base.join("work/link")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside/f.txt"), base.join("work/file-link")).unwrap();
+        let roots = root(&base.join("work"));
+        assert!(check_in(&s(base.join("work/link/f.txt")), &roots).is_err());
+        assert!(check_in(&s(base.join("work/file-link")), &roots).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retrieval_does_not_follow_links_out_of_a_root() {
+        let base = tree("retrieval");
+        fs::write(base.join("outside/secret.txt"), "zebracorn credentials").unwrap();
+        fs::write(base.join("work/notes.txt"), "zebracorn sightings").unwrap();
+        std::os::unix::fs::symlink(base.join("outside"), base.join("work/linked")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside/secret.txt"), base.join("work/secret.txt")).unwrap();
+        let roots = root(&base.join("work"));
+        let hits = crate::retrieval::search(&roots[0], "zebracorn", 10);
+        assert_eq!(hits.iter().map(|h| h.path.as_str()).collect::<Vec<_>>(), vec!["notes.txt"]);
+        for (_, path, _) in crate::files::walk_dir(&roots[0]) {
+            assert!(check_in(&s(path), &roots).is_ok());
+        }
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn sibling_with_a_common_prefix_is_outside() {
+        let base = tree("prefix");
+        let roots = root(&base.join("work"));
+        assert!(check_in(&s(base.join("work/f.txt")), &roots).is_ok());
+        assert!(check_in(&s(base.join("work2/f.txt")), &roots).is_err());
+        assert!(check_in(&s(base.join("work2")), &roots).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn non_canonical_roots_are_resolved() {
+        let base = tree("noncanon");
+        let roots = root(&base.join("work/sub/.././sub/.."));
+        assert_eq!(roots[0], base.join("work").canonicalize().unwrap());
+        assert!(check_in(&s(base.join("work/sub/f.txt")), &roots).is_ok());
+        assert!(check_in(&s(base.join("work2/f.txt")), &roots).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(base.join("work"), base.join("alias")).unwrap();
+            let roots = root(&base.join("alias"));
+            assert!(check_in(&s(base.join("work/f.txt")), &roots).is_ok());
+            assert!(check_in(&s(base.join("alias/f.txt")), &roots).is_ok());
+            assert!(check_in(&s(base.join("outside/f.txt")), &roots).is_err());
+        }
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn missing_paths_are_refused() {
+        let base = tree("missing");
+        let roots = root(&base.join("work"));
+        assert!(check_in(&s(base.join("work/nope.txt")), &roots).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+}
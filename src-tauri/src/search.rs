@@ -0,0 +1,438 @@
+// Output search across cells.
+//
+// The PTY reader thread feeds every chunk to `record`, which strips ANSI
+// sequences, splits it into lines and appends complete lines to a per-cell
+// history log (`<data>/history/<cell-id>.log`, one `<epoch-ms>\t<text>` per
+// line). This is a plain log, not an index: searches scan it newest first,
+// together with the line still being written, falling back to the live
+// buffer or saved output for cells that have no history yet. The rolling
+// summarizer reads the same log from the position its last summary covered.
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+
+use crate::ai::AnsiStripper;
+
+const MAX_LINE_CHARS: usize = 2000;
+/// Output that never ends a line (spinners and progress bars redrawn with
+/// `\r`) is cut into lines of this size instead of growing without bound.
+const MAX_PARTIAL_BYTES: usize = 4096;
+const MAX_LOG_BYTES: u64 = 4_000_000;
+const DEFAULT_LIMIT: usize = 200;
+
+/// Per-cell recording state, kept for as long as the cell's PTY runs.
+#[derive(Default)]
+struct CellLog {
+    stripper: AnsiStripper,
+    /// Unterminated trailing line, waiting for its newline.
+    partial: String,
+    /// Open log file and its size; opened on the first complete line.
+    file: Option<(File, u64)>,
+}
+
+#[derive(Default)]
+pub(crate) struct OutputHistory(Mutex<HashMap<String, CellLog>>);
+
+pub(crate) fn log_path(app: &tauri::AppHandle, cell_id: &str) -> PathBuf {
+    crate::storage::history_dir(app).join(format!("{}.log", cell_id))
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("log.1")
+}
+
+fn clean_line(line: &str) -> String {
+    let line = line.trim_end();
+    if line.len() <= MAX_LINE_CHARS {
+        return line.to_string();
+    }
+    let mut end = MAX_LINE_CHARS;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line[..end].to_string()
+}
+
+/// Index a chunk of PTY output for `cell_id`. Returns the lines it completed
+/// and the still-unterminated tail (e.g. a prompt waiting for input).
+pub(crate) fn record(app: &tauri::AppHandle, cell_id: &str, data: &str) -> (Vec<String>, String) {
+    let history = app.state::<OutputHistory>();
+    let mut logs = history.0.lock().unwrap();
+    let log = logs.entry(cell_id.to_string()).or_default();
+    let text = log.stripper.push(data);
+    let complete = take_lines(&mut log.partial, &text);
+    append_lines(app, cell_id, log, &complete);
+    (complete, clean_line(&log.partial))
+}
+
+/// Append `text` to the unterminated line `partial` and return the lines this
+/// completes. A line reaching `MAX_PARTIAL_BYTES` counts as complete.
+fn take_lines(partial: &mut String, text: &str) -> Vec<String> {
+    let mut done = Vec::new();
+    for mut piece in text.split_inclusive('\n') {
+        loop {
+            let room = MAX_PARTIAL_BYTES.saturating_sub(partial.len());
+            if piece.len() < room {
+                partial.push_str(piece);
+                break;
+            }
+            let mut end = room;
+            while !piece.is_char_boundary(end) {
+                end -= 1;
+            }
+            partial.push_str(&piece[..end]);
+            piece = &piece[end..];
+            done.push(std::mem::take(partial));
+        }
+        if partial.ends_with('\n') {
+            done.push(std::mem::take(partial));
+        }
+    }
+    done.iter().map(|line| clean_line(line)).filter(|l| !l.is_empty()).collect()
+}
+
+/// Flush the unterminated line and close the log when a cell's PTY exits.
+pub(crate) fn flush(app: &tauri::AppHandle, cell_id: &str) {
+    let history = app.state::<OutputHistory>();
+    let Some(mut log) = history.0.lock().unwrap().remove(cell_id) else { return };
+    let line = clean_line(&log.partial);
+    if !line.is_empty() {
+        append_lines(app, cell_id, &mut log, &[line]);
+    }
+}
+
+fn open_log(path: &Path) -> Option<(File, u64)> {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path).ok()?;
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    Some((file, len))
+}
+
+fn append_lines(app: &tauri::AppHandle, cell_id: &str, log: &mut CellLog, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    let path = log_path(app, cell_id);
+    // Keep one rotated generation so history stays bounded.
+    if log.file.as_ref().is_some_and(|(_, len)| *len > MAX_LOG_BYTES) {
+        log.file = None;
+        let _ = std::fs::rename(&path, rotated_path(&path));
+    }
+    if log.file.is_none() {
+        log.file = open_log(&path);
+    }
+    let Some((file, len)) = log.file.as_mut() else { return };
+    let now = crate::now_millis();
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&format!("{}\t{}\n", now, line));
+    }
+    if file.write_all(out.as_bytes()).is_ok() {
+        *len += out.len() as u64;
+    } else {
+        // Reopen next time, e.g. after the history directory was removed.
+        log.file = None;
+    }
+}
+
+/// The line `cell_id` is still writing, if any.
+fn pending_line(app: &tauri::AppHandle, cell_id: &str) -> Option<String> {
+    let history = app.state::<OutputHistory>();
+    let logs = history.0.lock().unwrap();
+    let line = clean_line(&logs.get(cell_id)?.partial);
+    (!line.is_empty()).then_some(line)
+}
+
+// ─── Reading since a position ────────────────────────────────────────────────
+
+/// Size of `cell_id`'s history log, i.e. the position after its last line.
+pub(crate) fn log_end(app: &tauri::AppHandle, cell_id: &str) -> u64 {
+    std::fs::metadata(log_path(app, cell_id)).map_or(0, |m| m.len())
+}
+
+/// Text of the lines logged for `cell_id` after `offset` (a `log_end` value),
+/// and the position after the last of them.
+pub(crate) fn read_since(app: &tauri::AppHandle, cell_id: &str, offset: u64) -> (Vec<String>, u64) {
+    lines_since(&log_path(app, cell_id), offset)
+}
+
+fn lines_since(path: &Path, offset: u64) -> (Vec<String>, u64) {
+    let end = std::fs::metadata(path).map_or(0, |m| m.len());
+    if end >= offset {
+        return read_from(path, offset);
+    }
+    // Shorter than `offset`: rotated since, so the rest of the old generation
+    // comes first. Rotation happens at MAX_LOG_BYTES, far apart from the
+    // positions readers keep, so the new log has not outgrown `offset` yet.
+    let (mut lines, _) = read_from(&rotated_path(path), offset);
+    let (newer, end) = read_from(path, 0);
+    lines.extend(newer);
+    (lines, end)
+}
+
+fn read_from(path: &Path, offset: u64) -> (Vec<String>, u64) {
+    let mut content = Vec::new();
+    let read = File::open(path).and_then(|mut file| {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_end(&mut content)
+    });
+    if read.is_err() {
+        return (Vec::new(), offset);
+    }
+    // Whole lines only: the reader thread may be halfway through an append.
+    let whole = content.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let lines = String::from_utf8_lossy(&content[..whole])
+        .lines()
+        .filter_map(|l| l.split_once('\t').map(|(_, text)| text.to_string()))
+        .collect();
+    (lines, offset + whole as u64)
+}
+
+// ─── Queries ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    pub query: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    pub cell_ids: Option<Vec<String>>,
+    /// "Stimulus" | "Will" | "Supply"; resolved with `cols`.
+    pub roles: Option<Vec<String>>,
+    pub cols: Option<u32>,
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub context: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub cell_id: String,
+    pub theme: String,
+    pub role: String,
+    /// None for saved outputs, which carry no per-line time.
+    pub timestamp_ms: Option<u64>,
+    pub line: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    pub source: String,
+}
+
+struct Line {
+    timestamp_ms: Option<u64>,
+    text: String,
+}
+
+fn read_log(path: &Path) -> Vec<Line> {
+    let Ok(content) = std::fs::read_to_string(path) else { return Vec::new() };
+    content
+        .lines()
+        .filter_map(|l| {
+            let (ts, text) = l.split_once('\t')?;
+            Some(Line { timestamp_ms: ts.parse().ok(), text: text.to_string() })
+        })
+        .collect()
+}
+
+fn build_matcher(q: &SearchQuery) -> Result<Regex, String> {
+    let pattern = if q.regex { q.query.clone() } else { regex::escape(&q.query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!q.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+fn in_range(q: &SearchQuery, ts: Option<u64>) -> bool {
+    match ts {
+        Some(ts) => q.since_ms.map_or(true, |s| ts >= s) && q.until_ms.map_or(true, |u| ts <= u),
+        // Untimed lines only match when no time filter is given.
+        None => q.since_ms.is_none() && q.until_ms.is_none(),
+    }
+}
+
+pub(crate) fn search(
+    app: &tauri::AppHandle,
+    cells: &[crate::CellState],
+    q: &SearchQuery,
+) -> Result<Vec<SearchHit>, String> {
+    if q.query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let matcher = build_matcher(q)?;
+    let cols = q.cols.unwrap_or(3) as usize;
+    let context = q.context.unwrap_or(2);
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT);
+    let saved = crate::storage::load_cell_outputs(app);
+
+    let mut hits = Vec::new();
+    for cell in cells {
+        if let Some(ids) = &q.cell_ids {
+            if !ids.contains(&cell.id) {
+                continue;
+            }
+        }
+        let role = crate::ai::get_cell_role(&cell.id, cols);
+        if let Some(roles) = &q.roles {
+            if !roles.iter().any(|r| r.eq_ignore_ascii_case(role)) {
+                continue;
+            }
+        }
+
+        let path = log_path(app, &cell.id);
+        let mut lines = read_log(&rotated_path(&path));
+        lines.extend(read_log(&path));
+        let source = if lines.is_empty() {
+            // No history yet (e.g. output from before history existed): the
+            // live buffer, or the saved output when the cell isn't running.
+            let raw = if cell.last_output.is_empty() {
+                saved.get(&cell.id).map(String::as_str).unwrap_or_default()
+            } else {
+                cell.last_output.as_str()
+            };
+            lines = crate::ai::strip_ansi(raw)
+                .lines()
+                .map(clean_line)
+                .filter(|l| !l.is_empty())
+                .map(|text| Line { timestamp_ms: None, text })
+                .collect();
+            "saved"
+        } else {
+            if let Some(text) = pending_line(app, &cell.id) {
+                lines.push(Line { timestamp_ms: Some(crate::now_millis()), text });
+            }
+            "history"
+        };
+
+        // Newest first, so a cell stops contributing once it has `limit` hits.
+        let mut found = 0;
+        for (i, line) in lines.iter().enumerate().rev() {
+            if found == limit {
+                break;
+            }
+            if !in_range(q, line.timestamp_ms) || !matcher.is_match(&line.text) {
+                continue;
+            }
+            found += 1;
+            let start = i.saturating_sub(context);
+            let end = (i + 1 + context).min(lines.len());
+            hits.push(SearchHit {
+                cell_id: cell.id.clone(),
+                theme: cell.theme.clone(),
+                role: role.to_string(),
+                timestamp_ms: line.timestamp_ms,
+                line: line.text.clone(),
+                before: lines[start..i].iter().map(|l| l.text.clone()).collect(),
+                after: lines[i + 1..end].iter().map(|l| l.text.clone()).collect(),
+                source: source.to_string(),
+            });
+        }
+    }
+
+    hits.sort_by_key(|h| std::cmp::Reverse(h.timestamp_ms.unwrap_or(0)));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-search-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("cell-0.log")
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn splits_chunks_into_lines() {
+        let mut partial = String::new();
+        assert!(take_lines(&mut partial, "one\ntw").eq(&["one"]));
+        assert_eq!(partial, "tw");
+        assert!(take_lines(&mut partial, "o\n\nthree\n").eq(&["two", "three"]));
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn output_without_newlines_is_cut_into_bounded_lines() {
+        let mut partial = String::new();
+        let mut lines = Vec::new();
+        // A progress bar redrawn in place: the stripper has already dropped the `\r`.
+        let chunk = "█ 42% ".repeat(1000);
+        let mut fed = 0;
+        while fed < 1_000_000 {
+            lines.extend(take_lines(&mut partial, &chunk));
+            assert!(partial.len() < MAX_PARTIAL_BYTES);
+            fed += chunk.len();
+        }
+        assert!(lines.len() >= fed / MAX_PARTIAL_BYTES);
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_CHARS));
+
+        // The next newline still ends the line that was in progress.
+        partial.clear();
+        take_lines(&mut partial, "abc");
+        assert!(take_lines(&mut partial, "def\n").eq(&["abcdef"]));
+    }
+
+    #[test]
+    fn reads_whole_lines_after_the_offset() {
+        let path = temp_log("offset");
+        append(&path, "1\tfirst\n2\tsecond\n");
+        let (lines, end) = lines_since(&path, 0);
+        assert_eq!(lines, vec!["first", "second"]);
+        assert_eq!(end, 17);
+
+        // A line still being written is left for the next read.
+        append(&path, "3\tthird\n4\tfou");
+        let (lines, end) = lines_since(&path, end);
+        assert_eq!(lines, vec!["third"]);
+        append(&path, "rth\n");
+        assert_eq!(lines_since(&path, end).0, vec!["fourth"]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn reads_across_a_rotation() {
+        let path = temp_log("rotation");
+        append(&path, "1\tread before\n2\tunread\n");
+        let offset = 14;
+        std::fs::rename(&path, rotated_path(&path)).unwrap();
+        append(&path, "3\tnew\n");
+        let (lines, end) = lines_since(&path, offset);
+        assert_eq!(lines, vec!["unread", "new"]);
+        assert_eq!(end, 6);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn missing_log_reads_nothing() {
+        let path = temp_log("missing");
+        assert_eq!(lines_since(&path, 0), (Vec::new(), 0));
+        assert_eq!(lines_since(&path, 40), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn clean_line_trims_and_caps_length() {
+        assert_eq!(clean_line("done   "), "done");
+        let long = "é".repeat(MAX_LINE_CHARS);
+        let capped = clean_line(&long);
+        assert!(capped.len() <= MAX_LINE_CHARS && capped.chars().all(|c| c == 'é'));
+    }
+}
@@ -4,8 +4,13 @@ use std::fs;
 use std::path::PathBuf;
 use tauri::Manager;
 
-const MAX_HISTORY: usize = 20;
+pub const DEFAULT_ANALYSIS_RETENTION: usize = 200;
 const MAX_OUTPUT_CHARS: usize = 5000;
+const SETTINGS_FILE: &str = "settings.json";
+const ANALYSES_DIR: &str = "analyses";
+const ANALYSIS_INDEX_FILE: &str = "analysis-index.jsonl";
+/// Single-file history written by older versions; moved into `ANALYSES_DIR` on the next save.
+const LEGACY_ANALYSIS_FILE: &str = "analysis-history.json";
 
 // ─── AI Config ───────────────────────────────────────────────────────────────
 
@@ -18,6 +23,47 @@ pub struct AiConfig {
     pub anthropic_key: String,
     pub model: Option<String>,  // None = use provider default
     pub ollama_url: String,
+    /// Tried in order when the main provider fails with a quota, auth or outage error.
+    #[serde(default)]
+    pub fallbacks: Vec<ProviderChoice>,
+    /// Per-task overrides keyed by "name", "summarize", "analyze" or "chat".
+    #[serde(default)]
+    pub tasks: HashMap<String, TaskModel>,
+    /// Sampling temperature; None = provider default. Normally set per task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Paid AI calls are refused once this month's estimated spend reaches it.
+    #[serde(default)]
+    pub monthly_cap_usd: Option<f64>,
+    /// Prices by model name prefix, ahead of the built-in list. With a cap set,
+    /// a paid model priced in neither is refused.
+    #[serde(default)]
+    pub model_prices: HashMap<String, ModelPrice>,
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Model settings for one AI task; unset fields fall back to the main config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskModel {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderChoice {
+    pub provider: String,
+    pub model: Option<String>,
 }
 
 impl Default for AiConfig {
@@ -31,6 +77,11 @@ impl Default for AiConfig {
             anthropic_key: String::new(),
             model: None,
             ollama_url: "http://localhost:11434".to_string(),
+            fallbacks: Vec::new(),
+            tasks: HashMap::new(),
+            temperature: None,
+            monthly_cap_usd: None,
+            model_prices: HashMap::new(),
         }
     }
 }
@@ -59,12 +110,85 @@ pub fn save_ai_config(config: &AiConfig) -> Result<(), String> {
     Ok(())
 }
 
+// ─── App settings ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppSettings {
+    /// Analyses kept in history.
+    pub analysis_retention: usize,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings { analysis_retention: DEFAULT_ANALYSIS_RETENTION }
+    }
+}
+
+pub fn load_settings(app: &tauri::AppHandle) -> AppSettings {
+    load_json(app, SETTINGS_FILE)
+}
+
+/// Create `<data>/settings.json` on first start, taking over `analysisRetention`
+/// from the AI config file, where older versions kept it.
+pub fn init_settings(app: &tauri::AppHandle) {
+    if data_dir(app).join(SETTINGS_FILE).exists() {
+        return;
+    }
+    let legacy = fs::read_to_string(config_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|config| config.get("analysisRetention")?.as_u64());
+    let settings = match legacy {
+        Some(retention) => AppSettings { analysis_retention: retention as usize },
+        None => AppSettings::default(),
+    };
+    if let Err(e) = save_settings(app, &settings) {
+        eprintln!("settings: {}", e);
+    }
+}
+
+pub fn save_settings(app: &tauri::AppHandle, settings: &AppSettings) -> Result<(), String> {
+    save_json(app, SETTINGS_FILE, settings)
+}
+
+// ─── Analysis history ────────────────────────────────────────────────────────
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisEntry {
-    pub timestamp: String,
+    /// Empty for entries saved before ids existed; filled in on load.
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub timestamp_ms: u64,
+    /// ISO timestamp written by older versions; migrated to `timestamp_ms` on load.
+    #[serde(default, skip_serializing)]
+    timestamp: Option<String>,
     pub summaries: HashMap<String, String>,
     pub themes: HashMap<String, String>,
     pub ideas: Vec<String>,
+    #[serde(default)]
+    pub flow: Option<crate::FlowAnalysis>,
+    /// What the analysis was run on.
+    #[serde(default)]
+    pub input: Option<AnalysisInput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisInput {
+    pub language: String,
+    pub cols: usize,
+    pub output_dir: Option<String>,
+    pub cells: Vec<CellSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellSnapshot {
+    pub id: String,
+    pub theme: String,
+    pub status: String,
+    /// ANSI-stripped tail of the output the analysis saw.
+    pub output: String,
 }
 
 fn data_dir(app: &tauri::AppHandle) -> PathBuf {
@@ -74,12 +198,52 @@ fn data_dir(app: &tauri::AppHandle) -> PathBuf {
         .join("chaos-grid")
 }
 
+/// Directory holding per-cell output history logs (see `search`).
+pub fn history_dir(app: &tauri::AppHandle) -> PathBuf {
+    data_dir(app).join("history")
+}
+
 fn ensure_dir(dir: &PathBuf) {
     if !dir.exists() {
         let _ = fs::create_dir_all(dir);
     }
 }
 
+/// Load a JSON document from the data dir, falling back to its default.
+pub fn load_json<T: serde::de::DeserializeOwned + Default>(app: &tauri::AppHandle, name: &str) -> T {
+    fs::read_to_string(data_dir(app).join(name))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_json<T: Serialize>(app: &tauri::AppHandle, name: &str, value: &T) -> Result<(), String> {
+    let dir = data_dir(app);
+    ensure_dir(&dir);
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    fs::write(dir.join(name), json).map_err(|e| e.to_string())
+}
+
+/// Append one record to a JSON-lines log in the data dir.
+pub fn append_jsonl<T: Serialize>(app: &tauri::AppHandle, name: &str, value: &T) -> Result<(), String> {
+    use std::io::Write;
+    let dir = data_dir(app);
+    ensure_dir(&dir);
+    let line = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(name))
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
+
+/// Read a JSON-lines log, skipping lines that don't parse.
+pub fn load_jsonl<T: serde::de::DeserializeOwned>(app: &tauri::AppHandle, name: &str) -> Vec<T> {
+    let Ok(content) = fs::read_to_string(data_dir(app).join(name)) else { return Vec::new() };
+    content.lines().filter_map(|l| serde_json::from_str(l).ok()).collect()
+}
+
 pub fn load_cell_outputs(app: &tauri::AppHandle) -> HashMap<String, String> {
     let path = data_dir(app).join("cell-outputs.json");
     if !path.exists() {
@@ -114,44 +278,175 @@ pub fn save_cell_output(app: &tauri::AppHandle, cell_id: &str, buffer: &str) {
     }
 }
 
-pub fn load_analysis_history(app: &tauri::AppHandle) -> Vec<AnalysisEntry> {
-    let path = data_dir(app).join("analysis-history.json");
-    if !path.exists() {
-        return Vec::new();
+/// One line of the analysis index: enough to list analyses without reading them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisIndexEntry {
+    pub id: String,
+    pub timestamp_ms: u64,
+    pub cell_count: usize,
+    pub confidence: Option<String>,
+    pub priority_cell: Option<String>,
+    pub next: Option<String>,
+}
+
+impl AnalysisIndexEntry {
+    fn of(entry: &AnalysisEntry) -> Self {
+        let flow = entry.flow.as_ref();
+        AnalysisIndexEntry {
+            id: entry.id.clone(),
+            timestamp_ms: entry.timestamp_ms,
+            cell_count: entry.summaries.len(),
+            confidence: flow.and_then(|f| f.confidence.clone()),
+            priority_cell: flow.and_then(|f| f.priority_cell.clone()),
+            next: flow.map(|f| f.next.clone()),
+        }
     }
-    match fs::read_to_string(&path) {
-        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
-        Err(_) => Vec::new(),
+}
+
+/// Ids as `save_analysis` and the legacy migration generate them: `analysis-<ms>` or `legacy-<n>`.
+fn is_analysis_id(id: &str) -> bool {
+    let number = id.strip_prefix("analysis-").or_else(|| id.strip_prefix("legacy-"));
+    number.is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn analysis_path(app: &tauri::AppHandle, id: &str) -> Result<PathBuf, String> {
+    if !is_analysis_id(id) {
+        return Err(format!("invalid analysis id: {}", id));
     }
+    Ok(data_dir(app).join(ANALYSES_DIR).join(format!("{}.json", id)))
 }
 
+/// The single-file history of older versions, with ids and timestamps filled in.
+/// Read-only: `save_analysis` moves it into the new layout.
+fn load_legacy_history(app: &tauri::AppHandle) -> Vec<AnalysisEntry> {
+    let Ok(content) = fs::read_to_string(data_dir(app).join(LEGACY_ANALYSIS_FILE)) else { return Vec::new() };
+    let mut history: Vec<AnalysisEntry> = serde_json::from_str(&content).unwrap_or_default();
+    for (i, entry) in history.iter_mut().enumerate() {
+        if entry.id.is_empty() {
+            entry.id = format!("legacy-{}", i);
+        }
+        if let Some(iso) = entry.timestamp.take() {
+            if entry.timestamp_ms == 0 {
+                entry.timestamp_ms = crate::time::parse_iso(&iso).unwrap_or(0);
+            }
+        }
+    }
+    history
+}
+
+/// Every saved analysis, oldest first, without reading the analyses themselves.
+pub fn analysis_index(app: &tauri::AppHandle) -> Vec<AnalysisIndexEntry> {
+    let mut index: Vec<AnalysisIndexEntry> = load_legacy_history(app).iter().map(AnalysisIndexEntry::of).collect();
+    index.extend(load_jsonl::<AnalysisIndexEntry>(app, ANALYSIS_INDEX_FILE));
+    index
+}
+
+pub fn load_analysis(app: &tauri::AppHandle, id: &str) -> Result<AnalysisEntry, String> {
+    if let Ok(content) = fs::read_to_string(analysis_path(app, id)?) {
+        return serde_json::from_str(&content).map_err(|e| format!("analysis {}: {}", id, e));
+    }
+    load_legacy_history(app)
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or_else(|| format!("no analysis {}", id))
+}
+
+/// The newest `limit` analyses, oldest first.
+pub fn load_recent_analyses(app: &tauri::AppHandle, limit: usize) -> Vec<AnalysisEntry> {
+    let legacy = load_legacy_history(app);
+    let index = load_jsonl::<AnalysisIndexEntry>(app, ANALYSIS_INDEX_FILE);
+    let from_index = index.len().min(limit);
+    let mut entries: Vec<AnalysisEntry> = legacy
+        .into_iter()
+        .rev()
+        .take(limit - from_index)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    for item in &index[index.len() - from_index..] {
+        let Ok(path) = analysis_path(app, &item.id) else { continue };
+        match fs::read_to_string(path).map(|c| serde_json::from_str(&c)) {
+            Ok(Ok(entry)) => entries.push(entry),
+            _ => eprintln!("analysis history: {} is missing or unreadable", item.id),
+        }
+    }
+    entries
+}
+
+fn write_analysis(app: &tauri::AppHandle, entry: &AnalysisEntry) -> Result<(), String> {
+    let path = analysis_path(app, &entry.id)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())?;
+    append_jsonl(app, ANALYSIS_INDEX_FILE, &AnalysisIndexEntry::of(entry))
+}
+
+/// Move the single-file history of older versions into the per-analysis layout.
+fn migrate_legacy_history(app: &tauri::AppHandle) -> Result<(), String> {
+    let legacy = load_legacy_history(app);
+    let path = data_dir(app).join(LEGACY_ANALYSIS_FILE);
+    if legacy.is_empty() && !path.exists() {
+        return Ok(());
+    }
+    for entry in &legacy {
+        if !is_analysis_id(&entry.id) {
+            eprintln!("analysis history: dropping legacy entry with id {:?}", entry.id);
+            continue;
+        }
+        write_analysis(app, entry)?;
+    }
+    fs::rename(&path, path.with_extension("json.migrated")).map_err(|e| e.to_string())
+}
+
+/// Drop the oldest analyses beyond `retention`.
+fn prune_analyses(app: &tauri::AppHandle, retention: usize) -> Result<(), String> {
+    let index = load_jsonl::<AnalysisIndexEntry>(app, ANALYSIS_INDEX_FILE);
+    if index.len() <= retention {
+        return Ok(());
+    }
+    let (dropped, kept) = index.split_at(index.len() - retention);
+    for path in dropped.iter().filter_map(|item| analysis_path(app, &item.id).ok()) {
+        let _ = fs::remove_file(path);
+    }
+    let mut lines = String::new();
+    for item in kept {
+        lines.push_str(&serde_json::to_string(item).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    // Write the new index beside the old one, then swap, so a crash leaves one of them whole.
+    let path = data_dir(app).join(ANALYSIS_INDEX_FILE);
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, lines).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())
+}
+
+/// Save an analysis in its own file and add it to the index, keeping the
+/// newest `retention` entries. Returns its id.
 pub fn save_analysis(
     app: &tauri::AppHandle,
     result: &crate::AnalyzeResult,
-    themes: HashMap<String, String>,
-) {
-    let dir = data_dir(app);
-    ensure_dir(&dir);
-    let path = dir.join("analysis-history.json");
-
-    let mut history = load_analysis_history(app);
+    input: AnalysisInput,
+    retention: usize,
+) -> Result<String, String> {
+    migrate_legacy_history(app)?;
+    let now = crate::now_millis();
     let entry = AnalysisEntry {
-        timestamp: chrono_now_iso(),
+        id: format!("analysis-{}", now),
+        timestamp_ms: now,
+        timestamp: None,
         summaries: result.summaries.clone(),
-        themes,
+        themes: input.cells.iter().map(|c| (c.id.clone(), c.theme.clone())).collect(),
         ideas: result.ideas.clone(),
+        flow: result.flow.clone(),
+        input: Some(input),
     };
-    history.push(entry);
-
-    // Keep last N
-    if history.len() > MAX_HISTORY {
-        let start = history.len() - MAX_HISTORY;
-        history = history[start..].to_vec();
-    }
-
-    if let Ok(json) = serde_json::to_string_pretty(&history) {
-        let _ = fs::write(&path, json);
-    }
+    write_analysis(app, &entry)?;
+    prune_analyses(app, retention.max(1))?;
+    Ok(entry.id)
 }
 
 // ─── Session Restore ──────────────────────────────────────────────────────────
@@ -191,41 +486,17 @@ pub fn load_session(app: &tauri::AppHandle) -> Option<SavedSession> {
     serde_json::from_str(&content).ok()
 }
 
-fn chrono_now_iso() -> String {
-    // Simple ISO timestamp without chrono dependency
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let dur = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    let secs = dur.as_secs();
-    // Format as ISO-ish: use seconds since epoch as a fallback
-    // For proper ISO, we'd need chrono, but let's keep deps minimal
-    let days = secs / 86400;
-    let remaining = secs % 86400;
-    let hours = remaining / 3600;
-    let minutes = (remaining % 3600) / 60;
-    let seconds = remaining % 60;
-
-    // Calculate year/month/day from days since epoch (1970-01-01)
-    let (year, month, day) = days_to_date(days);
-
-    format!(
-        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000Z",
-        year, month, day, hours, minutes, seconds
-    )
-}
-
-fn days_to_date(days_since_epoch: u64) -> (u64, u64, u64) {
-    // Algorithm from http://howardhinnant.github.io/date_algorithms.html
-    let z = days_since_epoch + 719468;
-    let era = z / 146097;
-    let doe = z - era * 146097;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if m <= 2 { y + 1 } else { y };
-    (y, m, d)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analysis_ids_must_have_the_generated_form() {
+        for id in ["analysis-1760000000000", "legacy-0", "legacy-12"] {
+            assert!(is_analysis_id(id), "{}", id);
+        }
+        for id in ["", "analysis-", "legacy-x", "../../x", "analysis-1/../../x", "analysis-1.json", "other-1", "analysis-１"] {
+            assert!(!is_analysis_id(id), "{}", id);
+        }
+    }
 }
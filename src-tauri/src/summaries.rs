@@ -0,0 +1,289 @@
+// Per-cell AI summaries. `summarize` folds the output a cell printed since its
+// last summary into that summary; with the rolling summarizer enabled this runs
+// in the background whenever a cell has printed another `everyKb` of output.
+// New output is read from the cell's history log (see `search`), which keeps far
+// more than the live buffer, from the position the previous summary covered.
+// Summaries are kept on `CellState` and persisted to `<data>/cell-summaries.json`,
+// and grid analysis uses them in place of long raw output.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+use crate::ai::AiTask;
+use crate::storage::AiConfig;
+use crate::{CellState, CellStateMap};
+
+const CONFIG_FILE: &str = "summarizer.json";
+const SUMMARIES_FILE: &str = "cell-summaries.json";
+const TICK: Duration = Duration::from_secs(15);
+const ERROR_BACKOFF: Duration = Duration::from_secs(300);
+/// Most new output folded into a summary in one call.
+const NEW_OUTPUT_TOKENS: usize = 3000;
+const MAX_SUMMARY_TOKENS: u32 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellSummary {
+    pub text: String,
+    pub updated_at: u64,
+    /// End of the cell's history log (`search::log_end`) when the summary was made.
+    #[serde(default)]
+    pub log_offset: u64,
+    /// `CellState::output_bytes` at the time of the summary; not persisted
+    /// because the byte counter restarts with the app.
+    #[serde(skip)]
+    pub covered_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SummarizerConfig {
+    pub enabled: bool,
+    /// Re-summarize a cell after this much new output, counted without ANSI sequences.
+    pub every_kb: u64,
+    pub language: Option<String>,
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        SummarizerConfig { enabled: false, every_kb: 8, language: None }
+    }
+}
+
+pub(crate) struct Summarizer(Mutex<SummarizerConfig>);
+
+impl Summarizer {
+    pub(crate) fn load(app: &tauri::AppHandle) -> Self {
+        Summarizer(Mutex::new(crate::storage::load_json(app, CONFIG_FILE)))
+    }
+
+    pub(crate) fn config(&self) -> SummarizerConfig {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set(&self, app: &tauri::AppHandle, mut config: SummarizerConfig) -> Result<SummarizerConfig, String> {
+        config.every_kb = config.every_kb.max(1);
+        crate::storage::save_json(app, CONFIG_FILE, &config)?;
+        *self.0.lock().unwrap() = config.clone();
+        Ok(config)
+    }
+}
+
+pub(crate) fn load_saved(app: &tauri::AppHandle) -> HashMap<String, CellSummary> {
+    crate::storage::load_json(app, SUMMARIES_FILE)
+}
+
+fn save_all(app: &tauri::AppHandle) -> Result<(), String> {
+    let summaries: HashMap<String, CellSummary> = {
+        let states = app.state::<CellStateMap>();
+        let states = states.0.lock().map_err(|e| e.to_string())?;
+        states
+            .values()
+            .filter_map(|c| c.summary.clone().map(|s| (c.id.clone(), s)))
+            .collect()
+    };
+    crate::storage::save_json(app, SUMMARIES_FILE, &summaries)
+}
+
+/// Output printed since the cell's summary was made (all of it if there is none),
+/// limited to what the output buffer still holds. Analysis uses this; the
+/// summarizer itself reads the history log.
+pub(crate) fn new_output(cell: &CellState) -> &str {
+    let Some(summary) = &cell.summary else { return &cell.last_output };
+    let unseen = cell.output_bytes.saturating_sub(summary.covered_bytes) as usize;
+    let mut start = cell.last_output.len().saturating_sub(unseen);
+    while !cell.last_output.is_char_boundary(start) {
+        start += 1;
+    }
+    &cell.last_output[start..]
+}
+
+fn build_prompt(cell: &CellState, previous: Option<&str>, output: &str, language: &str) -> String {
+    let previous = match previous {
+        Some(text) => format!("## Summary so far\n{}\n\n## New output since then\n", text),
+        None => "## Output\n".to_string(),
+    };
+    format!(
+        "You keep a running summary of one terminal cell where an AI coding agent works.\n\
+         Cell: [{}] {}\n\n{}{}\n\n\
+         Write the updated summary: what the agent is working on, what it finished, and any open \
+         problem or question. At most 5 short sentences, no headings. Respond in: {}",
+        cell.id,
+        cell.theme,
+        previous,
+        output,
+        language
+    )
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CellSummaryPayload {
+    cell_id: String,
+    summary: CellSummary,
+}
+
+/// Fold the cell's new output into its summary, store it and emit `cell-summary`.
+pub(crate) async fn summarize(app: &tauri::AppHandle, cell_id: &str, language: &str) -> Result<CellSummary, String> {
+    let cell = app
+        .state::<CellStateMap>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(cell_id)
+        .cloned()
+        .ok_or_else(|| format!("unknown cell {}", cell_id))?;
+    let offset = cell.summary.as_ref().map_or(0, |s| s.log_offset);
+    let (lines, log_offset) = crate::search::read_since(app, cell_id, offset);
+    let output = if log_offset == 0 {
+        // No history logged yet, e.g. output restored from a previous run.
+        crate::ai::clean_screen(new_output(&cell))
+    } else {
+        crate::ai::clean_screen(&lines.join("\n"))
+    };
+    let previous = cell.summary.as_ref().map(|s| s.text.as_str());
+    if output.is_empty() {
+        if let Some(summary) = &cell.summary {
+            return Ok(summary.clone());
+        }
+        return Err(format!("{} has no output to summarize", cell_id));
+    }
+
+    let (config, max_tokens) = AiTask::Summarize.apply(&app.state::<Mutex<AiConfig>>().lock().unwrap(), MAX_SUMMARY_TOKENS);
+    let output = crate::ai::fit_tail(&config, &output, NEW_OUTPUT_TOKENS);
+    let prompt = build_prompt(&cell, previous, &output, language);
+    let text = crate::ai::call_ai(AiTask::Summarize, &config, &prompt, max_tokens).await?;
+    let summary = CellSummary {
+        text: text.trim().to_string(),
+        updated_at: crate::now_millis(),
+        log_offset,
+        covered_bytes: cell.output_bytes,
+    };
+
+    if let Some(state) = app.state::<CellStateMap>().0.lock().map_err(|e| e.to_string())?.get_mut(cell_id) {
+        state.summary = Some(summary.clone());
+    }
+    save_all(app)?;
+    let _ = app.emit(
+        "cell-summary",
+        CellSummaryPayload { cell_id: cell_id.to_string(), summary: summary.clone() },
+    );
+    Ok(summary)
+}
+
+/// Bytes of text in `lines`, newlines included.
+fn text_bytes(lines: &[String]) -> u64 {
+    lines.iter().map(|l| l.len() as u64 + 1).sum()
+}
+
+/// Whether the log grew by `threshold` bytes of text since `offset`.
+fn is_due(app: &tauri::AppHandle, cell_id: &str, offset: u64, threshold: u64) -> bool {
+    // Log lines also carry a timestamp, so the file's growth is an upper bound.
+    let end = crate::search::log_end(app, cell_id);
+    if end >= offset && end - offset < threshold {
+        return false;
+    }
+    text_bytes(&crate::search::read_since(app, cell_id, offset).0) >= threshold
+}
+
+/// Cells that printed at least `every_kb` since their last summary.
+fn due_cells(app: &tauri::AppHandle, every_kb: u64) -> Vec<String> {
+    let offsets: Vec<(String, u64)> = {
+        let states = app.state::<CellStateMap>();
+        let states = states.0.lock().unwrap();
+        states
+            .values()
+            .map(|c| (c.id.clone(), c.summary.as_ref().map_or(0, |s| s.log_offset)))
+            .collect()
+    };
+    let mut due: Vec<String> = offsets
+        .into_iter()
+        .filter(|(id, offset)| is_due(app, id, *offset, every_kb * 1024))
+        .map(|(id, _)| id)
+        .collect();
+    due.sort();
+    due
+}
+
+/// Background loop for the rolling summarizer; idles while it is disabled.
+pub(crate) fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut retry_after: Option<Instant> = None;
+        loop {
+            tokio::time::sleep(TICK).await;
+            let config = app.state::<Summarizer>().config();
+            if !config.enabled || retry_after.is_some_and(|at| Instant::now() < at) {
+                continue;
+            }
+            let language = config.language.as_deref().unwrap_or("English");
+            for cell_id in due_cells(&app, config.every_kb) {
+                if let Err(e) = summarize(&app, &cell_id, language).await {
+                    // Usually a provider problem (no key, rate limit): back off instead of retrying every tick.
+                    eprintln!("summarizer: {}: {}", cell_id, e);
+                    retry_after = Some(Instant::now() + ERROR_BACKOFF);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(output: &str, output_bytes: u64, covered_bytes: Option<u64>) -> CellState {
+        CellState {
+            id: "cell-0".to_string(),
+            theme: "parser".to_string(),
+            pid: None,
+            last_output: output.to_string(),
+            status: "idle".to_string(),
+            updated_at: 0,
+            output_bytes,
+            summary: covered_bytes.map(|covered_bytes| CellSummary {
+                text: "earlier work".to_string(),
+                updated_at: 0,
+                log_offset: 0,
+                covered_bytes,
+            }),
+        }
+    }
+
+    #[test]
+    fn text_bytes_counts_newlines() {
+        assert_eq!(text_bytes(&[]), 0);
+        assert_eq!(text_bytes(&["ab".to_string(), "日本".to_string()]), 3 + 7);
+    }
+
+    #[test]
+    fn new_output_is_what_follows_the_summary() {
+        assert_eq!(new_output(&cell("old\nnew", 7, None)), "old\nnew");
+        assert_eq!(new_output(&cell("old\nnew", 7, Some(4))), "new");
+        assert_eq!(new_output(&cell("old\nnew", 7, Some(7))), "");
+        // More printed than the buffer holds: all of the buffer.
+        assert_eq!(new_output(&cell("old\nnew", 9000, Some(10))), "old\nnew");
+        // Never splits a character.
+        assert_eq!(new_output(&cell("é!", 2, Some(0))), "!");
+    }
+
+    #[test]
+    fn summaries_saved_before_log_offsets_load() {
+        let summary: CellSummary = serde_json::from_str(r#"{"text": "t", "updatedAt": 5}"#).unwrap();
+        assert_eq!(summary.log_offset, 0);
+        assert_eq!(summary.covered_bytes, 0);
+    }
+
+    #[test]
+    fn prompt_carries_previous_summary_and_new_output() {
+        let c = cell("", 0, None);
+        let first = build_prompt(&c, None, "cargo test", "English");
+        assert!(first.contains("## Output\ncargo test") && first.contains("[cell-0] parser"));
+        let next = build_prompt(&c, Some("earlier work"), "more", "日本語");
+        assert!(next.contains("## Summary so far\nearlier work\n\n## New output since then\nmore"));
+        assert!(next.ends_with("Respond in: 日本語"));
+    }
+}
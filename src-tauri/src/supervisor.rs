@@ -0,0 +1,454 @@
+// Opt-in supervisor: runs the flow analysis on a timer (and optionally when a
+// cell changes status), then dispatches the prompts it proposes to idle cells,
+// within guardrails:
+//   - only cells in `allowed_cells`, only `send_prompt` / `enqueue_task`,
+//   - at most `max_actions_per_hour` dispatches,
+//   - a daily token budget for the analysis calls, counted from the usage ledger,
+//   - prompts containing a blocked command are refused.
+// `dry_run` logs decisions without dispatching. `kill` stops it immediately and
+// persists `enabled: false`. Every run is logged to `<data>/supervisor-log.jsonl`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Listener, Manager};
+
+use crate::actions::ActionContext;
+use crate::{CellState, FlowAction, FlowActionKind};
+
+const CONFIG_FILE: &str = "supervisor.json";
+const LOG_FILE: &str = "supervisor-log.jsonl";
+const TICK: Duration = Duration::from_secs(5);
+/// Minimum gap between runs caused by status changes.
+const STATUS_CHANGE_GAP: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SupervisorConfig {
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub interval_secs: u64,
+    pub on_status_change: bool,
+    /// A cell counts as idle after this long without output.
+    pub idle_secs: u64,
+    pub allowed_cells: Vec<String>,
+    pub max_actions_per_hour: u32,
+    /// Tokens the supervisor's analyses may spend per day (0 = unlimited). A run
+    /// starts only while today's total is below it, so one run may overshoot.
+    pub daily_token_budget: u64,
+    /// Case-insensitive substrings that make a prompt ineligible.
+    pub blocked_commands: Vec<String>,
+    pub language: Option<String>,
+    pub cols: u32,
+    pub output_dir: Option<String>,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            enabled: false,
+            dry_run: true,
+            interval_secs: 600,
+            on_status_change: false,
+            idle_secs: 120,
+            allowed_cells: Vec::new(),
+            max_actions_per_hour: 6,
+            daily_token_budget: 200_000,
+            blocked_commands: ["rm -rf", "sudo", "git push", "--force", "drop table", "mkfs"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            language: None,
+            cols: 3,
+            output_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorDecision {
+    pub action: FlowAction,
+    /// "dispatched" | "dry-run" | "skipped" | "failed"
+    pub outcome: String,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorRun {
+    pub timestamp_ms: u64,
+    /// "interval" | "status-change" | "manual"
+    pub trigger: String,
+    pub dry_run: bool,
+    /// The analysis' own reasoning: what is stuck and what should happen next.
+    pub stuck: String,
+    pub next: String,
+    pub decisions: Vec<SupervisorDecision>,
+    pub error: Option<String>,
+    /// Tokens the run's analysis used, from the usage ledger.
+    #[serde(default)]
+    pub tokens: u64,
+}
+
+struct State {
+    config: SupervisorConfig,
+    last_run: Option<Instant>,
+    status_changed: bool,
+    dispatched: VecDeque<Instant>,
+    /// (day number, tokens spent that day)
+    spent: (u64, u64),
+}
+
+pub(crate) struct Supervisor {
+    state: Mutex<State>,
+    running: AtomicBool,
+    killed: AtomicBool,
+}
+
+fn today() -> u64 {
+    crate::now_millis() / 86_400_000
+}
+
+/// Clears `Supervisor::running` however a run ends, including by panic or cancellation.
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn load(app: &tauri::AppHandle) -> Self {
+        let config: SupervisorConfig = crate::storage::load_json(app, CONFIG_FILE);
+        let killed = AtomicBool::new(!config.enabled);
+        // Carry today's spend over a restart.
+        let spent_today = crate::storage::load_jsonl::<SupervisorRun>(app, LOG_FILE)
+            .iter()
+            .filter(|run| run.timestamp_ms / 86_400_000 == today())
+            .map(|run| run.tokens)
+            .sum();
+        Supervisor {
+            state: Mutex::new(State {
+                config,
+                last_run: None,
+                status_changed: false,
+                dispatched: VecDeque::new(),
+                spent: (today(), spent_today),
+            }),
+            running: AtomicBool::new(false),
+            killed,
+        }
+    }
+
+    pub(crate) fn config(&self) -> SupervisorConfig {
+        self.state.lock().unwrap().config.clone()
+    }
+
+    pub(crate) fn set(&self, app: &tauri::AppHandle, config: SupervisorConfig) -> Result<(), String> {
+        crate::storage::save_json(app, CONFIG_FILE, &config)?;
+        self.killed.store(!config.enabled, Ordering::SeqCst);
+        let mut state = self.state.lock().unwrap();
+        state.config = config;
+        // Start the interval fresh instead of firing right away.
+        state.last_run = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Kill switch: disable and abort any dispatch still in progress.
+    pub(crate) fn kill(&self, app: &tauri::AppHandle) -> Result<(), String> {
+        self.killed.store(true, Ordering::SeqCst);
+        let config = {
+            let mut state = self.state.lock().unwrap();
+            state.config.enabled = false;
+            state.config.clone()
+        };
+        crate::storage::save_json(app, CONFIG_FILE, &config)
+    }
+}
+
+/// Start the background loop and the status-change listener.
+pub(crate) fn start(app: tauri::AppHandle) {
+    let listener_app = app.clone();
+    app.listen_any("cell-status", move |_| {
+        listener_app.state::<Supervisor>().state.lock().unwrap().status_changed = true;
+    });
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(TICK).await;
+            let trigger = {
+                let supervisor = app.state::<Supervisor>();
+                let mut state = supervisor.state.lock().unwrap();
+                let since_last = state.last_run.map(|at| at.elapsed());
+                let trigger = if !state.config.enabled {
+                    None
+                } else if since_last.map_or(true, |d| d >= Duration::from_secs(state.config.interval_secs)) {
+                    Some("interval")
+                } else if state.config.on_status_change
+                    && state.status_changed
+                    && since_last.map_or(true, |d| d >= STATUS_CHANGE_GAP)
+                {
+                    Some("status-change")
+                } else {
+                    None
+                };
+                if trigger.is_some() {
+                    state.status_changed = false;
+                }
+                trigger
+            };
+            if let Some(trigger) = trigger {
+                if let Err(e) = run_once(&app, trigger).await {
+                    eprintln!("supervisor: {}", e);
+                }
+            }
+        }
+    });
+}
+
+fn is_idle(cell: &CellState, idle_secs: u64) -> bool {
+    cell.pid.is_some() && crate::now_millis().saturating_sub(cell.updated_at) >= idle_secs * 1000
+}
+
+/// Decide whether an action may be dispatched; Err explains why not.
+/// `busy` holds cells this run already sent a prompt to.
+fn check_action(
+    action: &FlowAction,
+    config: &SupervisorConfig,
+    cells: &[CellState],
+    busy: &HashSet<String>,
+) -> Result<(), String> {
+    let (cell_id, text) = match &action.kind {
+        FlowActionKind::SendPrompt { cell_id, prompt } => (cell_id, prompt),
+        FlowActionKind::EnqueueTask { cell_id, task } => (cell_id, task),
+        _ => return Err("supervisor only dispatches prompts and tasks".to_string()),
+    };
+    if !config.allowed_cells.contains(cell_id) {
+        return Err(format!("{} is not in allowed cells", cell_id));
+    }
+    let lower = text.to_lowercase();
+    if let Some(blocked) = config
+        .blocked_commands
+        .iter()
+        .find(|b| !b.is_empty() && lower.contains(&b.to_lowercase()))
+    {
+        return Err(format!("contains blocked command '{}'", blocked));
+    }
+    if let FlowActionKind::SendPrompt { .. } = &action.kind {
+        let cell = cells.iter().find(|c| &c.id == cell_id).ok_or("unknown cell")?;
+        if busy.contains(cell_id) {
+            return Err(format!("{} was already sent a prompt in this run", cell_id));
+        }
+        if !is_idle(cell, config.idle_secs) {
+            return Err(format!("{} is not idle", cell_id));
+        }
+    }
+    Ok(())
+}
+
+/// Run one analysis-and-dispatch cycle.
+pub(crate) async fn run_once(app: &tauri::AppHandle, trigger: &str) -> Result<SupervisorRun, String> {
+    let supervisor = app.state::<Supervisor>();
+    if supervisor.running.swap(true, Ordering::SeqCst) {
+        return Err("a supervisor run is already in progress".to_string());
+    }
+    let guard = RunningGuard(&supervisor.running);
+    let result = run_inner(app, &supervisor, trigger).await;
+    drop(guard);
+
+    let run = result?;
+    if let Err(e) = crate::storage::append_jsonl(app, LOG_FILE, &run) {
+        eprintln!("supervisor log: {}", e);
+    }
+    let _ = app.emit("supervisor-run", &run);
+    Ok(run)
+}
+
+async fn run_inner(app: &tauri::AppHandle, supervisor: &Supervisor, trigger: &str) -> Result<SupervisorRun, String> {
+    let config = supervisor.config();
+    let mut cells: Vec<CellState> = app
+        .state::<crate::CellStateMap>()
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .values()
+        .cloned()
+        .collect();
+    cells.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut run = SupervisorRun {
+        timestamp_ms: crate::now_millis(),
+        trigger: trigger.to_string(),
+        dry_run: config.dry_run,
+        stuck: String::new(),
+        next: String::new(),
+        decisions: Vec::new(),
+        error: None,
+        tokens: 0,
+    };
+
+    {
+        let mut state = supervisor.state.lock().unwrap();
+        state.last_run = Some(Instant::now());
+        if state.spent.0 != today() {
+            state.spent = (today(), 0);
+        }
+        if config.daily_token_budget > 0 && state.spent.1 >= config.daily_token_budget {
+            run.error = Some(format!(
+                "daily token budget reached ({} of {} used)",
+                state.spent.1, config.daily_token_budget
+            ));
+            return Ok(run);
+        }
+    }
+
+    // Runs don't overlap (see `running`); an analysis the user starts meanwhile
+    // is counted too, which errs on the side of the budget.
+    let started_ms = crate::now_millis();
+    let result = crate::commands::ai_cmds::analyze(
+        app.clone(),
+        app.state(),
+        app.state(),
+        config.language.clone(),
+        Some(config.cols),
+        config.output_dir.clone(),
+    )
+    .await;
+    run.tokens = crate::usage::tokens_since(app, crate::ai::AiTask::Analyze.key(), started_ms);
+    {
+        let mut state = supervisor.state.lock().unwrap();
+        if state.spent.0 == today() {
+            state.spent.1 += run.tokens;
+        }
+    }
+    let flow = match result {
+        Ok(result) => result.flow,
+        Err(e) => {
+            run.error = Some(e);
+            return Ok(run);
+        }
+    };
+    let Some(flow) = flow else {
+        run.error = Some("analysis returned no flow".to_string());
+        return Ok(run);
+    };
+    run.stuck = flow.stuck.clone();
+    run.next = flow.next.clone();
+
+    let ctx = ActionContext {
+        output_dir: config.output_dir.clone(),
+        cols: Some(config.cols),
+        tool_cmd: None,
+    };
+    let mut busy = HashSet::new();
+    for action in flow.actions.unwrap_or_default() {
+        let decision = |outcome: &str, note: String| SupervisorDecision {
+            action: action.clone(),
+            outcome: outcome.to_string(),
+            note,
+        };
+        if let Err(why) = check_action(&action, &config, &cells, &busy) {
+            run.decisions.push(decision("skipped", why));
+            continue;
+        }
+        let within_limit = {
+            let mut state = supervisor.state.lock().unwrap();
+            while state.dispatched.front().is_some_and(|at| at.elapsed() >= HOUR) {
+                state.dispatched.pop_front();
+            }
+            state.dispatched.len() < config.max_actions_per_hour as usize
+        };
+        if !within_limit {
+            run.decisions.push(decision("skipped", "hourly action limit reached".to_string()));
+            continue;
+        }
+        if config.dry_run {
+            run.decisions.push(decision("dry-run", action.reason.clone()));
+            continue;
+        }
+        if supervisor.killed.load(Ordering::SeqCst) {
+            run.decisions.push(decision("skipped", "supervisor was stopped".to_string()));
+            continue;
+        }
+        supervisor.state.lock().unwrap().dispatched.push_back(Instant::now());
+        if let FlowActionKind::SendPrompt { cell_id, .. } = &action.kind {
+            mark_busy(app, cell_id);
+            busy.insert(cell_id.clone());
+        }
+        match crate::actions::apply(app, action.clone(), &ctx, "supervisor").await {
+            Ok(entry) => run.decisions.push(decision("dispatched", entry.detail)),
+            Err(e) => run.decisions.push(decision("failed", e)),
+        }
+    }
+    Ok(run)
+}
+
+/// Restart a prompted cell's idle clock, so no run treats it as idle before
+/// it has had `idle_secs` to respond.
+fn mark_busy(app: &tauri::AppHandle, cell_id: &str) {
+    if let Some(cell) = app.state::<crate::CellStateMap>().0.lock().unwrap().get_mut(cell_id) {
+        cell.updated_at = crate::now_millis();
+    }
+}
+
+/// Most recent runs first.
+pub(crate) fn load_log(app: &tauri::AppHandle, limit: usize) -> Vec<SupervisorRun> {
+    let mut runs: Vec<SupervisorRun> = crate::storage::load_jsonl(app, LOG_FILE);
+    runs.reverse();
+    runs.truncate(limit);
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle_cell(id: &str) -> CellState {
+        CellState {
+            id: id.to_string(),
+            theme: String::new(),
+            pid: Some(1),
+            last_output: String::new(),
+            status: "active".to_string(),
+            updated_at: 0,
+            output_bytes: 0,
+            summary: None,
+        }
+    }
+
+    fn prompt(cell_id: &str, prompt: &str) -> FlowAction {
+        FlowAction {
+            kind: FlowActionKind::SendPrompt { cell_id: cell_id.to_string(), prompt: prompt.to_string() },
+            reason: String::new(),
+        }
+    }
+
+    fn config() -> SupervisorConfig {
+        SupervisorConfig { allowed_cells: vec!["1-1".to_string()], ..Default::default() }
+    }
+
+    #[test]
+    fn allows_a_prompt_to_an_idle_allowed_cell() {
+        let cells = [idle_cell("1-1")];
+        assert!(check_action(&prompt("1-1", "run the tests"), &config(), &cells, &HashSet::new()).is_ok());
+    }
+
+    #[test]
+    fn refuses_blocked_commands_and_other_cells() {
+        let cells = [idle_cell("1-1"), idle_cell("1-2")];
+        let none = HashSet::new();
+        assert!(check_action(&prompt("1-1", "then SUDO reboot"), &config(), &cells, &none).is_err());
+        assert!(check_action(&prompt("1-2", "run the tests"), &config(), &cells, &none).is_err());
+    }
+
+    #[test]
+    fn sends_one_prompt_per_cell_per_run() {
+        let cells = [idle_cell("1-1")];
+        let busy = HashSet::from(["1-1".to_string()]);
+        assert!(check_action(&prompt("1-1", "run the tests"), &config(), &cells, &busy).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+// Time handling. Timestamps are stored as epoch milliseconds everywhere;
+// these helpers turn them into text for prompts and display.
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+
+pub(crate) fn now_millis() -> u64 {
+    Utc::now().timestamp_millis().max(0) as u64
+}
+
+fn to_utc(ms: u64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(ms as i64).single()
+}
+
+/// Local wall-clock time with zone offset, e.g. `2025-03-01 18:30 +09:00`.
+pub(crate) fn format_local(ms: u64) -> String {
+    to_utc(ms)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M %:z").to_string())
+        .unwrap_or_default()
+}
+
+/// Local time in a custom `strftime` format, e.g. `%Y-%m` for the month.
+pub(crate) fn format_local_as(ms: u64, format: &str) -> String {
+    to_utc(ms)
+        .map(|t| t.with_timezone(&Local).format(format).to_string())
+        .unwrap_or_default()
+}
+
+/// Parse an RFC 3339 timestamp (any offset) into epoch millis.
+pub(crate) fn parse_iso(s: &str) -> Option<u64> {
+    let t = DateTime::parse_from_rfc3339(s.trim()).ok()?;
+    u64::try_from(t.timestamp_millis()).ok()
+}
+
+/// Parse an HTTP date (`Retry-After`, RFC 7231 IMF-fixdate) into epoch millis.
+pub(crate) fn parse_http_date(s: &str) -> Option<u64> {
+    let t = DateTime::parse_from_rfc2822(s.trim()).ok()?;
+    u64::try_from(t.timestamp_millis()).ok()
+}
+
+/// "just now", "5m ago", "3h ago", "2d ago"; future times read "in 5m".
+pub(crate) fn format_relative(ms: u64, now_ms: u64) -> String {
+    let (delta, future) = if now_ms >= ms { (now_ms - ms, false) } else { (ms - now_ms, true) };
+    let secs = delta / 1000;
+    let amount = if secs < 60 {
+        if !future && secs < 10 {
+            return "just now".to_string();
+        }
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else if secs < 86_400 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}d", secs / 86_400)
+    };
+    if future {
+        format!("in {}", amount)
+    } else {
+        format!("{} ago", amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SecondsFormat;
+
+    // 2025-03-01T09:30:15.250Z
+    const MS: u64 = 1_740_821_415_250;
+
+    #[test]
+    fn epoch_millis_round_trip_through_rfc3339() {
+        let iso = to_utc(MS).unwrap().to_rfc3339_opts(SecondsFormat::Millis, true);
+        assert_eq!(iso, "2025-03-01T09:30:15.250Z");
+        assert_eq!(parse_iso(&iso), Some(MS));
+    }
+
+    #[test]
+    fn parses_legacy_analysis_timestamps() {
+        // What older versions wrote: whole seconds with a literal `.000Z`.
+        assert_eq!(parse_iso("2025-03-01T09:30:15.000Z"), Some(MS - 250));
+        assert_eq!(parse_iso("2025-03-01T09:30:15Z"), Some(MS - 250));
+        assert_eq!(parse_iso(" 2025-03-01T18:30:15.250+09:00\n"), Some(MS));
+        assert_eq!(parse_iso("1970-01-01T00:00:00.000Z"), Some(0));
+    }
+
+    #[test]
+    fn rejects_malformed_and_pre_epoch_timestamps() {
+        assert_eq!(parse_iso(""), None);
+        assert_eq!(parse_iso("2025-03-01"), None);
+        assert_eq!(parse_iso("2025-13-01T00:00:00Z"), None);
+        assert_eq!(parse_iso("1969-12-31T23:59:59Z"), None);
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        assert_eq!(parse_http_date("Sat, 01 Mar 2025 09:30:15 GMT"), Some(MS - 250));
+        assert_eq!(parse_http_date("120"), None);
+    }
+
+    #[test]
+    fn format_local_shows_minutes_and_offset() {
+        // Independent of the machine's zone: the text must name the same minute.
+        let text = format_local(MS);
+        let back = DateTime::parse_from_str(&text, "%Y-%m-%d %H:%M %:z").unwrap();
+        assert_eq!(back.timestamp_millis() as u64, MS - 15_250);
+        assert_eq!(format_local_as(MS, "%Y"), "2025");
+    }
+
+    #[test]
+    fn format_relative_buckets() {
+        let now = MS;
+        assert_eq!(format_relative(now, now), "just now");
+        assert_eq!(format_relative(now - 9_999, now), "just now");
+        assert_eq!(format_relative(now - 10_000, now), "10s ago");
+        assert_eq!(format_relative(now - 59_999, now), "59s ago");
+        assert_eq!(format_relative(now - 60_000, now), "1m ago");
+        assert_eq!(format_relative(now - 3_599_999, now), "59m ago");
+        assert_eq!(format_relative(now - 3_600_000, now), "1h ago");
+        assert_eq!(format_relative(now - 86_399_999, now), "23h ago");
+        assert_eq!(format_relative(now - 86_400_000, now), "1d ago");
+        assert_eq!(format_relative(now - 30 * 86_400_000, now), "30d ago");
+    }
+
+    #[test]
+    fn format_relative_future() {
+        let now = MS;
+        assert_eq!(format_relative(now + 5_000, now), "in 5s");
+        assert_eq!(format_relative(now + 5 * 60_000, now), "in 5m");
+        assert_eq!(format_relative(now + 2 * 3_600_000, now), "in 2h");
+        assert_eq!(format_relative(now + 3 * 86_400_000, now), "in 3d");
+    }
+}
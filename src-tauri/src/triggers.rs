@@ -0,0 +1,508 @@
+// User-defined output triggers.
+//
+// Each line a cell prints, plus its unterminated last line (so prompts such as
+// "Continue? (y/n)" match before the user answers), is tested against the
+// enabled rules. A match fires the rule's action unless the same rule already
+// fired for that cell within its debounce window. Rules live in
+// `<data>/triggers.json`.
+//
+// Text fields of an action may reference capture groups as `$1` / `${name}`
+// (`$0` is the whole match, `$$` a literal dollar sign).
+
+use regex::{Captures, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+const TRIGGERS_FILE: &str = "triggers.json";
+const DEFAULT_DEBOUNCE_MS: u64 = 5000;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum TriggerAction {
+    /// OS notification; defaults to the cell id and the matched line.
+    Notify { title: Option<String>, body: Option<String> },
+    /// Write text to the matching cell's PTY (e.g. "y\n").
+    Reply { text: String },
+    /// Append a task to another cell's task queue.
+    EnqueueTask { cell_id: String, task: String },
+    MarkErrored,
+    /// Run a grid analysis; the result is emitted as `analysis-result`.
+    Analyze { language: Option<String> },
+    /// POST the match as JSON to a webhook on localhost.
+    Webhook { url: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerRule {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Only evaluate on these cells (None = all).
+    pub cell_ids: Option<Vec<String>>,
+    /// "Stimulus" | "Will" | "Supply"; resolved with the config's `cols`.
+    pub roles: Option<Vec<String>>,
+    pub debounce_ms: Option<u64>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    pub action: TriggerAction,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerConfig {
+    /// Grid width, needed to resolve cell roles.
+    pub cols: u32,
+    pub rules: Vec<TriggerRule>,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        TriggerConfig { cols: 3, rules: Vec::new() }
+    }
+}
+
+struct State {
+    config: TriggerConfig,
+    /// Compiled pattern per rule, same order as `config.rules`.
+    patterns: Vec<Regex>,
+    last_fired: HashMap<(String, String), Instant>,
+}
+
+pub(crate) struct Triggers(Mutex<State>);
+
+fn compile(rule: &TriggerRule) -> Result<Regex, String> {
+    RegexBuilder::new(&rule.pattern)
+        .case_insensitive(!rule.case_sensitive)
+        .build()
+        .map_err(|e| format!("rule '{}': invalid pattern: {}", rule.name, e))
+}
+
+fn is_local_url(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else { return false };
+    matches!(url.scheme(), "http" | "https")
+        && matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+}
+
+fn validate(rule: &TriggerRule) -> Result<Regex, String> {
+    if let TriggerAction::Webhook { url } = &rule.action {
+        if !is_local_url(url) {
+            return Err(format!("rule '{}': webhooks must point at localhost", rule.name));
+        }
+    }
+    compile(rule)
+}
+
+fn in_scope(rule: &TriggerRule, cell_id: &str, cols: u32) -> bool {
+    if let Some(ids) = &rule.cell_ids {
+        if !ids.iter().any(|id| id == cell_id) {
+            return false;
+        }
+    }
+    if let Some(roles) = &rule.roles {
+        let role = crate::ai::get_cell_role(cell_id, cols as usize);
+        if !roles.iter().any(|r| r.eq_ignore_ascii_case(role)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn expand(caps: &Captures, template: &str) -> String {
+    let mut out = String::new();
+    caps.expand(template, &mut out);
+    out
+}
+
+/// The action with capture references filled in.
+fn render(action: &TriggerAction, caps: &Captures) -> TriggerAction {
+    match action {
+        TriggerAction::Notify { title, body } => TriggerAction::Notify {
+            title: title.as_deref().map(|t| expand(caps, t)),
+            body: body.as_deref().map(|b| expand(caps, b)),
+        },
+        TriggerAction::Reply { text } => TriggerAction::Reply { text: expand(caps, text) },
+        TriggerAction::EnqueueTask { cell_id, task } => TriggerAction::EnqueueTask {
+            cell_id: cell_id.clone(),
+            task: expand(caps, task),
+        },
+        other => other.clone(),
+    }
+}
+
+impl Triggers {
+    pub(crate) fn load(app: &tauri::AppHandle) -> Self {
+        let mut config: TriggerConfig = crate::storage::load_json(app, TRIGGERS_FILE);
+        // A rule that no longer compiles is dropped rather than failing startup.
+        let mut patterns = Vec::new();
+        config.rules.retain(|rule| match validate(rule) {
+            Ok(re) => {
+                patterns.push(re);
+                true
+            }
+            Err(e) => {
+                eprintln!("triggers: {}", e);
+                false
+            }
+        });
+        Triggers(Mutex::new(State { config, patterns, last_fired: HashMap::new() }))
+    }
+
+    pub(crate) fn config(&self) -> TriggerConfig {
+        self.0.lock().unwrap().config.clone()
+    }
+
+    /// Validate, persist and activate a new rule set.
+    pub(crate) fn set(&self, app: &tauri::AppHandle, mut config: TriggerConfig) -> Result<TriggerConfig, String> {
+        let patterns = config.rules.iter().map(validate).collect::<Result<Vec<_>, _>>()?;
+        let stamp = crate::now_millis();
+        for (i, rule) in config.rules.iter_mut().enumerate() {
+            if rule.id.is_empty() {
+                rule.id = format!("rule-{}-{}", stamp, i);
+            }
+        }
+        crate::storage::save_json(app, TRIGGERS_FILE, &config)?;
+        let mut state = self.0.lock().unwrap();
+        state.last_fired.retain(|(rule_id, _), _| config.rules.iter().any(|r| &r.id == rule_id));
+        state.config = config.clone();
+        state.patterns = patterns;
+        Ok(config)
+    }
+}
+
+// ─── Evaluation ──────────────────────────────────────────────────────────────
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TriggerFiredPayload {
+    rule_id: String,
+    rule_name: String,
+    cell_id: String,
+    line: String,
+}
+
+/// A rule that fired, its rendered action and the line that matched.
+type Fired = (TriggerRule, TriggerAction, String);
+
+/// The rules `texts` from `cell_id` fire at `now`, recording them for the debounce.
+fn fire(state: &mut State, cell_id: &str, texts: &[&str], now: Instant) -> Vec<Fired> {
+    let State { config, patterns, last_fired } = state;
+    let mut fired = Vec::new();
+    for (rule, re) in config.rules.iter().zip(patterns.iter()) {
+        if !rule.enabled || !in_scope(rule, cell_id, config.cols) {
+            continue;
+        }
+        let Some((line, caps)) = texts.iter().find_map(|t| re.captures(t).map(|c| (*t, c))) else {
+            continue;
+        };
+        let key = (rule.id.clone(), cell_id.to_string());
+        let debounce = Duration::from_millis(rule.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS));
+        if last_fired.get(&key).is_some_and(|at| now.saturating_duration_since(*at) < debounce) {
+            continue;
+        }
+        last_fired.insert(key, now);
+        fired.push((rule.clone(), render(&rule.action, &caps), line.to_string()));
+    }
+    fired
+}
+
+/// Match freshly printed output from `cell_id` against the rules and fire actions.
+pub(crate) fn evaluate(app: &tauri::AppHandle, cell_id: &str, lines: &[String], pending: &str) {
+    let fired = {
+        let triggers = app.state::<Triggers>();
+        let mut state = triggers.0.lock().unwrap();
+        if state.config.rules.is_empty() {
+            return;
+        }
+        let texts: Vec<&str> = lines
+            .iter()
+            .map(|l| l.as_str())
+            .chain(Some(pending).filter(|p| !p.is_empty()))
+            .collect();
+        fire(&mut state, cell_id, &texts, Instant::now())
+    };
+
+    for (rule, action, line) in fired {
+        let _ = app.emit(
+            "trigger-fired",
+            TriggerFiredPayload {
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                cell_id: cell_id.to_string(),
+                line: line.clone(),
+            },
+        );
+        if let Err(e) = run_action(app, &rule, cell_id, &line, action) {
+            eprintln!("trigger '{}' on {}: {}", rule.name, cell_id, e);
+        }
+    }
+}
+
+fn run_action(
+    app: &tauri::AppHandle,
+    rule: &TriggerRule,
+    cell_id: &str,
+    line: &str,
+    action: TriggerAction,
+) -> Result<(), String> {
+    match action {
+        TriggerAction::Notify { title, body } => {
+            use tauri_plugin_notification::NotificationExt;
+            let theme = app
+                .state::<crate::CellStateMap>()
+                .0
+                .lock()
+                .unwrap()
+                .get(cell_id)
+                .map(|c| c.theme.clone())
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| cell_id.to_string());
+            app.notification()
+                .builder()
+                .title(title.unwrap_or_else(|| format!("chaos-grid: {}", theme)))
+                .body(body.unwrap_or_else(|| line.to_string()))
+                .show()
+                .map_err(|e| e.to_string())
+        }
+        TriggerAction::Reply { text } => {
+            crate::commands::pty::write_to_cell(&app.state::<crate::PtySessions>(), cell_id, &text)
+        }
+        TriggerAction::EnqueueTask { cell_id: target, task } => {
+            crate::pty_manager::emit_task(app, &target, &task);
+            Ok(())
+        }
+        TriggerAction::MarkErrored => {
+            let states = app.state::<crate::CellStateMap>();
+            if let Some(state) = states.0.lock().unwrap().get_mut(cell_id) {
+                mark_errored(state);
+            }
+            crate::pty_manager::emit_status(app, cell_id, "error");
+            Ok(())
+        }
+        TriggerAction::Analyze { language } => {
+            let app = app.clone();
+            let cols = app.state::<Triggers>().config().cols;
+            tauri::async_runtime::spawn(async move {
+                let result = crate::commands::ai_cmds::analyze(
+                    app.clone(),
+                    app.state(),
+                    app.state(),
+                    language,
+                    Some(cols),
+                    None,
+                )
+                .await;
+                match result {
+                    Ok(result) => {
+                        let _ = app.emit("analysis-result", result);
+                    }
+                    Err(e) => eprintln!("trigger analysis failed: {}", e),
+                }
+            });
+            Ok(())
+        }
+        TriggerAction::Webhook { url } => {
+            if !is_local_url(&url) {
+                return Err("webhooks must point at localhost".to_string());
+            }
+            let body = serde_json::json!({
+                "ruleId": rule.id,
+                "ruleName": rule.name,
+                "cellId": cell_id,
+                "line": line,
+                "timestamp": crate::now_millis(),
+            });
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = webhook_client().post(&url).json(&body).send().await {
+                    eprintln!("trigger webhook {}: {}", url, e);
+                }
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Set by `MarkErrored`; further output does not turn the cell active again
+/// (see `pty_manager::becomes_active`), only an exit or respawn clears it.
+fn mark_errored(state: &mut crate::CellState) {
+    state.status = "error".to_string();
+    state.updated_at = crate::now_millis();
+}
+
+/// Shared webhook client. Redirects are not followed, so a local endpoint
+/// can't bounce the POST to another host.
+fn webhook_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .connect_timeout(WEBHOOK_TIMEOUT)
+            .timeout(WEBHOOK_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+// ─── Dry runs ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerMatch {
+    pub line: String,
+    /// Capture groups, index 0 being the whole match.
+    pub captures: Vec<Option<String>>,
+    /// The action that would fire, with captures filled in.
+    pub action: TriggerAction,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerTest {
+    /// False if `cell_id` was given and the rule's scope excludes it.
+    pub in_scope: bool,
+    pub matches: Vec<TriggerMatch>,
+}
+
+/// Run a rule against sample text without firing anything.
+pub(crate) fn test_rule(
+    rule: &TriggerRule,
+    sample: &str,
+    cell_id: Option<&str>,
+    cols: u32,
+) -> Result<TriggerTest, String> {
+    let re = validate(rule)?;
+    let stripped = crate::ai::strip_ansi(sample);
+    let matches = stripped
+        .lines()
+        .filter_map(|line| {
+            let caps = re.captures(line)?;
+            Some(TriggerMatch {
+                line: line.to_string(),
+                captures: caps.iter().map(|m| m.map(|m| m.as_str().to_string())).collect(),
+                action: render(&rule.action, &caps),
+            })
+        })
+        .collect();
+    Ok(TriggerTest {
+        in_scope: cell_id.map_or(true, |id| in_scope(rule, id, cols)),
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, pattern: &str, action: TriggerAction) -> TriggerRule {
+        TriggerRule {
+            id: id.to_string(),
+            name: id.to_string(),
+            pattern: pattern.to_string(),
+            case_sensitive: false,
+            cell_ids: None,
+            roles: None,
+            debounce_ms: None,
+            enabled: true,
+            action,
+        }
+    }
+
+    fn state(rules: Vec<TriggerRule>) -> State {
+        let patterns = rules.iter().map(|r| validate(r).unwrap()).collect();
+        State { config: TriggerConfig { cols: 3, rules }, patterns, last_fired: HashMap::new() }
+    }
+
+    fn fired_ids(fired: &[Fired]) -> Vec<&str> {
+        fired.iter().map(|(rule, _, _)| rule.id.as_str()).collect()
+    }
+
+    #[test]
+    fn a_rule_fires_once_per_cell_within_its_debounce() {
+        let mut slow = rule("slow", r"error: (\w+)", TriggerAction::Reply { text: "fix $1".into() });
+        slow.debounce_ms = Some(10_000);
+        let mut fast = rule("fast", "ERROR", TriggerAction::MarkErrored);
+        fast.debounce_ms = Some(0);
+        let mut state = state(vec![slow, fast]);
+        let start = Instant::now();
+
+        let fired = fire(&mut state, "cell-0", &["ok", "error: timeout"], start);
+        assert_eq!(fired_ids(&fired), vec!["slow", "fast"]);
+        assert!(matches!(&fired[0].1, TriggerAction::Reply { text } if text == "fix timeout"));
+        assert_eq!(fired[0].2, "error: timeout");
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(fired_ids(&fire(&mut state, "cell-0", &["error: again"], later)), vec!["fast"]);
+        // The window is per cell.
+        assert_eq!(fired_ids(&fire(&mut state, "cell-1", &["error: again"], later)), vec!["slow", "fast"]);
+        let after = start + Duration::from_secs(10);
+        assert_eq!(fired_ids(&fire(&mut state, "cell-0", &["error: again"], after)), vec!["slow", "fast"]);
+        assert!(fire(&mut state, "cell-0", &["all good"], after).is_empty());
+    }
+
+    #[test]
+    fn disabled_and_out_of_scope_rules_do_not_fire() {
+        let mut off = rule("off", "done", TriggerAction::MarkErrored);
+        off.enabled = false;
+        let mut other = rule("other", "done", TriggerAction::MarkErrored);
+        other.cell_ids = Some(vec!["cell-5".into()]);
+        let mut state = state(vec![off, other]);
+        assert!(fire(&mut state, "cell-0", &["done"], Instant::now()).is_empty());
+        assert_eq!(fired_ids(&fire(&mut state, "cell-5", &["done"], Instant::now())), vec!["other"]);
+    }
+
+    #[test]
+    fn marked_errored_stays_errored_while_output_continues() {
+        let mut cell = crate::CellState {
+            id: "cell-0".into(),
+            theme: String::new(),
+            pid: Some(1),
+            last_output: String::new(),
+            status: "idle".into(),
+            updated_at: 0,
+            output_bytes: 0,
+            summary: None,
+        };
+        assert!(crate::pty_manager::becomes_active(&cell.status));
+        mark_errored(&mut cell);
+        assert_eq!(cell.status, "error");
+        assert!(cell.updated_at > 0);
+        assert!(!crate::pty_manager::becomes_active(&cell.status));
+    }
+
+    #[test]
+    fn webhooks_must_point_at_localhost() {
+        for url in ["http://localhost:8080/hook", "https://127.0.0.1/x", "http://[::1]:9000/"] {
+            assert!(validate(&rule("ok", ".", TriggerAction::Webhook { url: url.into() })).is_ok(), "{}", url);
+        }
+        for url in [
+            "http://example.com/hook",
+            "http://localhost.example.com/",
+            "http://127.0.0.1.nip.io/",
+            "http://localhost@example.com/",
+            "ftp://localhost/",
+            "file:///etc/passwd",
+            "localhost:8080",
+        ] {
+            let err = validate(&rule("bad", ".", TriggerAction::Webhook { url: url.into() })).unwrap_err();
+            assert_eq!(err, "rule 'bad': webhooks must point at localhost", "{}", url);
+        }
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let err = validate(&rule("broken", "(unclosed", TriggerAction::MarkErrored)).unwrap_err();
+        assert!(err.starts_with("rule 'broken': invalid pattern"), "{}", err);
+    }
+}
@@ -0,0 +1,287 @@
+// Ledger of the app's own AI usage. Every provider reply's token counts are
+// appended to `<data>/ai-usage.jsonl` with an estimated cost, and an optional
+// monthly cap (`AiConfig::monthly_cap_usd`) blocks paid calls once reached.
+// Models are priced from `AiConfig::model_prices`, then a built-in list; while
+// a cap is set, a paid model with no price is refused rather than counted as free.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::storage::AiConfig;
+
+const USAGE_FILE: &str = "ai-usage.jsonl";
+
+/// Token counts reported by the provider for one call.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    pub timestamp_ms: u64,
+    /// "name" | "summarize" | "analyze" | "chat"; "other" in older entries.
+    pub task: String,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// None when the model has no known price.
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    /// Calls whose cost could not be estimated.
+    pub unpriced_calls: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub from_day: String,
+    pub total: UsageTotals,
+    pub by_day: BTreeMap<String, UsageTotals>,
+    pub by_task: BTreeMap<String, UsageTotals>,
+    pub by_model: BTreeMap<String, UsageTotals>,
+    pub month_to_date_usd: f64,
+    pub monthly_cap_usd: Option<f64>,
+}
+
+/// The ledger file's app and the running spend total. One lock covers both, so
+/// a cap check never sees a total the ledger hasn't caught up with.
+struct Ledger {
+    app: tauri::AppHandle,
+    /// (local month, spend in it), loaded from the file on first use.
+    month_spend: Option<(String, f64)>,
+}
+
+static LEDGER: Mutex<Option<Ledger>> = Mutex::new(None);
+
+pub(crate) fn init(app: &tauri::AppHandle) {
+    *LEDGER.lock().unwrap() = Some(Ledger { app: app.clone(), month_spend: None });
+}
+
+/// USD per million (input, output) tokens: the longest matching prefix in the
+/// config's `model_prices`, else the built-in approximate list prices.
+fn price(config: &AiConfig, model: &str) -> Option<(f64, f64)> {
+    const PRICES: &[(&str, f64, f64)] = &[
+        ("gemini-2.5-pro", 1.25, 10.0),
+        ("gemini-2.5-flash-lite", 0.10, 0.40),
+        ("gemini-2.5-flash", 0.30, 2.50),
+        ("gemini-2.0-flash-lite", 0.075, 0.30),
+        ("gemini-2.0-flash", 0.10, 0.40),
+        ("gpt-4o-mini", 0.15, 0.60),
+        ("gpt-4o", 2.50, 10.0),
+        ("gpt-4.1-nano", 0.10, 0.40),
+        ("gpt-4.1-mini", 0.40, 1.60),
+        ("gpt-4.1", 2.0, 8.0),
+        ("gpt-4.5-preview", 75.0, 150.0),
+        ("o3-mini", 1.10, 4.40),
+        ("o1", 15.0, 60.0),
+        ("claude-haiku-4-5", 1.0, 5.0),
+        ("claude-3-5-haiku", 0.80, 4.0),
+        ("claude-sonnet-4", 3.0, 15.0),
+        ("claude-3-5-sonnet", 3.0, 15.0),
+        ("claude-3-7-sonnet", 3.0, 15.0),
+        ("claude-opus-4-5", 5.0, 25.0),
+        ("claude-opus-4-6", 5.0, 25.0),
+        ("claude-opus-4", 15.0, 75.0),
+    ];
+    if config.provider == "ollama" {
+        return Some((0.0, 0.0));
+    }
+    let configured = config
+        .model_prices
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len());
+    if let Some((_, p)) = configured {
+        return Some((p.input, p.output));
+    }
+    PRICES
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, input, output)| (input, output))
+}
+
+fn month_of(ms: u64) -> String {
+    crate::time::format_local_as(ms, "%Y-%m")
+}
+
+fn month_spend(ledger: &mut Ledger, month: &str) -> f64 {
+    if let Some((m, spend)) = &ledger.month_spend {
+        if m == month {
+            return *spend;
+        }
+    }
+    let spend = crate::storage::load_jsonl::<UsageRecord>(&ledger.app, USAGE_FILE)
+        .iter()
+        .filter(|r| month_of(r.timestamp_ms) == month)
+        .filter_map(|r| r.cost_usd)
+        .sum();
+    ledger.month_spend = Some((month.to_string(), spend));
+    spend
+}
+
+fn cost(config: &AiConfig, model: &str, usage: Usage) -> Option<f64> {
+    price(config, model).map(|(input, output)| {
+        (usage.input_tokens as f64 * input + usage.output_tokens as f64 * output) / 1_000_000.0
+    })
+}
+
+/// Refuse a paid call to `model` once this month's estimated spend reached the
+/// cap, or when its cost could not be counted against the cap.
+pub(crate) fn check_cap(config: &AiConfig, model: &str) -> Result<(), String> {
+    if config.monthly_cap_usd.is_none() || config.provider == "ollama" {
+        return Ok(());
+    }
+    let mut ledger = LEDGER.lock().unwrap();
+    let Some(ledger) = ledger.as_mut() else { return Ok(()) };
+    let spent = month_spend(ledger, &month_of(crate::now_millis()));
+    within_cap(config, model, spent)
+}
+
+fn within_cap(config: &AiConfig, model: &str, spent: f64) -> Result<(), String> {
+    let Some(cap) = config.monthly_cap_usd else { return Ok(()) };
+    if price(config, model).is_none() {
+        return Err(format!(
+            "No price is known for {}, so it cannot be counted against monthlyCapUsd. Add it to modelPrices in the AI config.",
+            model
+        ));
+    }
+    if spent >= cap {
+        return Err(format!(
+            "Monthly AI budget reached: ${:.2} of ${:.2} spent. Raise monthlyCapUsd in the AI config to continue.",
+            spent, cap
+        ));
+    }
+    Ok(())
+}
+
+/// Append one call to the ledger. `task` is an `AiTask` key.
+pub(crate) fn record(task: &str, config: &AiConfig, model: &str, usage: Usage) {
+    let mut ledger = LEDGER.lock().unwrap();
+    let Some(ledger) = ledger.as_mut() else { return };
+    let cost_usd = cost(config, model, usage);
+    let entry = UsageRecord {
+        timestamp_ms: crate::now_millis(),
+        task: task.to_string(),
+        provider: config.provider.clone(),
+        model: model.to_string(),
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cost_usd,
+    };
+    let month = month_of(entry.timestamp_ms);
+    let spent = month_spend(ledger, &month);
+    ledger.month_spend = Some((month, spent + cost_usd.unwrap_or(0.0)));
+    if let Err(e) = crate::storage::append_jsonl(&ledger.app, USAGE_FILE, &entry) {
+        eprintln!("usage ledger: {}", e);
+    }
+}
+
+/// Tokens (input + output) recorded for `task` since `since_ms`.
+pub(crate) fn tokens_since(app: &tauri::AppHandle, task: &str, since_ms: u64) -> u64 {
+    crate::storage::load_jsonl::<UsageRecord>(app, USAGE_FILE)
+        .iter()
+        .filter(|r| r.timestamp_ms >= since_ms && r.task == task)
+        .map(|r| r.input_tokens + r.output_tokens)
+        .sum()
+}
+
+fn add(totals: &mut UsageTotals, r: &UsageRecord) {
+    totals.calls += 1;
+    totals.input_tokens += r.input_tokens;
+    totals.output_tokens += r.output_tokens;
+    match r.cost_usd {
+        Some(cost) => totals.cost_usd += cost,
+        None => totals.unpriced_calls += 1,
+    }
+}
+
+/// Usage over the last `days` local days (including today).
+pub(crate) fn report(app: &tauri::AppHandle, config: &AiConfig, days: u32) -> UsageReport {
+    let now = crate::now_millis();
+    let from_day = crate::time::format_local_as(now.saturating_sub(days.saturating_sub(1) as u64 * 86_400_000), "%Y-%m-%d");
+    let mut report = UsageReport {
+        from_day: from_day.clone(),
+        total: UsageTotals::default(),
+        by_day: BTreeMap::new(),
+        by_task: BTreeMap::new(),
+        by_model: BTreeMap::new(),
+        month_to_date_usd: LEDGER.lock().unwrap().as_mut().map_or(0.0, |l| month_spend(l, &month_of(now))),
+        monthly_cap_usd: config.monthly_cap_usd,
+    };
+    for r in crate::storage::load_jsonl::<UsageRecord>(app, USAGE_FILE) {
+        let day = crate::time::format_local_as(r.timestamp_ms, "%Y-%m-%d");
+        if day < from_day {
+            continue;
+        }
+        add(&mut report.total, &r);
+        add(report.by_day.entry(day).or_default(), &r);
+        add(report.by_task.entry(r.task.clone()).or_default(), &r);
+        add(report.by_model.entry(format!("{}/{}", r.provider, r.model)).or_default(), &r);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ModelPrice;
+
+    fn config(provider: &str, cap: Option<f64>) -> AiConfig {
+        AiConfig { provider: provider.to_string(), monthly_cap_usd: cap, ..AiConfig::default() }
+    }
+
+    #[test]
+    fn built_in_prices_match_by_prefix() {
+        let config = config("gemini", None);
+        assert_eq!(price(&config, "gemini-2.5-flash-lite-preview"), Some((0.10, 0.40)));
+        assert_eq!(price(&config, "gemini-2.5-flash"), Some((0.30, 2.50)));
+        assert_eq!(price(&config, "gemini-9-ultra"), None);
+        assert_eq!(price(&AiConfig { provider: "ollama".to_string(), ..config }, "llama3"), Some((0.0, 0.0)));
+    }
+
+    #[test]
+    fn configured_prices_come_first() {
+        let mut config = config("openai", None);
+        config.model_prices.insert("gpt-9".to_string(), ModelPrice { input: 1.0, output: 2.0 });
+        config.model_prices.insert("gpt-9-mini".to_string(), ModelPrice { input: 0.5, output: 1.0 });
+        config.model_prices.insert("gpt-4o".to_string(), ModelPrice { input: 3.0, output: 12.0 });
+        assert_eq!(price(&config, "gpt-9-turbo"), Some((1.0, 2.0)));
+        assert_eq!(price(&config, "gpt-9-mini-2026"), Some((0.5, 1.0)));
+        assert_eq!(price(&config, "gpt-4o"), Some((3.0, 12.0)));
+        let usage = Usage { input_tokens: 2_000_000, output_tokens: 500_000 };
+        assert_eq!(cost(&config, "gpt-9", usage), Some(3.0));
+        assert_eq!(cost(&config, "my-finetune", usage), None);
+    }
+
+    #[test]
+    fn cap_blocks_once_reached() {
+        let config = config("gemini", Some(5.0));
+        assert!(within_cap(&config, "gemini-2.5-pro", 4.99).is_ok());
+        let err = within_cap(&config, "gemini-2.5-pro", 5.0).unwrap_err();
+        assert!(err.contains("Monthly AI budget reached"), "{}", err);
+        assert!(within_cap(&AiConfig { monthly_cap_usd: None, ..config }, "gemini-2.5-pro", 100.0).is_ok());
+    }
+
+    #[test]
+    fn cap_refuses_models_without_a_price() {
+        let mut config = config("openai", Some(5.0));
+        assert!(within_cap(&config, "gpt-9", 0.0).unwrap_err().contains("modelPrices"));
+        config.model_prices.insert("gpt-9".to_string(), ModelPrice { input: 1.0, output: 2.0 });
+        assert!(within_cap(&config, "gpt-9", 0.0).is_ok());
+        assert!(within_cap(&AiConfig { monthly_cap_usd: None, ..config }, "unlisted", 0.0).is_ok());
+    }
+}
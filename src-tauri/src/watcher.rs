@@ -0,0 +1,551 @@
+// Watches cell work directories (and directories the Files tab lists) and
+// keeps a cached file list for each, so listing a watched directory needs no
+// walk. Changes are debounced and emitted as `files-changed` with the added,
+// modified and deleted paths, relative to the watched root. Directories that
+// were only listed are dropped again after `LISTED_IDLE` without a listing.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+use crate::files::{FileEntry, WalkFilter};
+
+/// Quiet period that ends a batch of changes.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// Longest a steady stream of changes delays its event.
+const MAX_DELAY: Duration = Duration::from_secs(2);
+const MAX_ROOTS: usize = 64;
+/// How long a listed directory no cell works in stays watched after its last listing.
+const LISTED_IDLE: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Copy, PartialEq)]
+struct CachedFile {
+    modified_ms: u64,
+    size_bytes: u64,
+}
+
+struct WatchedRoot {
+    files: HashMap<String, CachedFile>,
+    filter: WalkFilter,
+    /// Cells whose work dir this is.
+    cells: HashSet<String>,
+    /// Watched because it was listed; kept after its cells stop until idle.
+    listed: bool,
+    last_used: Instant,
+}
+
+impl WatchedRoot {
+    /// Only kept for listings, and not listed for `LISTED_IDLE`.
+    fn is_idle(&self) -> bool {
+        self.cells.is_empty() && self.last_used.elapsed() >= LISTED_IDLE
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    watcher: Option<RecommendedWatcher>,
+    roots: HashMap<PathBuf, WatchedRoot>,
+    /// Roots being scanned outside the lock, with the paths that changed meanwhile.
+    pending: HashMap<PathBuf, HashSet<PathBuf>>,
+}
+
+impl Inner {
+    fn remove(&mut self, root: &Path) {
+        self.roots.remove(root);
+        if let Some(watcher) = self.watcher.as_mut() {
+            let _ = watcher.unwatch(root);
+        }
+    }
+
+    /// Drop idle listed roots, then, if still at `MAX_ROOTS`, the least recently
+    /// listed root no cell works in.
+    fn make_room(&mut self) {
+        let idle: Vec<PathBuf> = self.roots.iter().filter(|(_, w)| w.is_idle()).map(|(r, _)| r.clone()).collect();
+        for root in idle {
+            self.remove(&root);
+        }
+        if self.roots.len() + self.pending.len() < MAX_ROOTS {
+            return;
+        }
+        let oldest = self
+            .roots
+            .iter()
+            .filter(|(_, w)| w.cells.is_empty())
+            .min_by_key(|(_, w)| w.last_used)
+            .map(|(r, _)| r.clone());
+        if let Some(root) = oldest {
+            self.remove(&root);
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FileWatcher(Mutex<Inner>);
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesChanged {
+    pub root: String,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl FilesChanged {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+fn scan(root: &Path) -> HashMap<String, CachedFile> {
+    crate::files::walk_dir(root)
+        .into_iter()
+        .filter_map(|(rel, path, modified_ms)| {
+            let size_bytes = std::fs::metadata(&path).ok()?.len();
+            Some((rel, CachedFile { modified_ms, size_bytes }))
+        })
+        .collect()
+}
+
+impl FileWatcher {
+    /// Watch `root` as the work dir of `cell_id`, or because it was listed when `cell_id` is None.
+    /// The initial walk runs without holding the lock, so other roots keep updating meanwhile.
+    pub(crate) fn watch(&self, root: &Path, cell_id: Option<&str>) -> Result<(), String> {
+        {
+            let mut inner = self.0.lock().unwrap();
+            if let Some(watched) = inner.roots.get_mut(root) {
+                add_user(watched, cell_id);
+                return Ok(());
+            }
+            if !root.is_dir() {
+                return Err(format!("{}: not a directory", root.display()));
+            }
+            if !inner.pending.contains_key(root) {
+                inner.make_room();
+                if inner.roots.len() + inner.pending.len() >= MAX_ROOTS {
+                    return Err(format!("already watching {} directories", MAX_ROOTS));
+                }
+            }
+            // Watch before scanning so no change falls between the two.
+            let watcher = inner.watcher.as_mut().ok_or("file watching is unavailable")?;
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|e| format!("{}: {}", root.display(), e))?;
+            inner.pending.entry(root.to_path_buf()).or_default();
+        }
+
+        let files = scan(root);
+        let filter = WalkFilter::new(root);
+
+        let mut inner = self.0.lock().unwrap();
+        let changed = inner.pending.remove(root).unwrap_or_default();
+        if let Some(watched) = inner.roots.get_mut(root) {
+            // Another caller finished scanning first.
+            add_user(watched, cell_id);
+            return Ok(());
+        }
+        let mut watched = WatchedRoot {
+            files,
+            filter,
+            cells: HashSet::new(),
+            listed: false,
+            last_used: Instant::now(),
+        };
+        add_user(&mut watched, cell_id);
+        let mut ignored = FilesChanged::default();
+        for path in &changed {
+            update_path(root, &mut watched, path, observe(path), &mut ignored);
+        }
+        inner.roots.insert(root.to_path_buf(), watched);
+        Ok(())
+    }
+
+    /// Stop tracking `cell_id`'s work dir unless something else still needs it.
+    pub(crate) fn unwatch_cell(&self, cell_id: &str) {
+        let mut inner = self.0.lock().unwrap();
+        let mut unused = Vec::new();
+        for (root, watched) in inner.roots.iter_mut() {
+            if watched.cells.remove(cell_id) && watched.cells.is_empty() && (!watched.listed || watched.is_idle()) {
+                unused.push(root.clone());
+            }
+        }
+        for root in unused {
+            inner.remove(&root);
+        }
+    }
+
+    /// The cached files under `root`, or None when it is not watched.
+    pub(crate) fn files(&self, root: &Path) -> Option<Vec<FileEntry>> {
+        let mut inner = self.0.lock().unwrap();
+        let watched = inner.roots.get_mut(root)?;
+        if watched.listed {
+            watched.last_used = Instant::now();
+        }
+        Some(
+            watched
+                .files
+                .iter()
+                .map(|(rel, file)| FileEntry {
+                    name: rel.clone(),
+                    path: root.join(rel).to_string_lossy().to_string(),
+                    modified_ms: file.modified_ms,
+                    size_bytes: file.size_bytes,
+                    is_dir: false,
+                })
+                .collect(),
+        )
+    }
+}
+
+fn add_user(watched: &mut WatchedRoot, cell_id: Option<&str>) {
+    match cell_id {
+        Some(id) => {
+            watched.cells.insert(id.to_string());
+        }
+        None => {
+            watched.listed = true;
+            watched.last_used = Instant::now();
+        }
+    }
+}
+
+// ─── Change tracking ─────────────────────────────────────────────────────────
+
+fn upsert(watched: &mut WatchedRoot, rel: String, file: CachedFile, change: &mut FilesChanged) {
+    match watched.files.insert(rel.clone(), file) {
+        None => change.added.push(rel),
+        Some(old) if old != file => change.modified.push(rel),
+        Some(_) => {}
+    }
+}
+
+/// What a changed path is on disk now. Read without holding the cache lock.
+enum Seen {
+    /// Created or moved in: everything below it is new.
+    Dir(HashMap<String, CachedFile>),
+    File(CachedFile),
+    /// The file, or a directory and everything below it, is gone.
+    Gone,
+}
+
+fn observe(path: &Path) -> Seen {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => Seen::Dir(scan(path)),
+        Ok(meta) => Seen::File(CachedFile { modified_ms: crate::files::modified_ms(&meta), size_bytes: meta.len() }),
+        Err(_) => Seen::Gone,
+    }
+}
+
+/// Bring the cache in line with `path` as it was observed on disk.
+fn update_path(root: &Path, watched: &mut WatchedRoot, path: &Path, seen: Seen, change: &mut FilesChanged) {
+    let Ok(rel_path) = path.strip_prefix(root) else { return };
+    // Deletions go through even for excluded paths: the filter may have changed since they were added.
+    if !matches!(seen, Seen::Gone) && watched.filter.excludes(path) {
+        return;
+    }
+    match seen {
+        Seen::Dir(files) => {
+            for (sub, file) in files {
+                upsert(watched, rel_path.join(sub).to_string_lossy().to_string(), file, change);
+            }
+        }
+        Seen::File(file) => upsert(watched, rel_path.to_string_lossy().to_string(), file, change),
+        Seen::Gone => {
+            let gone: Vec<String> = watched
+                .files
+                .keys()
+                .filter(|rel| Path::new(rel).starts_with(rel_path))
+                .cloned()
+                .collect();
+            for rel in gone {
+                watched.files.remove(&rel);
+                change.deleted.push(rel);
+            }
+        }
+    }
+}
+
+/// Replace the cache with a fresh walk of the root, for when events were lost.
+fn rescan(watched: &mut WatchedRoot, current: HashMap<String, CachedFile>, change: &mut FilesChanged) {
+    for rel in watched.files.keys() {
+        if !current.contains_key(rel) {
+            change.deleted.push(rel.clone());
+        }
+    }
+    watched.files.retain(|rel, _| current.contains_key(rel));
+    for (rel, file) in current {
+        upsert(watched, rel, file, change);
+    }
+}
+
+/// The disk state one watched root needs for a batch, gathered outside the lock.
+enum Update {
+    Rescan { filter: Option<WalkFilter>, files: HashMap<String, CachedFile> },
+    Paths(Vec<(Seen, PathBuf)>),
+}
+
+fn apply(app: &tauri::AppHandle, paths: &HashSet<PathBuf>, full_rescan: bool) {
+    let watcher = app.state::<FileWatcher>();
+    // Which roots need what, decided under the lock; the walks happen after it is released.
+    let mut plans: Vec<(PathBuf, bool, Vec<PathBuf>)> = Vec::new();
+    {
+        let mut inner = watcher.0.lock().unwrap();
+        for (root, changed) in inner.pending.iter_mut() {
+            // Replayed once the scan is in; a lost-events rescan covers the whole root.
+            if full_rescan {
+                changed.insert(root.clone());
+            }
+            changed.extend(paths.iter().filter(|p| p.starts_with(root)).cloned());
+        }
+        for root in inner.roots.keys() {
+            let under: Vec<PathBuf> = paths.iter().filter(|p| p.starts_with(root)).cloned().collect();
+            let rules_changed = under
+                .iter()
+                .any(|p| p.file_name().is_some_and(|n| crate::files::IGNORE_FILES.iter().any(|f| n == *f)));
+            if full_rescan || rules_changed || !under.is_empty() {
+                plans.push((root.clone(), rules_changed, under));
+            }
+        }
+    }
+
+    let updates: Vec<(PathBuf, Update)> = plans
+        .into_iter()
+        .map(|(root, rules_changed, under)| {
+            let update = if full_rescan || rules_changed {
+                Update::Rescan { filter: rules_changed.then(|| WalkFilter::new(&root)), files: scan(&root) }
+            } else {
+                Update::Paths(under.into_iter().map(|p| (observe(&p), p)).collect())
+            };
+            (root, update)
+        })
+        .collect();
+
+    let mut changes = Vec::new();
+    {
+        let mut inner = watcher.0.lock().unwrap();
+        for (root, update) in updates {
+            // Unwatched meanwhile.
+            let Some(watched) = inner.roots.get_mut(&root) else { continue };
+            let mut change = FilesChanged { root: root.to_string_lossy().to_string(), ..Default::default() };
+            match update {
+                Update::Rescan { filter, files } => {
+                    if let Some(filter) = filter {
+                        watched.filter = filter;
+                    }
+                    rescan(watched, files, &mut change);
+                }
+                Update::Paths(seen) => {
+                    for (seen, path) in seen {
+                        update_path(&root, watched, &path, seen, &mut change);
+                    }
+                }
+            }
+            if !change.is_empty() {
+                changes.push(change);
+            }
+        }
+    }
+    for mut change in changes {
+        change.added.sort();
+        change.modified.sort();
+        change.deleted.sort();
+        crate::pipes::files_changed(app, &change);
+        let _ = app.emit("files-changed", change);
+    }
+}
+
+/// Collect events from `first` on until `DEBOUNCE` passes without one (or `MAX_DELAY` overall).
+/// Returns the changed paths and whether events were lost, or None once the watcher is gone.
+fn next_batch(
+    first: notify::Result<Event>,
+    rx: &mpsc::Receiver<notify::Result<Event>>,
+) -> Option<(HashSet<PathBuf>, bool)> {
+    let started = Instant::now();
+    let mut paths = HashSet::new();
+    let mut full_rescan = false;
+    let mut next = Some(first);
+    while let Some(result) = next.take() {
+        match result {
+            // Reads, including our own indexing, change nothing.
+            Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+            Ok(event) => {
+                full_rescan |= event.need_rescan();
+                paths.extend(event.paths);
+            }
+            Err(e) => {
+                eprintln!("file watcher: {}", e);
+                full_rescan = true;
+            }
+        }
+        let Some(left) = MAX_DELAY.checked_sub(started.elapsed()) else { break };
+        match rx.recv_timeout(DEBOUNCE.min(left)) {
+            Ok(result) => next = Some(result),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    Some((paths, full_rescan))
+}
+
+fn debounce_loop(app: tauri::AppHandle, rx: mpsc::Receiver<notify::Result<Event>>) {
+    while let Ok(first) = rx.recv() {
+        let Some((paths, full_rescan)) = next_batch(first, &rx) else { return };
+        if full_rescan || !paths.is_empty() {
+            apply(&app, &paths, full_rescan);
+        }
+    }
+}
+
+pub(crate) fn start(app: &tauri::AppHandle) {
+    let (tx, rx) = mpsc::channel();
+    let watcher = RecommendedWatcher::new(
+        move |result| {
+            let _ = tx.send(result);
+        },
+        notify::Config::default(),
+    );
+    match watcher {
+        Ok(watcher) => app.state::<FileWatcher>().0.lock().unwrap().watcher = Some(watcher),
+        Err(e) => {
+            eprintln!("file watcher: {}", e);
+            return;
+        }
+    }
+    let app = app.clone();
+    std::thread::spawn(move || debounce_loop(app, rx));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, Flag, ModifyKind};
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-grid-watcher-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn watched(root: &Path) -> WatchedRoot {
+        WatchedRoot {
+            files: scan(root),
+            filter: WalkFilter::new(root),
+            cells: HashSet::new(),
+            listed: true,
+            last_used: Instant::now(),
+        }
+    }
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    fn sorted(mut names: Vec<String>) -> Vec<String> {
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn events_in_quick_succession_form_one_batch() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(event(EventKind::Modify(ModifyKind::Any), "/w/b.txt")).unwrap();
+        tx.send(event(EventKind::Access(AccessKind::Any), "/w/read.txt")).unwrap();
+        tx.send(event(EventKind::Modify(ModifyKind::Any), "/w/a.txt")).unwrap();
+        let started = Instant::now();
+        let (paths, full_rescan) = next_batch(event(EventKind::Create(CreateKind::File), "/w/a.txt"), &rx).unwrap();
+        assert!(started.elapsed() >= DEBOUNCE);
+        assert_eq!(paths, HashSet::from([PathBuf::from("/w/a.txt"), PathBuf::from("/w/b.txt")]));
+        assert!(!full_rescan);
+
+        tx.send(Ok(Event::new(EventKind::Other).set_flag(Flag::Rescan))).unwrap();
+        let (_, full_rescan) = next_batch(Err(notify::Error::generic("queue overflow")), &rx).unwrap();
+        assert!(full_rescan);
+        drop(tx);
+        assert!(next_batch(event(EventKind::Any, "/w/a.txt"), &rx).is_none());
+    }
+
+    #[test]
+    fn a_steady_stream_is_cut_at_the_max_delay() {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for i in 0..40 {
+                std::thread::sleep(Duration::from_millis(100));
+                if tx.send(event(EventKind::Any, &format!("/w/{}", i))).is_err() {
+                    return;
+                }
+            }
+        });
+        let started = Instant::now();
+        let (paths, _) = next_batch(event(EventKind::Any, "/w/first"), &rx).unwrap();
+        let took = started.elapsed();
+        assert!(took >= MAX_DELAY && took < MAX_DELAY + DEBOUNCE * 2, "{:?}", took);
+        assert!(paths.len() > 10 && paths.len() < 40);
+    }
+
+    #[test]
+    fn changed_paths_update_the_cache() {
+        let root = temp_root("update");
+        std::fs::write(root.join("keep.txt"), "a").unwrap();
+        std::fs::write(root.join("edit.txt"), "a").unwrap();
+        std::fs::create_dir_all(root.join("old")).unwrap();
+        std::fs::write(root.join("old/x.txt"), "a").unwrap();
+        let mut w = watched(&root);
+
+        std::fs::write(root.join("edit.txt"), "longer").unwrap();
+        std::fs::create_dir_all(root.join("new/deep")).unwrap();
+        std::fs::write(root.join("new/deep/y.txt"), "a").unwrap();
+        std::fs::remove_dir_all(root.join("old")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::fs::write(root.join("node_modules/z.js"), "a").unwrap();
+
+        let mut change = FilesChanged::default();
+        for rel in ["edit.txt", "new", "old", "node_modules", "keep.txt"] {
+            let path = root.join(rel);
+            update_path(&root, &mut w, &path, observe(&path), &mut change);
+        }
+        assert_eq!(change.added, vec!["new/deep/y.txt"]);
+        assert_eq!(change.modified, vec!["edit.txt"]);
+        assert_eq!(change.deleted, vec!["old/x.txt"]);
+        assert_eq!(sorted(w.files.keys().cloned().collect()), vec!["edit.txt", "keep.txt", "new/deep/y.txt"]);
+
+        std::fs::remove_file(root.join("keep.txt")).unwrap();
+        let mut change = FilesChanged::default();
+        rescan(&mut w, scan(&root), &mut change);
+        assert!(change.added.is_empty() && change.modified.is_empty());
+        assert_eq!(change.deleted, vec!["keep.txt"]);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn listed_roots_expire_after_the_idle_period() {
+        let root = temp_root("idle");
+        let long_ago = Instant::now().checked_sub(LISTED_IDLE + Duration::from_secs(1)).unwrap();
+        let mut inner = Inner::default();
+
+        let mut stale = watched(&root);
+        stale.last_used = long_ago;
+        inner.roots.insert(PathBuf::from("/stale"), stale);
+        let mut working = watched(&root);
+        working.last_used = long_ago;
+        working.cells.insert("cell-0".into());
+        inner.roots.insert(PathBuf::from("/working"), working);
+        let mut recent = watched(&root);
+        recent.last_used = Instant::now() - Duration::from_secs(599);
+        inner.roots.insert(PathBuf::from("/recent"), recent);
+
+        assert!(inner.roots[Path::new("/stale")].is_idle());
+        assert!(!inner.roots[Path::new("/working")].is_idle());
+        assert!(!inner.roots[Path::new("/recent")].is_idle());
+        inner.make_room();
+        let mut left: Vec<&PathBuf> = inner.roots.keys().collect();
+        left.sort();
+        assert_eq!(left, vec![Path::new("/recent"), Path::new("/working")]);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
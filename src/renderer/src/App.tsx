@@ -1,11 +1,13 @@
 import { useState, useEffect, useCallback, type JSX } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import type { CellState, GridPreset } from '../../shared/types'
 import { getCellIds, cellWorkDir } from '../../shared/types'
 import { useLocalStorage } from './hooks/useLocalStorage'
 import TopBar, { type CliTool, TOOL_COMMANDS } from './components/TopBar'
 import Grid, { type ViewMode } from './components/Grid'
 import SessionRestoreDialog from './components/SessionRestoreDialog'
+import { appendTask } from './components/TaskQueue'
 
 export default function App(): JSX.Element {
   const [cellStates, setCellStates] = useState<Record<string, CellState>>({})
@@ -34,6 +36,14 @@ export default function App(): JSX.Element {
     })
   }, [])
 
+  // Tasks queued by the backend (triggers, pipes, analysis actions)
+  useEffect(() => {
+    const unlisten = listen<{ cellId: string; task: string }>('task-enqueued', (event) => {
+      appendTask(event.payload.cellId, event.payload.task)
+    })
+    return () => { unlisten.then((fn) => fn()) }
+  }, [])
+
   const handleActivity = useCallback((id: string) => {
     setCellActivity((prev) => ({ ...prev, [id]: Date.now() }))
   }, [])
@@ -118,7 +128,8 @@ export default function App(): JSX.Element {
 
   const handleThemeChange = useCallback((id: string, theme: string) => {
     invoke('set_theme', { cellId: id, theme })
-    setCellStates((prev) => ({ ...prev, [id]: { ...prev[id], theme } }))
+      .then(() => setCellStates((prev) => ({ ...prev, [id]: { ...prev[id], theme } })))
+      .catch((e) => console.warn('theme:', e))
   }, [])
 
   // Save session whenever active cells change (debounced 2s)
@@ -1,7 +1,7 @@
 import { type JSX } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import { fileExt, extColor, timeAgo, formatSize } from '../utils/files'
-import type { FileEntry, GenreInfo } from '../utils/output-types'
+import type { FileEntry, FilePreviewInfo, GenreInfo } from '../utils/output-types'
 
 const GIT_STATUS_COLOR: Record<string, string> = { A: '#00cc66', M: '#ffaa44', D: '#ff4466', '?': '#555' }
 
@@ -70,32 +70,53 @@ export function FileListPanel({ files, loading, selectedFile, selectedGenre, fil
 
 interface FilePreviewProps {
   selectedFile: string | null
-  fileContent: string | null
+  preview: FilePreviewInfo | null
   fileError: string | null
   loading: boolean
+  onLoadMore: () => void
 }
 
-export function FilePreview({ selectedFile, fileContent, fileError, loading }: FilePreviewProps): JSX.Element {
+const LINE_ENDING_LABEL: Record<string, string> = { lf: 'LF', crlf: 'CRLF', cr: 'CR', mixed: 'mixed EOL' }
+
+function previewMeta(preview: FilePreviewInfo): string {
+  const parts = [formatSize(preview.sizeBytes)]
+  if (preview.text) {
+    parts.push(preview.text.encoding + (preview.text.hasBom ? ' BOM' : ''))
+    if (LINE_ENDING_LABEL[preview.text.lineEnding]) parts.push(LINE_ENDING_LABEL[preview.text.lineEnding])
+    if (!preview.text.eof) parts.push(`${formatSize(preview.text.endByte)} shown`)
+  } else {
+    parts.push(preview.format ?? 'binary')
+  }
+  return parts.join(' · ')
+}
+
+const smallButton = {
+  background: 'none', border: '1px solid #222', color: '#555', cursor: 'pointer', fontSize: 9, padding: '2px 7px', borderRadius: 3,
+} as const
+
+export function FilePreview({ selectedFile, preview, fileError, loading, onLoadMore }: FilePreviewProps): JSX.Element {
   const fileName = selectedFile ? selectedFile.split('/').pop() ?? selectedFile : null
   const ext = fileName ? fileExt(fileName) : ''
+  const text = preview?.text ?? null
   return (
     <div style={{ flex: 1, display: 'flex', flexDirection: 'column', overflow: 'hidden', minWidth: 0 }}>
       {fileName && (
         <div style={{ padding: '0 12px', height: 28, borderBottom: '1px solid #1a1a1a', display: 'flex', alignItems: 'center', gap: 8, flexShrink: 0 }}>
           <span style={{ color: extColor(ext), fontWeight: 700, fontSize: 9 }}>{ext || 'FILE'}</span>
           <span style={{ color: '#aaa', fontSize: 11 }}>{fileName}</span>
+          {preview && <span style={{ color: '#555', fontSize: 9 }}>{previewMeta(preview)}</span>}
           <span style={{ flex: 1 }} />
-          {fileContent && (
+          {text && (
             <button
-              onClick={() => navigator.clipboard.writeText(fileContent)}
-              style={{ background: 'none', border: '1px solid #222', color: '#555', cursor: 'pointer', fontSize: 9, padding: '2px 7px', borderRadius: 3 }}
+              onClick={() => navigator.clipboard.writeText(text.content)}
+              style={smallButton}
               onMouseEnter={(e) => { e.currentTarget.style.borderColor = '#444'; e.currentTarget.style.color = '#aaa' }}
               onMouseLeave={(e) => { e.currentTarget.style.borderColor = '#222'; e.currentTarget.style.color = '#555' }}
             >COPY</button>
           )}
           <button
             onClick={() => invoke('open_file', { path: selectedFile })}
-            style={{ background: 'none', border: '1px solid #222', color: '#555', cursor: 'pointer', fontSize: 9, padding: '2px 7px', borderRadius: 3 }}
+            style={smallButton}
             onMouseEnter={(e) => { e.currentTarget.style.borderColor = '#444'; e.currentTarget.style.color = '#aaa' }}
             onMouseLeave={(e) => { e.currentTarget.style.borderColor = '#222'; e.currentTarget.style.color = '#555' }}
           >OPEN</button>
@@ -106,13 +127,34 @@ export function FilePreview({ selectedFile, fileContent, fileError, loading }: F
         fontFamily: 'JetBrains Mono, Menlo, Monaco, monospace',
         fontSize: 12, lineHeight: 1.75,
         color: fileError ? '#ff4444' : '#ccc',
-        whiteSpace: 'pre-wrap', wordBreak: 'break-word',
+        whiteSpace: preview?.hex ? 'pre' : 'pre-wrap', wordBreak: 'break-word',
       }}>
         {!selectedFile ? (
           <span style={{ color: '#444' }}>Select a file to preview</span>
         ) : loading ? (
           <span style={{ color: '#444' }}>Loading...</span>
-        ) : fileError ? fileError : (fileContent ?? '')}
+        ) : fileError ? fileError : !preview ? null : text ? (
+          <>
+            {text.content}
+            {!text.eof && (
+              <div style={{ marginTop: 8 }}>
+                <button onClick={onLoadMore} style={smallButton}>
+                  LOAD MORE ({formatSize(preview.sizeBytes - text.endByte)} left)
+                </button>
+              </div>
+            )}
+          </>
+        ) : (
+          <>
+            {preview.dataUrl && (
+              <img src={preview.dataUrl} alt={fileName ?? ''} style={{ maxWidth: '100%', display: 'block', marginBottom: 12 }} />
+            )}
+            {preview.kind === 'pdf' && (
+              <div style={{ color: '#666', marginBottom: 8 }}>PDF document — use OPEN to view it.</div>
+            )}
+            {!preview.dataUrl && preview.hex && <span style={{ color: '#888' }}>{preview.hex.dump}</span>}
+          </>
+        )}
       </div>
     </div>
   )
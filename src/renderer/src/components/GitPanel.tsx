@@ -53,21 +53,8 @@ export default function GitPanel({ selectedGenre, gitInfo, loading, onRefresh }:
   const [selectedHash, setSelectedHash] = useState<string | null>(null)
   const [diffContent, setDiffContent] = useState<string | null>(null)
   const [diffLoading, setDiffLoading] = useState(false)
-  const [remoteUrl, setRemoteUrl] = useState<string | null>(null)
-
-  // Load remote URL by reading .git/config
-  useEffect(() => {
-    if (!selectedGenre) { setRemoteUrl(null); return }
-    const gitConfigPath = `${selectedGenre.dir}/.git/config`
-    invoke<string>('read_file_content', { path: gitConfigPath })
-      .then((content) => {
-        // Parse [remote "origin"] url = ...
-        const match = content.match(/\[remote\s+"origin"\][^\[]*url\s*=\s*(.+)/s)
-        const urlLine = match ? match[1].split('\n')[0].trim() : null
-        setRemoteUrl(urlLine)
-      })
-      .catch(() => setRemoteUrl(null))
-  }, [selectedGenre?.name])
+  const remotes = gitInfo?.remotes ?? []
+  const remoteUrl = (remotes.find((r) => r.name === 'origin') ?? remotes[0])?.url ?? null
 
   // Reset diff when genre changes
   useEffect(() => {
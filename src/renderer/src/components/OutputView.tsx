@@ -1,8 +1,9 @@
 import { useState, useEffect, useCallback, useMemo, useRef, type JSX } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import type { CellState } from '../../../shared/types'
 import { getCellIds, getCellRole, roleColor, cellWorkDir } from '../../../shared/types'
-import type { FileEntry, GenreInfo, GitInfo, ActivityEntry } from '../utils/output-types'
+import type { FileEntry, FilePreviewInfo, GenreInfo, GitInfo, ActivityEntry } from '../utils/output-types'
 import AgentStatusBar from './AgentStatusBar'
 import GenreSelector from './GenreSelector'
 import { FileListPanel, FilePreview } from './FilesTab'
@@ -36,7 +37,7 @@ export default function OutputView({ cellStates, gridRows, gridCols, outputDir }
   const [summarizing, setSummarizing] = useState(false)
   const [selectedGenre, setSelectedGenre] = useState<string | null>(null)
   const [selectedFile, setSelectedFile] = useState<string | null>(null)
-  const [fileContent, setFileContent] = useState<string | null>(null)
+  const [filePreview, setFilePreview] = useState<FilePreviewInfo | null>(null)
   const [loadingContent, setLoadingContent] = useState(false)
   const [fileError, setFileError] = useState<string | null>(null)
   const [rightMode, setRightMode] = useState<RightMode>('dashboard')
@@ -108,18 +109,22 @@ export default function OutputView({ cellStates, gridRows, gridCols, outputDir }
     loadActivity(genres)
   }, [genres, loadFiles, loadActivity])
 
-  // Auto-refresh every 30s
+  // File lists refresh on `files-changed` (cheap: the backend keeps them cached); git activity every 30s
   useEffect(() => {
     if (genres.length === 0) return
-    const iv = setInterval(() => {
+    const refreshFiles = () => {
       genres.forEach((g) => {
         invoke<FileEntry[]>('list_dir_files_recursive', { path: g.dir })
           .then((list) => setAllFiles((prev) => ({ ...prev, [g.name]: list.sort((a, b) => b.modifiedMs - a.modifiedMs) })))
           .catch(() => {})
       })
-      loadActivity(genres)
-    }, 30_000)
-    return () => clearInterval(iv)
+    }
+    const unlistenPromise = listen('files-changed', refreshFiles)
+    const iv = setInterval(() => loadActivity(genres), 30_000)
+    return () => {
+      clearInterval(iv)
+      unlistenPromise.then((fn) => fn())
+    }
   }, [genres, loadActivity])
 
   // Auto-select first genre
@@ -160,17 +165,39 @@ export default function OutputView({ cellStates, gridRows, gridCols, outputDir }
     summarizeAll(genres)
   }, [allFiles, genres, loadingGenres, summarizeAll])
 
-  // Load file content
+  // Load file preview (first window of text, or a hexdump)
   useEffect(() => {
-    if (!selectedFile) { setFileContent(null); setFileError(null); return }
+    if (!selectedFile) { setFilePreview(null); setFileError(null); return }
     setLoadingContent(true)
-    setFileContent(null)
+    setFilePreview(null)
     setFileError(null)
-    invoke<string>('read_file_content', { path: selectedFile })
-      .then((c) => { setFileContent(c); setLoadingContent(false) })
+    invoke<FilePreviewInfo>('preview_file', { path: selectedFile })
+      .then((p) => { setFilePreview(p); setLoadingContent(false) })
       .catch((e) => { setFileError(String(e)); setLoadingContent(false) })
   }, [selectedFile])
 
+  // Append the next window of a large text file
+  const loadMore = useCallback(() => {
+    const text = filePreview?.text
+    if (!selectedFile || !text || text.eof) return
+    invoke<FilePreviewInfo>('preview_file', { path: selectedFile, range: { offset: text.endByte } })
+      .then((next) => {
+        if (!next.text) return
+        setFilePreview((prev) => prev?.text && prev.path === next.path ? {
+          ...prev,
+          text: {
+            ...prev.text,
+            content: prev.text.content + next.text!.content,
+            endByte: next.text!.endByte,
+            lines: prev.text.lines + next.text!.lines,
+            hadErrors: prev.text.hadErrors || next.text!.hadErrors,
+            eof: next.text!.eof,
+          },
+        } : prev)
+      })
+      .catch((e) => setFileError(String(e)))
+  }, [selectedFile, filePreview])
+
   if (!outputDir.trim()) {
     return (
       <div style={{ flex: 1, display: 'flex', alignItems: 'center', justifyContent: 'center', color: '#333', fontSize: 13 }}>
@@ -234,9 +261,10 @@ export default function OutputView({ cellStates, gridRows, gridCols, outputDir }
                 />
                 <FilePreview
                   selectedFile={selectedFile}
-                  fileContent={fileContent}
+                  preview={filePreview}
                   fileError={fileError}
                   loading={loadingContent}
+                  onLoadMore={loadMore}
                 />
               </>
             ) : (
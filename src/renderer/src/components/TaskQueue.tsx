@@ -13,13 +13,21 @@ function saveTasks(cellId: string, tasks: string[]) {
   localStorage.setItem(getStorageKey(cellId), JSON.stringify(tasks))
 }
 
+const TASKS_CHANGED_EVENT = 'chaos-grid-tasks-changed'
+
+// Queue a task from outside the panel (e.g. backend `task-enqueued` events)
+export function appendTask(cellId: string, task: string) {
+  saveTasks(cellId, [...loadTasks(cellId), task])
+  window.dispatchEvent(new CustomEvent<string>(TASKS_CHANGED_EVENT, { detail: cellId }))
+}
+
 interface TaskQueueProps {
   cellIds: string[]
   cellStates: Record<string, CellState>
 }
 
 interface PtyExitedPayload {
-  cell_id: string
+  cellId: string
 }
 
 export default function TaskQueue({ cellIds, cellStates }: TaskQueueProps): JSX.Element {
@@ -41,7 +49,7 @@ export default function TaskQueue({ cellIds, cellStates }: TaskQueueProps): JSX.
   // Listen for pty-exited events and auto-send next task
   useEffect(() => {
     const unlistenPromise = listen<PtyExitedPayload>('pty-exited', (event) => {
-      const cellId = event.payload?.cell_id
+      const cellId = event.payload?.cellId
       if (!cellId) return
       const cellTasks = loadTasks(cellId)
       if (cellTasks.length === 0) return
@@ -56,6 +64,16 @@ export default function TaskQueue({ cellIds, cellStates }: TaskQueueProps): JSX.
     return () => { unlistenPromise.then((fn) => fn()) }
   }, [])
 
+  // Pick up tasks appended while the panel is open
+  useEffect(() => {
+    const onChanged = (e: Event) => {
+      const cellId = (e as CustomEvent<string>).detail
+      setTasks((prev) => ({ ...prev, [cellId]: loadTasks(cellId) }))
+    }
+    window.addEventListener(TASKS_CHANGED_EVENT, onChanged)
+    return () => window.removeEventListener(TASKS_CHANGED_EVENT, onChanged)
+  }, [])
+
   const addTask = useCallback(() => {
     const trimmed = inputValue.trim()
     if (!trimmed || !selectedCellId) return
@@ -1,4 +1,5 @@
 import { useState, useRef, useEffect, type JSX } from 'react'
+import { invoke } from '@tauri-apps/api/core'
 import type { ViewMode } from './Grid'
 import type { GridPreset } from '../../../shared/types'
 import AiSettings from './AiSettings'
@@ -209,22 +210,34 @@ export default function TopBar({
             </div>
 
             {/* Output directory */}
-            <label style={{ display: 'flex', flexDirection: 'column', gap: 4 }}>
+            {/* Choosing through the native picker also adds the folder to the workspace roots */}
+            <div style={{ display: 'flex', flexDirection: 'column', gap: 4 }}>
               <span style={{ fontSize: 9, color: '#666' }}>OUTPUT DIRECTORY</span>
-              <input
-                type="text"
-                value={outputDir}
-                onChange={(e) => onOutputDirChange(e.target.value)}
-                placeholder="~/chaos-grid-output"
-                style={{
-                  background: '#1a1a1a', border: '1px solid #2a2a2a', color: '#ccc',
-                  fontFamily: 'monospace', fontSize: 11, padding: '5px 8px',
-                  outline: 'none', borderRadius: 3, width: '100%', boxSizing: 'border-box',
-                }}
-                onFocus={(e) => (e.currentTarget.style.borderColor = '#444')}
-                onBlur={(e) => (e.currentTarget.style.borderColor = '#2a2a2a')}
-              />
-            </label>
+              <div style={{ display: 'flex', gap: 4 }}>
+                <input
+                  type="text"
+                  value={outputDir}
+                  onChange={(e) => onOutputDirChange(e.target.value)}
+                  placeholder="~/chaos-grid-output"
+                  style={{
+                    background: '#1a1a1a', border: '1px solid #2a2a2a', color: '#ccc',
+                    fontFamily: 'monospace', fontSize: 11, padding: '5px 8px',
+                    outline: 'none', borderRadius: 3, flex: 1, minWidth: 0, boxSizing: 'border-box',
+                  }}
+                  onFocus={(e) => (e.currentTarget.style.borderColor = '#444')}
+                  onBlur={(e) => (e.currentTarget.style.borderColor = '#2a2a2a')}
+                />
+                <button
+                  className="btn"
+                  onClick={() => {
+                    invoke<string | null>('pick_workspace_root')
+                      .then((dir) => { if (dir) onOutputDirChange(dir) })
+                      .catch((e) => console.warn('workspace root:', e))
+                  }}
+                  style={{ fontSize: 10 }}
+                >選択…</button>
+              </div>
+            </div>
 
             {/* CLI Tool */}
             <label style={{ display: 'flex', flexDirection: 'column', gap: 4 }}>
@@ -6,6 +6,31 @@ export interface FileEntry {
   isDir: boolean
 }
 
+export interface TextPreview {
+  content: string
+  encoding: string
+  hasBom: boolean
+  lineEnding: 'lf' | 'crlf' | 'cr' | 'mixed' | 'none'
+  startByte: number
+  endByte: number
+  startLine: number | null
+  lines: number
+  hadErrors: boolean
+  eof: boolean
+}
+
+export interface FilePreviewInfo {
+  path: string
+  sizeBytes: number
+  modifiedMs: number
+  kind: 'text' | 'image' | 'pdf' | 'binary'
+  format: string | null
+  mime: string | null
+  text: TextPreview | null
+  hex: { offset: number; length: number; dump: string } | null
+  dataUrl: string | null
+}
+
 export interface GenreInfo {
   name: string
   dir: string
@@ -20,6 +45,15 @@ export interface GitCommit {
   message: string
 }
 
+export type GitChangeKind = 'added' | 'modified' | 'deleted' | 'renamed' | 'typeChanged' | 'untracked' | 'conflicted'
+
+export interface GitStatusEntry {
+  path: string
+  oldPath: string | null
+  staged: GitChangeKind | null
+  unstaged: GitChangeKind | null
+}
+
 export interface GitInfo {
   isGitRepo: boolean
   branch: string
@@ -27,6 +61,22 @@ export interface GitInfo {
   staged: string[]
   unstaged: string[]
   fileStatuses: Record<string, string>
+  entries: GitStatusEntry[]
+  remotes: { name: string; url: string | null }[]
+}
+
+export interface DiffFileStat {
+  path: string
+  oldPath: string | null
+  insertions: number
+  deletions: number
+  binary: boolean
+}
+
+export interface UncommittedDiff {
+  files: DiffFileStat[]
+  totalInsertions: number
+  totalDeletions: number
 }
 
 export interface ActivityEntry {
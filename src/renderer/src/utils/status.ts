@@ -1,2 +1,2 @@
-export const STATUS_DOT: Record<string, string> = { active: '●', thinking: '◎', idle: '○' }
-export const STATUS_COLOR: Record<string, string> = { active: '#00ff88', thinking: '#ffcc00', idle: '#333' }
+export const STATUS_DOT: Record<string, string> = { active: '●', thinking: '◎', idle: '○', error: '✕' }
+export const STATUS_COLOR: Record<string, string> = { active: '#00ff88', thinking: '#ffcc00', idle: '#333', error: '#ff4466' }
@@ -12,7 +12,14 @@ export interface CellState {
   theme: string
   pid: number | null
   lastOutput: string
-  status: 'idle' | 'active' | 'thinking'
+  status: 'idle' | 'active' | 'thinking' | 'error'
+  updatedAt: number
+  outputBytes?: number
+  summary?: CellSummary | null
+}
+
+export interface CellSummary {
+  text: string
   updatedAt: number
 }
 